@@ -1,30 +1,70 @@
 use crate::bench::{Bench, BenchProvider};
-use crate::ledger::{ImmuLedger, LedgerProvider};
+use crate::ledger::{ILedger, LedgerProvider};
 use crate::malice::{IMalice, MaliceProvider};
+use crate::repair::{IRepairer, RepairerProvider};
 use crate::util::Res;
+use crate::verifier::secrets::{AuditorKeys, AuditorKeysProvider};
 use crate::verifier::{IVerifier, VerifierProvider};
 use crate::{
     grpc::{GrpcProvider, IGrpcHandler},
     p2p,
     settings::{ISettings, SettingsProvider},
-    storage::{IStorage, StorageProvider},
+    storage::{
+        sweeper::{ISweeper, SweeperProvider},
+        IStorage, StorageProvider,
+    },
 };
-use runtime_injector::{Injector, IntoSingleton, TypedProvider};
+#[cfg(test)]
+use crate::{ledger::MemoryLedger, settings::Settings, storage::MemoryStorage};
+#[cfg(test)]
+use runtime_injector::constant;
+use runtime_injector::{Injector, InjectorBuilder, IntoSingleton, TypedProvider};
 use tokio::sync::Mutex;
 
 pub fn dependency_injector() -> Res<Injector> {
     let mut injector = Injector::builder();
-    injector.add_module(p2p::module());
     injector.provide(StorageProvider.singleton().with_interface::<dyn IStorage>());
     injector.provide(
-        MaliceProvider
+        SettingsProvider
             .singleton()
-            .with_interface::<Box<dyn IMalice>>(),
+            .with_interface::<dyn ISettings>(),
     );
     injector.provide(
-        SettingsProvider
+        LedgerProvider
             .singleton()
-            .with_interface::<dyn ISettings>(),
+            .with_interface::<Mutex<Box<dyn ILedger>>>(),
+    );
+    provide_services(&mut injector);
+
+    Ok(injector.build())
+}
+
+/// A node which takes its settings as they are and keeps its records and
+/// ledger in memory. Nodes given clones of the same ledger share it, so
+/// several of them can run in one process.
+#[cfg(test)]
+pub fn memory_dependency_injector(
+    settings: Settings,
+    ledger: MemoryLedger,
+    storage: MemoryStorage,
+) -> Res<Injector> {
+    let mut injector = Injector::builder();
+    injector.provide(constant(storage).with_interface::<dyn IStorage>());
+    injector.provide(constant(settings).with_interface::<dyn ISettings>());
+    injector.provide(constant(Mutex::new(Box::new(ledger) as Box<dyn ILedger>)));
+    provide_services(&mut injector);
+
+    Ok(injector.build())
+}
+
+/// Everything but the settings, the storage and the ledger.
+fn provide_services(injector: &mut InjectorBuilder) {
+    injector.add_module(p2p::module());
+    injector.provide(SweeperProvider.singleton().with_interface::<dyn ISweeper>());
+    injector.provide(
+        MaliceProvider
+            .singleton()
+            .with_interface::<Box<dyn IMalice>>(),
     );
     injector.provide(
         GrpcProvider
@@ -32,9 +72,14 @@ pub fn dependency_injector() -> Res<Injector> {
             .with_interface::<dyn IGrpcHandler>(),
     );
     injector.provide(
-        LedgerProvider
+        AuditorKeysProvider
             .singleton()
-            .with_interface::<Mutex<ImmuLedger>>(),
+            .with_interface::<AuditorKeys>(),
+    );
+    injector.provide(
+        RepairerProvider
+            .singleton()
+            .with_interface::<dyn IRepairer>(),
     );
     injector.provide(
         VerifierProvider
@@ -42,6 +87,4 @@ pub fn dependency_injector() -> Res<Injector> {
             .with_interface::<dyn IVerifier>(),
     );
     injector.provide(BenchProvider.singleton().with_interface::<Mutex<Bench>>());
-
-    Ok(injector.build())
 }
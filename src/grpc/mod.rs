@@ -1,7 +1,9 @@
-use crate::ledger::{ILedger, ImmuLedger};
+use crate::ledger::ILedger;
 use crate::p2p::controller::ISwarmController;
-use crate::settings::{ISettings, Por};
+use crate::reputation::ReputationModel;
+use crate::settings::{ISettings, Por, Redundancy, Selection};
 use crate::util::consts::{self, GRPC_TIMEOUT, LOCALHOST};
+use crate::util::erasure;
 use crate::util::grpc::kiss_grpc::kiss_service_server::KissService;
 use crate::util::grpc::kiss_grpc::kiss_service_server::KissServiceServer;
 use crate::util::grpc::kiss_grpc::{
@@ -9,22 +11,29 @@ use crate::util::grpc::kiss_grpc::{
     StoreResponse, VerifyRequest, VerifyResponse, *,
 };
 use crate::util::hasher::{self, hash};
+use crate::util::selection;
+use crate::util::types::{record_expiry, shard_record_key, Bytes, Shard};
 use crate::util::{ErrorKind, Res};
 use crate::verifier::por::{VerificationClient, VerificationClientConfig};
+use crate::verifier::secrets::AuditorKeys;
 use async_trait::async_trait;
+use futures::stream::{self, Stream, StreamExt};
 use libp2p_identity::PeerId;
-use log::{debug, info};
+use log::{debug, info, warn};
 use runtime_injector::{
     interface, InjectResult, Injector, RequestInfo, Service, ServiceFactory, Svc,
 };
+use std::collections::HashMap;
 use std::net::SocketAddr;
+use std::pin::Pin;
 use std::str::FromStr;
-use std::time::{Duration, SystemTime};
+use std::sync::OnceLock;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 use tokio::net::TcpListener;
 use tokio::sync::Mutex;
 use tokio_stream::wrappers::TcpListenerStream;
 use tonic::transport::Server;
-use tonic::{Request, Response, Status};
+use tonic::{Request, Response, Status, Streaming};
 use uuid::Uuid;
 
 interface! {
@@ -44,16 +53,25 @@ impl ServiceFactory<()> for GrpcProvider {
     ) -> InjectResult<Self::Result> {
         let port = injector.get::<Svc<dyn ISettings>>()?.grpc().port;
         let por = injector.get::<Svc<dyn ISettings>>()?.por();
+        let redundancy = injector.get::<Svc<dyn ISettings>>()?.redundancy();
+        let selection = injector.get::<Svc<dyn ISettings>>()?.selection();
+        let reputation = ReputationModel::new(injector.get::<Svc<dyn ISettings>>()?.reputation());
         let swarm_controller = injector.get::<Svc<dyn ISwarmController>>()?;
-        let ledger = injector.get::<Svc<Mutex<ImmuLedger>>>()?;
+        let ledger = injector.get::<Svc<Mutex<Box<dyn ILedger>>>>()?;
+        let auditor_keys = injector.get::<Svc<AuditorKeys>>()?;
 
         Ok(GrpcHandler {
             inner: Inner {
                 swarm_controller,
                 ledger,
                 por,
+                redundancy,
+                selection,
+                reputation,
+                auditor_keys,
             },
             port,
+            local_addr: OnceLock::new(),
         })
     }
 }
@@ -61,18 +79,25 @@ impl ServiceFactory<()> for GrpcProvider {
 #[async_trait]
 pub trait IGrpcHandler: Service {
     async fn start(&self) -> Res<()>;
+    /// The address the server listens on, once it does.
+    fn local_addr(&self) -> Option<SocketAddr>;
 }
 
 #[derive(Clone)]
 struct Inner {
     swarm_controller: Svc<dyn ISwarmController>,
-    ledger: Svc<Mutex<ImmuLedger>>,
+    ledger: Svc<Mutex<Box<dyn ILedger>>>,
     por: Por,
+    redundancy: Redundancy,
+    selection: Selection,
+    reputation: ReputationModel,
+    auditor_keys: Svc<AuditorKeys>,
 }
 
 pub struct GrpcHandler {
     inner: Inner,
     port: u16,
+    local_addr: OnceLock<SocketAddr>,
 }
 
 #[async_trait]
@@ -90,6 +115,7 @@ impl IGrpcHandler for GrpcHandler {
             .map_err(|e| ErrorKind::IoDetailed(e, self.port))?;
 
         info!("grpc listening on {}", real_addr);
+        let _ = self.local_addr.set(real_addr);
 
         let middleware = tower::ServiceBuilder::new()
             .timeout(Duration::from_secs(GRPC_TIMEOUT))
@@ -100,13 +126,17 @@ impl IGrpcHandler for GrpcHandler {
             .layer(middleware)
             .add_service(
                 KissServiceServer::new(self.inner.clone())
-                    .max_decoding_message_size(1024 * 1024 * 1024)
-                    .max_encoding_message_size(1024 * 1024 * 1024),
+                    .max_decoding_message_size(consts::MAX_FILE_SIZE)
+                    .max_encoding_message_size(consts::MAX_FILE_SIZE),
             )
             .serve_with_incoming(TcpListenerStream::new(listener))
             .await?;
         Ok(())
     }
+
+    fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr.get().copied()
+    }
 }
 
 #[async_trait]
@@ -125,18 +155,19 @@ impl KissService for Inner {
             .await
             .map_err(|e| Status::unknown(e.to_string()))?;
 
+        // every holder is audited against its own contract
         let mut result = vec![];
-        for contract in contracts.iter() {
-            let verification_client =
-                VerificationClient::new(VerificationClientConfig::from_contract(contract));
+        for contract in contracts.iter().filter(|x| !x.terminated) {
+            let client_config = VerificationClientConfig::from_contract(
+                contract,
+                self.auditor_keys.secret.as_ref(),
+            )
+            .map_err(|e| Status::failed_precondition(e.to_string()))?;
+            let verification_client = VerificationClient::new(client_config);
             let challenge = verification_client.make_challenge_vector();
             let response = self
                 .swarm_controller
-                .request_verification(
-                    contract.peer_id,
-                    request.file_uuid.clone(),
-                    challenge.clone(),
-                )
+                .request_verification(contract.peer_id, contract.record_key(), challenge.clone())
                 .await;
             match response {
                 Ok(response) => result.push(VerificationForPeer {
@@ -159,93 +190,35 @@ impl KissService for Inner {
         request: Request<StoreRequest>,
     ) -> std::result::Result<Response<StoreResponse>, Status> {
         let request = request.into_inner();
-        let start_time = SystemTime::now();
         debug!("store request for {}", request.name);
 
-        let file_uuid = Uuid::new_v4();
-
-        let closest = self.swarm_controller.get_closest_peers(file_uuid).await;
-        debug!("closest peers: {:?}", closest);
-        let closest_peers =
-            closest.map_err(|e| Status::internal(format!("no closest peers {}", e.to_string())))?;
-
-        let result = self
-            .swarm_controller
-            .put_to(
-                file_uuid.clone().to_string(),
-                request.content.clone(),
-                closest_peers
-                    .iter()
-                    .take(consts::REPLICATION_FACTOR)
-                    .cloned()
-                    .collect(),
-            )
-            .await;
-
-        debug!("put finished: {:?}", result);
-
-        if let Err(e) = result {
-            info!("failed to store file {}, {:?}", file_uuid, e);
-            return Err(Status::internal(e.to_string()));
-        }
-
-        if self.por.enabled {
-            let file_hash = hash(&request.content);
-            debug!("{}", file_hash);
+        let redundancy = self.redundancy_for(request.redundancy)?;
+        let file_uuid = self
+            .store_content(request.content, request.ttl, redundancy)
+            .await?;
 
-            for peer in closest_peers.iter().take(consts::REPLICATION_FACTOR) {
-                let client_config = VerificationClientConfig::from_file(&request.content);
-                let (secret_n, secret_m, rows, cols) = client_config.to_contract();
-                // retry writing the contract tot he ledger 10 times:
-                // writing sometimes fails
-                let mut success = false;
-                let mut last_error = String::new();
-                for _ in 0..10 {
-                    let res = self
-                        .ledger
-                        .lock()
-                        .await
-                        .create_contract(
-                            *peer,
-                            file_uuid,
-                            file_hash.clone(),
-                            request.ttl,
-                            secret_n.clone(),
-                            secret_m.clone(),
-                            rows,
-                            cols,
-                        )
-                        .await;
-                    if let Err(e) = res {
-                        last_error = e.to_string();
-                    } else {
-                        success = true;
-                        break;
-                    }
-                }
-                if !success {
-                    return Err(Status::internal(format!(
-                        "failed to write contract to immudb: {}",
-                        last_error
-                    )));
-                }
-            }
-        }
+        Ok(Response::new(StoreResponse {
+            name: file_uuid.to_string(),
+        }))
+    }
 
-        let since_start = start_time.elapsed().unwrap().as_millis();
-        info!(
-            "stored file {} at peers [{}] for {} ms",
-            file_uuid,
-            closest_peers
-                .iter()
-                .take(consts::REPLICATION_FACTOR)
-                .cloned()
-                .map(|x| x.to_string())
-                .collect::<Vec<_>>()
-                .join(", "),
-            since_start
+    async fn store_stream(
+        &self,
+        request: Request<Streaming<StoreChunk>>,
+    ) -> std::result::Result<Response<StoreResponse>, Status> {
+        let (content, metadata) =
+            collect_store_chunks(request.into_inner(), consts::MAX_FILE_SIZE).await?;
+        debug!(
+            "store stream request for {} ({} bytes)",
+            metadata.name,
+            content.len()
         );
 
+        let redundancy = self.redundancy_for(metadata.redundancy)?;
+        let file_uuid = self
+            .store_content(content, metadata.ttl, redundancy)
+            .await?;
+
         Ok(Response::new(StoreResponse {
             name: file_uuid.to_string(),
         }))
@@ -257,26 +230,177 @@ impl KissService for Inner {
     ) -> std::result::Result<Response<RetrieveResponse>, Status> {
         let request = request.into_inner();
         info!("received a get request for {}", request.name);
-        let mut ledger = self.ledger.lock().await;
-        let contract = ledger
-            .get_contract(request.name.clone())
+        let (content, _) = self.retrieve_content(request.name.clone()).await?;
+
+        Ok(Response::new(RetrieveResponse {
+            name: request.name,
+            content,
+        }))
+    }
+
+    type RetrieveStreamStream =
+        Pin<Box<dyn Stream<Item = Result<RetrieveChunk, Status>> + Send + 'static>>;
+
+    async fn retrieve_stream(
+        &self,
+        request: Request<RetrieveRequest>,
+    ) -> std::result::Result<Response<Self::RetrieveStreamStream>, Status> {
+        let request = request.into_inner();
+        info!("received a streaming get request for {}", request.name);
+        let (content, file_hash) = self.retrieve_content(request.name.clone()).await?;
+
+        let metadata = RetrieveMetadata {
+            name: request.name,
+            file_hash,
+            size: content.len() as u64,
+        };
+        Ok(Response::new(Box::pin(retrieve_chunks(
+            content,
+            metadata,
+            consts::STREAM_CHUNK_SIZE,
+        ))))
+    }
+
+    async fn delete(
+        &self,
+        request: Request<DeleteRequest>,
+    ) -> std::result::Result<Response<DeleteResponse>, Status> {
+        let request = request.into_inner();
+        info!("received a delete request for {}", request.file_uuid);
+        Uuid::from_str(request.file_uuid.as_str())
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+
+        let contracts = self
+            .ledger
+            .lock()
+            .await
+            .get_contracts(request.file_uuid.clone())
             .await
             .map_err(|e| Status::unknown(e.to_string()))?;
+        // holders only remove records whose contracts are terminated, so
+        // files stored without contracts can't be deleted
+        if contracts.is_empty() {
+            return Err(Status::failed_precondition(
+                "only files stored with contracts can be deleted",
+            ));
+        }
+        let mut holdings: Vec<(PeerId, String)> = contracts
+            .iter()
+            .map(|x| (x.peer_id, x.record_key()))
+            .collect();
+        holdings.sort();
+        holdings.dedup();
 
-        let res = self.swarm_controller.get(request.name.clone()).await;
-        info!("get finished: {:?}", res);
-        let content = res
-            .map_err(|e| Status::not_found(format!("failed getting from swarm: {}", e)))?
-            .file;
+        // terminate the contracts first, so the verifier doesn't punish
+        // the holders for removing the file and the holders agree to it
+        self.ledger
+            .lock()
+            .await
+            .terminate_contracts(request.file_uuid.clone())
+            .await
+            .map_err(|e| Status::unknown(e.to_string()))?;
 
-        let file_hash = hasher::hash(&content);
-        if file_hash != contract.file_hash {
-            return Err(Status::data_loss("file has been modified"));
+        let mut removals = vec![];
+        for (peer, record_key) in holdings {
+            let removed = self
+                .swarm_controller
+                .request_removal(peer, record_key.clone())
+                .await
+                .unwrap_or_else(|e| {
+                    warn!("failed to remove {} from {}: {}", record_key, peer, e);
+                    false
+                });
+            removals.push(RemovalForPeer {
+                peer_id: peer.to_string(),
+                removed,
+            });
         }
 
-        Ok(Response::new(RetrieveResponse {
-            name: request.name,
-            content,
+        Ok(Response::new(DeleteResponse {
+            file_uuid: request.file_uuid,
+            removals,
+        }))
+    }
+
+    async fn renew_lease(
+        &self,
+        request: Request<RenewLeaseRequest>,
+    ) -> std::result::Result<Response<RenewLeaseResponse>, Status> {
+        let request = request.into_inner();
+        info!(
+            "received a renew lease request for {} by {} s",
+            request.file_uuid, request.extend_by
+        );
+        if request.extend_by <= 0 {
+            return Err(Status::invalid_argument("extend_by must be positive"));
+        }
+
+        let contracts: Vec<_> = self
+            .ledger
+            .lock()
+            .await
+            .get_contracts(request.file_uuid.clone())
+            .await
+            .map_err(|e| Status::unknown(e.to_string()))?
+            .into_iter()
+            .filter(|x| !x.terminated)
+            .collect();
+        let contract = contracts
+            .first()
+            .ok_or_else(|| Status::not_found("no active contracts for file"))?;
+        if contract.expires_at().is_none() {
+            return Err(Status::failed_precondition("file does not expire"));
+        }
+        // replicas made by a repair have their own upload date, so every
+        // holder gets the expiry of its own contract
+        let too_long = || Status::invalid_argument("extend_by is too large");
+        let ttl = contract
+            .ttl
+            .checked_add(request.extend_by)
+            .ok_or_else(too_long)?;
+        let mut holdings: Vec<(PeerId, String, u64)> = vec![];
+        for contract in contracts.iter() {
+            let expires_at = contract
+                .ttl
+                .checked_add(request.extend_by)
+                .and_then(|ttl| contract.upload_date.checked_add(ttl))
+                .ok_or_else(too_long)?;
+            holdings.push((
+                contract.peer_id,
+                contract.record_key(),
+                expires_at.unsigned_abs(),
+            ));
+        }
+        holdings.sort();
+        holdings.dedup();
+
+        self.ledger
+            .lock()
+            .await
+            .renew_contracts(request.file_uuid.clone(), request.extend_by)
+            .await
+            .map_err(|e| Status::unknown(e.to_string()))?;
+
+        let mut renewals = vec![];
+        for (peer, record_key, expires_at) in holdings {
+            let renewed = self
+                .swarm_controller
+                .request_renewal(peer, record_key.clone(), expires_at)
+                .await
+                .unwrap_or_else(|e| {
+                    warn!("failed to renew {} at {}: {}", record_key, peer, e);
+                    false
+                });
+            renewals.push(RenewalForPeer {
+                peer_id: peer.to_string(),
+                renewed,
+            });
+        }
+
+        Ok(Response::new(RenewLeaseResponse {
+            file_uuid: request.file_uuid,
+            ttl,
+            renewals,
         }))
     }
 
@@ -365,37 +489,76 @@ impl KissService for Inner {
                 PeerId::from_str(peer_uuid).map_err(|e| Status::invalid_argument(e.to_string()))?,
             );
         }
+        let auditor_key = self
+            .auditor_keys
+            .public()
+            .map_err(|e| Status::failed_precondition(e.to_string()))?;
         let mut ledger = self.ledger.lock().await;
-        let file_uuid = Uuid::new_v4();
-        let client_config = VerificationClientConfig::from_file(&request.content);
-        let (secret_n, secret_m, rows, cols) = client_config.to_contract();
         for peer in peers.iter() {
-            ledger
-                .create_contract(
-                    peer.clone(),
-                    file_uuid,
-                    file_hash.clone(),
-                    request.ttl,
-                    secret_n.clone(),
-                    secret_m.clone(),
-                    rows,
-                    cols,
-                )
+            let total = ledger
+                .get_total_reputation(*peer)
                 .await
                 .map_err(|e| Status::unknown(e.to_string()))?;
+            if self.reputation.is_banned(total) {
+                return Err(Status::failed_precondition(format!("{} is banned", peer)));
+            }
+            if !ledger
+                .can_stake(*peer, consts::CONTRACT_STAKE)
+                .await
+                .map_err(|e| Status::unknown(e.to_string()))?
+            {
+                return Err(Status::failed_precondition(format!(
+                    "{} has too little reputation to stake on a contract",
+                    peer
+                )));
+            }
+        }
+        let file_uuid = Uuid::new_v4();
+        // holders read their contract when the record arrives, so the
+        // contracts are written first and terminated if the put fails
+        let created = async {
+            for peer in peers.iter() {
+                // every replica gets its own secrets
+                let client_config = VerificationClientConfig::from_file(&request.content);
+                let sealed_secrets = client_config
+                    .seal(auditor_key)
+                    .map_err(|e| Status::internal(e.to_string()))?;
+                let (rows, cols) = client_config.dimensions();
+                ledger
+                    .create_contract(
+                        peer.clone(),
+                        file_uuid,
+                        file_hash.clone(),
+                        request.ttl,
+                        sealed_secrets,
+                        rows,
+                        cols,
+                        None,
+                        client_config.version(),
+                        consts::CONTRACT_STAKE,
+                    )
+                    .await
+                    .map_err(|e| Status::unknown(e.to_string()))?;
+            }
+            Ok::<(), Status>(())
+        }
+        .await;
+        drop(ledger);
+        if let Err(e) = created {
+            self.abandon_contracts(file_uuid).await;
+            return Err(e);
         }
 
         info!("created contract for {}", file_uuid);
 
-        let peer_uuids: Result<Vec<_>, Status> = request
-            .peer_uuids
-            .iter()
-            .map(|x| PeerId::from_str(x).map_err(|e| Status::invalid_argument(e.to_string())))
-            .collect();
-
         let res = self
             .swarm_controller
-            .put_to(file_uuid.clone().to_string(), request.content, peer_uuids?)
+            .put_to(
+                file_uuid.clone().to_string(),
+                request.content,
+                record_expiry(request.ttl),
+                peers,
+            )
             .await;
         info!("put to finished: {:?}", res);
 
@@ -408,8 +571,574 @@ impl KissService for Inner {
             }
             Err(e) => {
                 info!("failed to store file {}", file_uuid);
+                self.abandon_contracts(file_uuid).await;
                 Err(Status::internal(e.to_string()))
             }
         }
     }
 }
+
+impl Inner {
+    /// The redundancy requested by the client, or the one from the settings.
+    fn redundancy_for(
+        &self,
+        requested: Option<crate::util::grpc::kiss_grpc::Redundancy>,
+    ) -> Result<Redundancy, Status> {
+        let redundancy = match requested.and_then(|x| x.mode) {
+            Some(redundancy::Mode::ReplicationFactor(factor)) => Redundancy::Replication {
+                factor: factor as usize,
+            },
+            Some(redundancy::Mode::ErasureCoding(coding)) => Redundancy::ErasureCoding {
+                data_shards: coding.data_shards as usize,
+                parity_shards: coding.parity_shards as usize,
+            },
+            None => self.redundancy.clone(),
+        };
+        match redundancy {
+            Redundancy::Replication { factor } if factor == 0 => Err(Status::invalid_argument(
+                "replication factor must be positive",
+            )),
+            Redundancy::ErasureCoding {
+                data_shards,
+                parity_shards,
+            } if data_shards == 0 || parity_shards == 0 => Err(Status::invalid_argument(
+                "erasure coding needs at least one data and one parity shard",
+            )),
+            Redundancy::ErasureCoding { .. } if !self.por.enabled => {
+                Err(Status::failed_precondition(
+                    "erasure coding needs proof of retrievability contracts to locate the shards",
+                ))
+            }
+            redundancy => Ok(redundancy),
+        }
+    }
+
+    async fn store_content(
+        &self,
+        content: Bytes,
+        ttl: i64,
+        redundancy: Redundancy,
+    ) -> Result<Uuid, Status> {
+        let start_time = SystemTime::now();
+        let file_uuid = Uuid::new_v4();
+
+        let peers_needed = match redundancy {
+            Redundancy::Replication { factor } => factor,
+            Redundancy::ErasureCoding {
+                data_shards,
+                parity_shards,
+            } => data_shards + parity_shards,
+        };
+        // holders stake on their contracts, so peers which can't are skipped;
+        // without proof of retrievability no contracts are written
+        let min_reputation = if self.por.enabled {
+            i64::max(self.selection.min_reputation, consts::CONTRACT_STAKE)
+        } else {
+            self.selection.min_reputation
+        };
+        let candidates = self
+            .selection_candidates(file_uuid, peers_needed, min_reputation)
+            .await?;
+        let closest_peers = selection::select(
+            self.selection.policy,
+            &candidates,
+            min_reputation,
+            peers_needed,
+        );
+
+        match redundancy {
+            Redundancy::Replication { .. } => {
+                self.store_replicated(file_uuid, content, ttl, &closest_peers)
+                    .await?
+            }
+            Redundancy::ErasureCoding {
+                data_shards,
+                parity_shards,
+            } => {
+                if closest_peers.len() < peers_needed {
+                    return Err(Status::failed_precondition(format!(
+                        "{} shards need as many peers, only {} found",
+                        peers_needed,
+                        closest_peers.len()
+                    )));
+                }
+                self.store_erasure_coded(
+                    file_uuid,
+                    content,
+                    ttl,
+                    &closest_peers,
+                    data_shards,
+                    parity_shards,
+                )
+                .await?
+            }
+        }
+
+        let since_start = start_time
+            .elapsed()
+            .map_err(|e| Status::internal(e.to_string()))?
+            .as_millis();
+        info!(
+            "stored file {} at peers [{}] picked by {:?} for {} ms",
+            file_uuid,
+            closest_peers
+                .iter()
+                .map(|x| x.to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            self.selection.policy,
+            since_start
+        );
+
+        Ok(file_uuid)
+    }
+
+    /// The closest peers to the file which aren't banned and have
+    /// `min_reputation`, with their reputation. When too few are found, the
+    /// peers closest to random keys are added as well.
+    async fn selection_candidates(
+        &self,
+        file_uuid: Uuid,
+        peers_needed: usize,
+        min_reputation: i64,
+    ) -> Result<Vec<(PeerId, i64)>, Status> {
+        let mut candidates: Vec<(PeerId, i64)> = vec![];
+        let mut key = file_uuid;
+        for _ in 0..=consts::SELECTION_EXTRA_LOOKUPS {
+            let closest = self.swarm_controller.get_closest_peers(key).await;
+            debug!("closest peers to {}: {:?}", key, closest);
+            for peer in closest.map_err(|e| Status::internal(format!("no closest peers {}", e)))? {
+                if candidates.iter().any(|(x, _)| *x == peer) {
+                    continue;
+                }
+                let (reputation, staked) = self
+                    .ledger
+                    .lock()
+                    .await
+                    .get_balance(peer)
+                    .await
+                    .map_err(|e| Status::unknown(e.to_string()))?;
+                if self.reputation.is_banned(reputation + staked) {
+                    debug!("skipping {}, it's banned", peer);
+                    continue;
+                }
+                if reputation < min_reputation {
+                    debug!("skipping {} with reputation {}", peer, reputation);
+                    continue;
+                }
+                candidates.push((peer, reputation));
+            }
+            if candidates.len() >= peers_needed {
+                break;
+            }
+            key = Uuid::new_v4();
+        }
+        Ok(candidates)
+    }
+
+    async fn store_replicated(
+        &self,
+        file_uuid: Uuid,
+        content: Bytes,
+        ttl: i64,
+        peers: &[PeerId],
+    ) -> Result<(), Status> {
+        // everything derived from the content is computed before handing it to
+        // the swarm, so the content is moved rather than copied
+        let file_hash = hash(&content);
+        debug!("{}", file_hash);
+        let client_configs = if self.por.enabled {
+            peers
+                .iter()
+                .map(|peer| (*peer, VerificationClientConfig::from_file(&content)))
+                .collect()
+        } else {
+            vec![]
+        };
+
+        // holders read their contract when the record arrives, so the
+        // contracts are written first and terminated if the put fails
+        let result = async {
+            for (peer, client_config) in client_configs {
+                self.write_contract(peer, file_uuid, &file_hash, ttl, client_config, None)
+                    .await?;
+            }
+            let result = self
+                .swarm_controller
+                .put_to(
+                    file_uuid.to_string(),
+                    content,
+                    record_expiry(ttl),
+                    peers.to_vec(),
+                )
+                .await;
+            debug!("put finished: {:?}", result);
+            result.map_err(|e| {
+                info!("failed to store file {}, {:?}", file_uuid, e);
+                Status::internal(e.to_string())
+            })
+        }
+        .await;
+        if result.is_err() && self.por.enabled {
+            self.abandon_contracts(file_uuid).await;
+        }
+        result
+    }
+
+    /// Sends every shard to a different peer and makes a contract per shard.
+    async fn store_erasure_coded(
+        &self,
+        file_uuid: Uuid,
+        content: Bytes,
+        ttl: i64,
+        peers: &[PeerId],
+        data_shards: usize,
+        parity_shards: usize,
+    ) -> Result<(), Status> {
+        let file_hash = hash(&content);
+        let shards = erasure::encode(&content, data_shards, parity_shards)
+            .map_err(|e| Status::internal(e.to_string()))?;
+
+        // like in `store_replicated`, every contract comes before its shard
+        let result = async {
+            for (index, (shard_content, peer)) in shards.into_iter().zip(peers).enumerate() {
+                let shard = Shard {
+                    index: index as i64,
+                    data_shards: data_shards as i64,
+                    parity_shards: parity_shards as i64,
+                    file_size: content.len() as i64,
+                    hash: hash(&shard_content),
+                };
+                let client_config = VerificationClientConfig::from_file(&shard_content);
+                let record_key = shard_record_key(&file_uuid.to_string(), shard.index);
+                self.write_contract(
+                    *peer,
+                    file_uuid,
+                    &file_hash,
+                    ttl,
+                    client_config,
+                    Some(shard),
+                )
+                .await?;
+                self.swarm_controller
+                    .put_to(record_key, shard_content, record_expiry(ttl), vec![*peer])
+                    .await
+                    .map_err(|e| {
+                        info!("failed to store shard {} of {}, {:?}", index, file_uuid, e);
+                        Status::internal(e.to_string())
+                    })?;
+            }
+            Ok::<(), Status>(())
+        }
+        .await;
+        if result.is_err() {
+            self.abandon_contracts(file_uuid).await;
+        }
+        result
+    }
+
+    /// Terminates the contracts of a file which couldn't be stored, so the
+    /// holders get their stakes back.
+    async fn abandon_contracts(&self, file_uuid: Uuid) {
+        if let Err(e) = self
+            .ledger
+            .lock()
+            .await
+            .terminate_contracts(file_uuid.to_string())
+            .await
+        {
+            warn!("failed to terminate the contracts of {}: {}", file_uuid, e);
+        }
+    }
+
+    async fn write_contract(
+        &self,
+        peer: PeerId,
+        file_uuid: Uuid,
+        file_hash: &str,
+        ttl: i64,
+        client_config: VerificationClientConfig,
+        shard: Option<Shard>,
+    ) -> Result<(), Status> {
+        // the secrets are sealed before they leave this node
+        let sealed_secrets = self
+            .auditor_keys
+            .public()
+            .and_then(|auditor_key| client_config.seal(auditor_key))
+            .map_err(|e| Status::failed_precondition(e.to_string()))?;
+        let (rows, cols) = client_config.dimensions();
+        // retry writing the contract tot he ledger 10 times:
+        // writing sometimes fails
+        let mut last_error = String::new();
+        for _ in 0..10 {
+            let res = self
+                .ledger
+                .lock()
+                .await
+                .create_contract(
+                    peer,
+                    file_uuid,
+                    file_hash.to_string(),
+                    ttl,
+                    sealed_secrets.clone(),
+                    rows,
+                    cols,
+                    shard.clone(),
+                    client_config.version(),
+                    consts::CONTRACT_STAKE,
+                )
+                .await;
+            match res {
+                Ok(()) => return Ok(()),
+                Err(e) => last_error = e.to_string(),
+            }
+        }
+        Err(Status::internal(format!(
+            "failed to write contract to immudb: {}",
+            last_error
+        )))
+    }
+
+    async fn retrieve_content(&self, name: String) -> Result<(Bytes, String), Status> {
+        let contract = self
+            .ledger
+            .lock()
+            .await
+            .get_contract(name.clone())
+            .await
+            .map_err(|e| Status::unknown(e.to_string()))?;
+        if contract.terminated {
+            return Err(Status::not_found("file has been deleted"));
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| Status::internal(e.to_string()))?
+            .as_secs() as i64;
+        if contract.is_expired(now) {
+            return Err(Status::not_found("file has expired"));
+        }
+
+        let content = match &contract.shard {
+            Some(shard) => self.retrieve_shards(name, shard).await?,
+            None => {
+                let res = self.swarm_controller.get(name).await;
+                debug!("get finished: {:?}", res.as_ref().map(|x| x.origin_peer_id));
+                res.map_err(|e| Status::not_found(format!("failed getting from swarm: {}", e)))?
+                    .file
+            }
+        };
+
+        let file_hash = hasher::hash(&content);
+        if file_hash != contract.file_hash {
+            return Err(Status::data_loss("file has been modified"));
+        }
+
+        Ok((content, file_hash))
+    }
+
+    /// Rebuilds an erasure coded file from the first `data_shards` shards
+    /// which can be fetched and match the hash in their contract. A shard
+    /// which doesn't is replaced by the next one, parity shards included.
+    async fn retrieve_shards(&self, file_uuid: String, shard: &Shard) -> Result<Bytes, Status> {
+        let data_shards = shard.data_shards as usize;
+        let parity_shards = shard.parity_shards as usize;
+        let holders: HashMap<i64, (PeerId, Shard)> = self
+            .ledger
+            .lock()
+            .await
+            .get_contracts(file_uuid.clone())
+            .await
+            .map_err(|e| Status::unknown(e.to_string()))?
+            .into_iter()
+            // repaired shards leave terminated contracts with their old holders
+            .filter(|x| !x.terminated)
+            .filter_map(|x| x.shard.map(|shard| (shard.index, (x.peer_id, shard))))
+            .collect();
+
+        let mut shards: Vec<Option<Bytes>> = vec![None; data_shards + parity_shards];
+        let mut fetched = 0;
+        for (index, slot) in shards.iter_mut().enumerate() {
+            if fetched == data_shards {
+                break;
+            }
+            let index = index as i64;
+            let record_key = shard_record_key(&file_uuid, index);
+            let result = match holders.get(&index) {
+                Some((holder, shard)) => self
+                    .swarm_controller
+                    .request_fetch(*holder, record_key.clone())
+                    .await
+                    .and_then(|content| {
+                        if shard.holds(&content) {
+                            Ok(content)
+                        } else {
+                            warn!("{} holds a modified copy of {}", holder, record_key);
+                            Err(ErrorKind::ShardModified(record_key.clone()).into())
+                        }
+                    }),
+                // shards without a known holder are looked up in the dht
+                None => self
+                    .swarm_controller
+                    .get(record_key.clone())
+                    .await
+                    .map(|x| x.file),
+            };
+            match result {
+                Ok(content) => {
+                    *slot = Some(content);
+                    fetched += 1;
+                }
+                Err(e) => debug!("failed getting shard {}: {}", record_key, e),
+            }
+        }
+        if fetched < data_shards {
+            return Err(Status::not_found(format!(
+                "only {} of the {} shards needed were found",
+                fetched, data_shards
+            )));
+        }
+
+        erasure::reconstruct(shards, data_shards, parity_shards, shard.file_size as usize)
+            .map_err(|e| Status::data_loss(e.to_string()))
+    }
+}
+
+/// Gathers the data chunks of a store stream and its trailing metadata.
+/// The whole file ends up in memory, since erasure coding and the PoR setup
+/// need all of it. Streams with more than `max_size` bytes of data are cut
+/// off as soon as they go over, so a client can't make the node buffer an
+/// unbounded file.
+async fn collect_store_chunks(
+    mut chunks: impl Stream<Item = Result<StoreChunk, Status>> + Unpin,
+    max_size: usize,
+) -> Result<(Bytes, StoreMetadata), Status> {
+    let mut content = Bytes::new();
+    let mut metadata = None;
+    while let Some(chunk) = chunks.next().await {
+        match chunk?.payload {
+            Some(store_chunk::Payload::Data(data)) => {
+                if metadata.is_some() {
+                    return Err(Status::invalid_argument(
+                        "data chunk received after the trailing metadata",
+                    ));
+                }
+                if content.len() + data.len() > max_size {
+                    return Err(Status::resource_exhausted(format!(
+                        "files can be at most {} bytes",
+                        max_size
+                    )));
+                }
+                content.extend_from_slice(&data);
+            }
+            Some(store_chunk::Payload::Metadata(meta)) => {
+                if metadata.is_some() {
+                    return Err(Status::invalid_argument("metadata sent more than once"));
+                }
+                metadata = Some(meta);
+            }
+            None => return Err(Status::invalid_argument("empty store chunk")),
+        }
+    }
+    let metadata = metadata.ok_or_else(|| Status::invalid_argument("missing trailing metadata"))?;
+    Ok((content, metadata))
+}
+
+/// Splits the content into data chunks of up to `chunk_size` bytes,
+/// followed by the metadata. The content is already fully in memory; this
+/// only keeps each gRPC message small.
+fn retrieve_chunks(
+    content: Bytes,
+    metadata: RetrieveMetadata,
+    chunk_size: usize,
+) -> impl Stream<Item = Result<RetrieveChunk, Status>> + Send + 'static {
+    let metadata = RetrieveChunk {
+        payload: Some(retrieve_chunk::Payload::Metadata(metadata)),
+    };
+    stream::unfold((content, 0), move |(content, offset)| async move {
+        if offset >= content.len() {
+            return None;
+        }
+        let end = usize::min(offset + chunk_size, content.len());
+        let chunk = RetrieveChunk {
+            payload: Some(retrieve_chunk::Payload::Data(
+                content.get(offset..end).unwrap_or_default().to_vec(),
+            )),
+        };
+        Some((Ok::<_, Status>(chunk), (content, end)))
+    })
+    .chain(stream::once(async move { Ok::<_, Status>(metadata) }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn data(bytes: &[u8]) -> Result<StoreChunk, Status> {
+        Ok(StoreChunk {
+            payload: Some(store_chunk::Payload::Data(bytes.to_vec())),
+        })
+    }
+
+    fn metadata() -> Result<StoreChunk, Status> {
+        Ok(StoreChunk {
+            payload: Some(store_chunk::Payload::Metadata(StoreMetadata {
+                name: "file".to_string(),
+                ttl: 60,
+                redundancy: None,
+            })),
+        })
+    }
+
+    #[tokio::test]
+    async fn store_chunks_are_joined() {
+        let chunks = stream::iter(vec![data(b"some "), data(b"content"), metadata()]);
+        let (content, metadata) = collect_store_chunks(chunks, 64).await.unwrap();
+        assert_eq!(content, b"some content".to_vec());
+        assert_eq!(metadata.name, "file");
+    }
+
+    #[tokio::test]
+    async fn malformed_store_streams_are_refused() {
+        for chunks in [
+            vec![data(b"content")],
+            vec![metadata(), data(b"content")],
+            vec![data(b"content"), metadata(), metadata()],
+            vec![Ok(StoreChunk { payload: None }), metadata()],
+        ] {
+            let status = collect_store_chunks(stream::iter(chunks), 64)
+                .await
+                .unwrap_err();
+            assert_eq!(status.code(), tonic::Code::InvalidArgument);
+        }
+    }
+
+    #[tokio::test]
+    async fn oversized_store_streams_are_cut_off() {
+        // the stream never ends, so it has to be cut off while reading
+        let chunks = stream::repeat_with(|| data(&[0; 16]));
+        let status = collect_store_chunks(chunks, 100).await.unwrap_err();
+        assert_eq!(status.code(), tonic::Code::ResourceExhausted);
+    }
+
+    #[tokio::test]
+    async fn retrieved_content_is_chunked_before_the_metadata() {
+        let metadata = RetrieveMetadata {
+            name: "file".to_string(),
+            file_hash: hash(b"some content"),
+            size: 12,
+        };
+        let chunks: Vec<_> = retrieve_chunks(b"some content".to_vec(), metadata.clone(), 5)
+            .map(|x| x.unwrap().payload)
+            .collect()
+            .await;
+        assert_eq!(
+            chunks,
+            vec![
+                Some(retrieve_chunk::Payload::Data(b"some ".to_vec())),
+                Some(retrieve_chunk::Payload::Data(b"conte".to_vec())),
+                Some(retrieve_chunk::Payload::Data(b"nt".to_vec())),
+                Some(retrieve_chunk::Payload::Metadata(metadata)),
+            ]
+        );
+    }
+}
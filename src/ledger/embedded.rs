@@ -0,0 +1,949 @@
+use super::{new_contract, ILedger};
+use crate::util::consts::INITIAL_REPUTATION;
+use crate::util::hasher::hash;
+use crate::util::types::{
+    AuditEntry, Bytes, Contract, ResponseTiming, SealedSecrets, Shard, VerificationClaim,
+};
+use crate::util::{ErrorKind, Res};
+use async_trait::async_trait;
+use libp2p_identity::PeerId;
+use log::info;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use uuid::Uuid;
+
+/// Hash the first entry of the chain points back to.
+const GENESIS: &str = "genesis";
+
+/// A ledger kept in a local file, for nodes which can't reach immudb.
+/// Every change is appended to the file as an entry which holds the hash of
+/// the entry before it, so editing or dropping an entry breaks the chain and
+/// the file is refused when it's opened. The current state is rebuilt from
+/// the entries on open and kept in memory.
+///
+/// Unlike immudb the file isn't shared, so it only suits a single node.
+pub struct EmbeddedLedger {
+    /// Where the entries are kept, `None` keeps them only in memory.
+    path: Option<PathBuf>,
+    sequence: u64,
+    head: String,
+    state: State,
+}
+
+#[derive(Default)]
+struct State {
+    /// In the order they were created.
+    contracts: Vec<Contract>,
+    /// Reputation and stake per peer.
+    reputation: HashMap<PeerId, (i64, i64)>,
+    verifications: Vec<VerificationClaim>,
+    /// In the order they were appended.
+    audits: Vec<AuditEntry>,
+    values: HashMap<String, Bytes>,
+    /// Last heartbeat per verifier. Liveness doesn't outlive the process,
+    /// so heartbeats aren't written to the file.
+    heartbeats: HashMap<PeerId, i64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Event {
+    /// Ledgers written before `ContractStaked` staked in a `ReputationSet`
+    /// right before this.
+    ContractCreated {
+        contract: Contract,
+    },
+    /// Creates the contract and moves its stake from the reputation of the
+    /// holder, so neither can be written without the other.
+    ContractStaked {
+        contract: Contract,
+    },
+    /// Ledgers written before `ContractsReleased` returned the stakes in
+    /// `ReputationSet`s right before this.
+    ContractsTerminated {
+        file_uuid: String,
+    },
+    /// Ledgers written before `ContractReleased` returned the stake in a
+    /// `ReputationSet` right before this.
+    ContractTerminated {
+        contract_uuid: String,
+    },
+    /// Ledgers written before `StakeSlashed` took the stake away in a
+    /// `ReputationSet` right before this.
+    ContractSlashed {
+        contract_uuid: String,
+        amount: i64,
+    },
+    /// Terminates the contracts of the file and returns what's left of their
+    /// stakes to the holders, so neither can be written without the other.
+    ContractsReleased {
+        file_uuid: String,
+    },
+    /// Like `ContractsReleased`, for a single contract.
+    ContractReleased {
+        contract_uuid: String,
+    },
+    /// Takes part of the stake on the contract away from the holder.
+    StakeSlashed {
+        contract_uuid: String,
+        amount: i64,
+    },
+    ContractsRenewed {
+        file_uuid: String,
+        extend_by: i64,
+        renewal_date: i64,
+    },
+    ReputationSet {
+        peer_id: PeerId,
+        reputation: i64,
+        staked: i64,
+    },
+    VerificationClaimed {
+        claim: VerificationClaim,
+    },
+    AuditLogged {
+        entry: AuditEntry,
+    },
+    ValueSet {
+        key: String,
+        value: Bytes,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    sequence: u64,
+    previous: String,
+    event: Event,
+    hash: String,
+}
+
+fn entry_hash(sequence: u64, previous: &str, event: &Event) -> Res<String> {
+    let event = serde_json::to_string(event).map_err(|e| ErrorKind::Generic(e.to_string()))?;
+    Ok(hash(
+        format!("{}\n{}\n{}", sequence, previous, event).as_bytes(),
+    ))
+}
+
+impl State {
+    fn apply(&mut self, event: Event) {
+        match event {
+            Event::ContractCreated { contract } => self.contracts.push(contract),
+            Event::ContractStaked { contract } => {
+                let (reputation, staked) = self.reputation(&contract.peer_id);
+                self.reputation.insert(
+                    contract.peer_id,
+                    (reputation - contract.stake, staked + contract.stake),
+                );
+                self.contracts.push(contract);
+            }
+            Event::ContractsTerminated { file_uuid } => self
+                .contracts
+                .iter_mut()
+                .filter(|x| x.file_uuid == file_uuid)
+                .for_each(|x| {
+                    x.terminated = true;
+                    x.stake = 0;
+                }),
+            Event::ContractTerminated { contract_uuid } => self
+                .contracts
+                .iter_mut()
+                .filter(|x| x.contract_uuid == contract_uuid)
+                .for_each(|x| {
+                    x.terminated = true;
+                    x.stake = 0;
+                }),
+            Event::ContractSlashed {
+                contract_uuid,
+                amount,
+            } => self
+                .contracts
+                .iter_mut()
+                .filter(|x| x.contract_uuid == contract_uuid)
+                .for_each(|x| x.stake -= amount),
+            Event::ContractsReleased { file_uuid } => self.release(|x| x.file_uuid == file_uuid),
+            Event::ContractReleased { contract_uuid } => {
+                self.release(|x| x.contract_uuid == contract_uuid)
+            }
+            Event::StakeSlashed {
+                contract_uuid,
+                amount,
+            } => {
+                let mut slashed = vec![];
+                for contract in self
+                    .contracts
+                    .iter_mut()
+                    .filter(|x| x.contract_uuid == contract_uuid)
+                {
+                    contract.stake -= amount;
+                    slashed.push(contract.peer_id);
+                }
+                for peer_id in slashed {
+                    let (reputation, staked) = self.reputation(&peer_id);
+                    self.reputation
+                        .insert(peer_id, (reputation, staked - amount));
+                }
+            }
+            Event::ContractsRenewed {
+                file_uuid,
+                extend_by,
+                ..
+            } => self
+                .contracts
+                .iter_mut()
+                .filter(|x| x.file_uuid == file_uuid && !x.terminated)
+                .for_each(|x| x.ttl += extend_by),
+            Event::ReputationSet {
+                peer_id,
+                reputation,
+                staked,
+            } => {
+                self.reputation.insert(peer_id, (reputation, staked));
+            }
+            Event::VerificationClaimed { claim } => {
+                // one claim per contract and verifier, like the immudb table
+                self.verifications.retain(|x| {
+                    x.contract_uuid != claim.contract_uuid
+                        || x.verified_by_id != claim.verified_by_id
+                });
+                self.verifications.push(claim);
+            }
+            Event::AuditLogged { entry } => self.audits.push(entry),
+            Event::ValueSet { key, value } => {
+                self.values.insert(key, value);
+            }
+        }
+    }
+
+    /// Terminates the contracts and gives their holders what's left of
+    /// their stakes.
+    fn release(&mut self, filter: impl Fn(&Contract) -> bool) {
+        let mut stakes = vec![];
+        for contract in self.contracts.iter_mut().filter(|x| filter(x)) {
+            if !contract.terminated && contract.stake > 0 {
+                stakes.push((contract.peer_id, contract.stake));
+            }
+            contract.terminated = true;
+            contract.stake = 0;
+        }
+        for (peer_id, stake) in stakes {
+            let (reputation, staked) = self.reputation(&peer_id);
+            self.reputation
+                .insert(peer_id, (reputation + stake, staked - stake));
+        }
+    }
+
+    fn audits(&self, from: i64, to: i64, filter: impl Fn(&AuditEntry) -> bool) -> Vec<AuditEntry> {
+        let mut audits: Vec<AuditEntry> = self
+            .audits
+            .iter()
+            .filter(|x| (from..to).contains(&x.audited_at) && filter(x))
+            .cloned()
+            .collect();
+        // verifiers append whenever their audits finish, not in audit order
+        audits.sort_by_key(|x| x.audited_at);
+        audits
+    }
+
+    fn reputation(&self, peer_id: &PeerId) -> (i64, i64) {
+        self.reputation
+            .get(peer_id)
+            .copied()
+            .unwrap_or((INITIAL_REPUTATION, 0))
+    }
+}
+
+impl EmbeddedLedger {
+    /// Opens the ledger at `path`, creating it if it doesn't exist,
+    /// and checks the hash chain of its entries.
+    pub fn open(path: impl AsRef<Path>) -> Res<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(ErrorKind::FilesystemErr)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(&path)
+            .map_err(ErrorKind::FilesystemErr)?;
+
+        let mut ledger = Self {
+            path: Some(path.clone()),
+            ..Self::in_memory()
+        };
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(ErrorKind::FilesystemErr)?;
+            if line.is_empty() {
+                continue;
+            }
+            let entry: Entry = serde_json::from_str(&line)
+                .map_err(|_| ErrorKind::LedgerChainBroken(ledger.sequence + 1))?;
+            ledger.verify(&entry)?;
+            ledger.sequence = entry.sequence;
+            ledger.head = entry.hash;
+            ledger.state.apply(entry.event);
+        }
+        info!(
+            "opened embedded ledger {} at entry {}",
+            path.display(),
+            ledger.sequence
+        );
+        Ok(ledger)
+    }
+
+    /// A ledger which keeps its entries only in memory and is gone with it.
+    pub fn in_memory() -> Self {
+        Self {
+            path: None,
+            sequence: 0,
+            head: GENESIS.to_string(),
+            state: State::default(),
+        }
+    }
+
+    fn verify(&self, entry: &Entry) -> Res<()> {
+        let expected = self.sequence + 1;
+        if entry.sequence != expected
+            || entry.previous != self.head
+            || entry.hash != entry_hash(entry.sequence, &entry.previous, &entry.event)?
+        {
+            return Err(ErrorKind::LedgerChainBroken(expected).into());
+        }
+        Ok(())
+    }
+
+    /// Writes the event to the file before applying it, so the state never
+    /// holds anything the file doesn't.
+    fn append(&mut self, event: Event) -> Res<()> {
+        let sequence = self.sequence + 1;
+        let entry = Entry {
+            sequence,
+            previous: self.head.clone(),
+            hash: entry_hash(sequence, &self.head, &event)?,
+            event,
+        };
+
+        if let Some(path) = &self.path {
+            let line =
+                serde_json::to_string(&entry).map_err(|e| ErrorKind::Generic(e.to_string()))?;
+            let mut file: File = OpenOptions::new()
+                .append(true)
+                .open(path)
+                .map_err(ErrorKind::FilesystemErr)?;
+            writeln!(file, "{}", line).map_err(ErrorKind::FilesystemErr)?;
+            file.sync_data().map_err(ErrorKind::FilesystemErr)?;
+        }
+
+        self.sequence = sequence;
+        self.head = entry.hash;
+        self.state.apply(entry.event);
+        Ok(())
+    }
+
+    fn set_reputation(&mut self, peer_id: PeerId, reputation: i64, staked: i64) -> Res<()> {
+        self.append(Event::ReputationSet {
+            peer_id,
+            reputation,
+            staked,
+        })
+    }
+}
+
+#[async_trait]
+impl ILedger for EmbeddedLedger {
+    async fn create_contract(
+        &mut self,
+        peer_id: PeerId,
+        file_uuid: Uuid,
+        file_hash: String,
+        ttl: i64,
+        sealed_secrets: SealedSecrets,
+        rows: i64,
+        cols: i64,
+        shard: Option<Shard>,
+        por_version: i64,
+        stake: i64,
+    ) -> Res<()> {
+        let contract = new_contract(
+            peer_id,
+            file_uuid,
+            file_hash,
+            ttl,
+            sealed_secrets,
+            rows,
+            cols,
+            shard,
+            por_version,
+            stake,
+        )?;
+        if self.state.reputation(&peer_id).0 < stake {
+            return Err(ErrorKind::InsufficientReputationToStake.into());
+        }
+        self.append(Event::ContractStaked { contract })
+    }
+
+    async fn get_contract(&mut self, file_uuid: String) -> Res<Contract> {
+        let contracts = self.get_contracts(file_uuid).await?;
+        // a file has one contract per holder, the active ones describe it best
+        let position = contracts.iter().position(|x| !x.terminated).unwrap_or(0);
+        contracts
+            .into_iter()
+            .nth(position)
+            .ok_or_else(|| ErrorKind::InvalidSql.into())
+    }
+
+    async fn get_all_contracts(&mut self) -> Res<Vec<Contract>> {
+        Ok(self.state.contracts.clone())
+    }
+
+    async fn get_active_contracts(&mut self) -> Res<Vec<Contract>> {
+        Ok(self
+            .state
+            .contracts
+            .iter()
+            .filter(|x| !x.terminated)
+            .cloned()
+            .collect())
+    }
+
+    async fn get_contracts(&mut self, file_uuid: String) -> Res<Vec<Contract>> {
+        Ok(self
+            .state
+            .contracts
+            .iter()
+            .filter(|x| x.file_uuid == file_uuid)
+            .cloned()
+            .collect())
+    }
+
+    async fn terminate_contracts(&mut self, file_uuid: String) -> Res<()> {
+        self.append(Event::ContractsReleased { file_uuid })
+    }
+
+    async fn terminate_contract(&mut self, contract_uuid: String) -> Res<()> {
+        self.append(Event::ContractReleased { contract_uuid })
+    }
+
+    async fn slash_contract(&mut self, contract_uuid: String, amount: i64) -> Res<i64> {
+        let contract = self
+            .state
+            .contracts
+            .iter()
+            .find(|x| x.contract_uuid == contract_uuid)
+            .ok_or_else(|| ErrorKind::RecordNotFound(contract_uuid.clone()))?;
+        let amount = amount.min(contract.stake);
+        if amount <= 0 {
+            return Ok(0);
+        }
+        self.append(Event::StakeSlashed {
+            contract_uuid,
+            amount,
+        })?;
+        Ok(amount)
+    }
+
+    async fn renew_contracts(&mut self, file_uuid: String, extend_by: i64) -> Res<()> {
+        let renewal_date = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        self.append(Event::ContractsRenewed {
+            file_uuid,
+            extend_by,
+            renewal_date,
+        })
+    }
+
+    async fn get_reputation(&mut self, peer_id: PeerId) -> Res<i64> {
+        Ok(self.state.reputation(&peer_id).0)
+    }
+
+    async fn get_staked(&mut self, peer_id: PeerId) -> Res<i64> {
+        Ok(self.state.reputation(&peer_id).1)
+    }
+
+    async fn get_balance(&mut self, peer_id: PeerId) -> Res<(i64, i64)> {
+        Ok(self.state.reputation(&peer_id))
+    }
+
+    async fn increase_reputation(&mut self, peer_id: PeerId, amount: i64) -> Res<()> {
+        let (reputation, staked) = self.state.reputation(&peer_id);
+        self.set_reputation(peer_id, reputation + amount, staked)
+    }
+
+    async fn decrease_reputation(&mut self, peer_id: PeerId, amount: i64) -> Res<()> {
+        self.increase_reputation(peer_id, -amount).await
+    }
+
+    async fn decay_reputation(&mut self, peer_id: PeerId, amount: i64) -> Res<()> {
+        self.increase_reputation(peer_id, amount).await
+    }
+
+    async fn stake_reputation(&mut self, peer_id: PeerId, amount: i64) -> Res<()> {
+        let (reputation, staked) = self.state.reputation(&peer_id);
+        if reputation < amount {
+            return Err(ErrorKind::InsufficientReputationToStake.into());
+        }
+        self.set_reputation(peer_id, reputation - amount, staked + amount)
+    }
+
+    async fn unstake_reputation(&mut self, peer_id: PeerId, amount: i64) -> Res<()> {
+        let (reputation, staked) = self.state.reputation(&peer_id);
+        if staked < amount {
+            return Err(ErrorKind::InsufficientReputationToUnstake.into());
+        }
+        self.set_reputation(peer_id, reputation + amount, staked - amount)
+    }
+
+    async fn get_previous_verified(
+        &mut self,
+        contract_uuid: String,
+    ) -> Res<Vec<VerificationClaim>> {
+        Ok(self
+            .state
+            .verifications
+            .iter()
+            .filter(|x| x.contract_uuid == contract_uuid)
+            .cloned()
+            .collect())
+    }
+
+    async fn create_verified_claim(
+        &mut self,
+        contract_uuid: String,
+        verified_by_id: PeerId,
+        succeeded: bool,
+        timing: Option<ResponseTiming>,
+    ) -> Res<()> {
+        let verification_time = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+        self.append(Event::VerificationClaimed {
+            claim: VerificationClaim {
+                contract_uuid,
+                verified_by_id,
+                verification_time,
+                succeeded,
+                timing,
+            },
+        })
+    }
+
+    async fn append_audit(&mut self, entry: AuditEntry) -> Res<()> {
+        self.append(Event::AuditLogged { entry })
+    }
+
+    async fn get_contract_audits(
+        &mut self,
+        contract_uuid: String,
+        from: i64,
+        to: i64,
+    ) -> Res<Vec<AuditEntry>> {
+        Ok(self
+            .state
+            .audits(from, to, |x| x.contract_uuid == contract_uuid))
+    }
+
+    async fn get_peer_audits(
+        &mut self,
+        peer_id: PeerId,
+        from: i64,
+        to: i64,
+    ) -> Res<Vec<AuditEntry>> {
+        Ok(self.state.audits(from, to, |x| {
+            x.holder_id == peer_id || x.verified_by_id == peer_id
+        }))
+    }
+
+    async fn record_verifier_heartbeat(&mut self, peer_id: PeerId, at: i64) -> Res<()> {
+        self.state.heartbeats.insert(peer_id, at);
+        Ok(())
+    }
+
+    async fn get_live_verifiers(&mut self, since: i64) -> Res<Vec<PeerId>> {
+        Ok(self
+            .state
+            .heartbeats
+            .iter()
+            .filter(|(_, at)| **at >= since)
+            .map(|(peer_id, _)| *peer_id)
+            .collect())
+    }
+
+    // the hash chain was checked when the file was opened and every entry
+    // since was written by this node, so the plain reads are already verified
+
+    async fn verified_set(&mut self, key: String, value: Bytes) -> Res<()> {
+        self.append(Event::ValueSet { key, value })
+    }
+
+    async fn verified_get(&mut self, key: String) -> Res<Bytes> {
+        self.state
+            .values
+            .get(&key)
+            .cloned()
+            .ok_or_else(|| ErrorKind::RecordNotFound(key).into())
+    }
+
+    async fn get_verified_contract(&mut self, contract_uuid: String) -> Res<Contract> {
+        self.state
+            .contracts
+            .iter()
+            .find(|x| x.contract_uuid == contract_uuid)
+            .cloned()
+            .ok_or_else(|| ErrorKind::RecordNotFound(contract_uuid).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::consts::CONTRACT_STAKE;
+    use crate::util::types::AuditFailure;
+
+    fn path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("kiss-{}-{}.ledger", name, Uuid::new_v4()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn sealed() -> SealedSecrets {
+        SealedSecrets {
+            ciphertext: vec![1, 2, 3],
+            commitment: hash(&[1, 2, 3]),
+        }
+    }
+
+    async fn store(ledger: &mut EmbeddedLedger, peer_id: PeerId, file_uuid: Uuid) {
+        ledger
+            .create_contract(
+                peer_id,
+                file_uuid,
+                "hash".to_string(),
+                60,
+                sealed(),
+                56,
+                1,
+                None,
+                2,
+                CONTRACT_STAKE,
+            )
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn contracts_survive_reopening() {
+        let path = path("reopen");
+        let file_uuid = Uuid::new_v4();
+        let peers = [PeerId::random(), PeerId::random()];
+        {
+            let mut ledger = EmbeddedLedger::open(&path).unwrap();
+            for peer in peers {
+                store(&mut ledger, peer, file_uuid).await;
+            }
+            ledger
+                .renew_contracts(file_uuid.to_string(), 40)
+                .await
+                .unwrap();
+            ledger.increase_reputation(peers[0], 5).await.unwrap();
+        }
+
+        let mut ledger = EmbeddedLedger::open(&path).unwrap();
+        let contracts = ledger.get_contracts(file_uuid.to_string()).await.unwrap();
+        assert_eq!(contracts.len(), 2);
+        assert!(contracts.iter().all(|x| x.ttl == 100));
+        assert!(contracts.iter().all(|x| x.sealed_secrets == Some(sealed())));
+        assert_eq!(
+            ledger.get_reputation(peers[0]).await.unwrap(),
+            INITIAL_REPUTATION - CONTRACT_STAKE + 5
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn contracts_are_written_with_their_stake() {
+        let mut ledger = EmbeddedLedger::in_memory();
+        let peer = PeerId::random();
+        store(&mut ledger, peer, Uuid::new_v4()).await;
+        assert_eq!(ledger.sequence, 1);
+        assert_eq!(ledger.get_staked(peer).await.unwrap(), CONTRACT_STAKE);
+        assert_eq!(
+            ledger.get_reputation(peer).await.unwrap(),
+            INITIAL_REPUTATION - CONTRACT_STAKE
+        );
+    }
+
+    #[tokio::test]
+    async fn stakes_move_in_the_entry_which_terminates_or_slashes() {
+        let mut ledger = EmbeddedLedger::in_memory();
+        let peer = PeerId::random();
+        let file_uuid = Uuid::new_v4();
+        store(&mut ledger, peer, file_uuid).await;
+        let contract = ledger.get_contract(file_uuid.to_string()).await.unwrap();
+
+        ledger
+            .slash_contract(contract.contract_uuid.clone(), 1)
+            .await
+            .unwrap();
+        assert_eq!(ledger.sequence, 2);
+        assert_eq!(ledger.get_staked(peer).await.unwrap(), CONTRACT_STAKE - 1);
+
+        ledger
+            .terminate_contract(contract.contract_uuid)
+            .await
+            .unwrap();
+        assert_eq!(ledger.sequence, 3);
+        assert_eq!(ledger.get_staked(peer).await.unwrap(), 0);
+        assert_eq!(
+            ledger.get_reputation(peer).await.unwrap(),
+            INITIAL_REPUTATION - 1
+        );
+    }
+
+    #[tokio::test]
+    async fn terminated_contracts_are_not_active() {
+        let path = path("terminate");
+        let mut ledger = EmbeddedLedger::open(&path).unwrap();
+        let (first, second) = (Uuid::new_v4(), Uuid::new_v4());
+        store(&mut ledger, PeerId::random(), first).await;
+        store(&mut ledger, PeerId::random(), second).await;
+
+        ledger.terminate_contracts(first.to_string()).await.unwrap();
+        let active = ledger.get_active_contracts().await.unwrap();
+        assert_eq!(
+            active
+                .iter()
+                .map(|x| x.file_uuid.clone())
+                .collect::<Vec<_>>(),
+            vec![second.to_string()]
+        );
+        assert!(
+            ledger
+                .get_contract(first.to_string())
+                .await
+                .unwrap()
+                .terminated
+        );
+        fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn staking_moves_reputation() {
+        let path = path("stake");
+        let mut ledger = EmbeddedLedger::open(&path).unwrap();
+        let peer = PeerId::random();
+        ledger.increase_reputation(peer, 10).await.unwrap();
+        let reputation = INITIAL_REPUTATION + 10;
+        assert!(ledger.stake_reputation(peer, reputation + 1).await.is_err());
+        ledger.stake_reputation(peer, 4).await.unwrap();
+        assert_eq!(ledger.get_reputation(peer).await.unwrap(), reputation - 4);
+        assert_eq!(ledger.get_staked(peer).await.unwrap(), 4);
+        assert!(ledger.unstake_reputation(peer, 5).await.is_err());
+        ledger.unstake_reputation(peer, 4).await.unwrap();
+        assert_eq!(ledger.get_reputation(peer).await.unwrap(), reputation);
+        fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn stakes_are_slashed_and_returned() {
+        let path = path("slash");
+        let mut ledger = EmbeddedLedger::open(&path).unwrap();
+        let (peer, file_uuid) = (PeerId::random(), Uuid::new_v4());
+        store(&mut ledger, peer, file_uuid).await;
+        assert_eq!(ledger.get_staked(peer).await.unwrap(), CONTRACT_STAKE);
+
+        let contract = ledger.get_contract(file_uuid.to_string()).await.unwrap();
+        let slashed = ledger
+            .slash_contract(contract.contract_uuid.clone(), 1)
+            .await
+            .unwrap();
+        assert_eq!(slashed, 1);
+        // no more than the stake can be taken
+        let slashed = ledger
+            .slash_contract(contract.contract_uuid.clone(), CONTRACT_STAKE)
+            .await
+            .unwrap();
+        assert_eq!(slashed, CONTRACT_STAKE - 1);
+        assert_eq!(ledger.get_staked(peer).await.unwrap(), 0);
+
+        store(&mut ledger, peer, file_uuid).await;
+        ledger
+            .terminate_contracts(file_uuid.to_string())
+            .await
+            .unwrap();
+        assert_eq!(ledger.get_staked(peer).await.unwrap(), 0);
+        assert_eq!(
+            ledger.get_reputation(peer).await.unwrap(),
+            INITIAL_REPUTATION - CONTRACT_STAKE
+        );
+
+        let mut poor = EmbeddedLedger::in_memory();
+        poor.decrease_reputation(peer, INITIAL_REPUTATION)
+            .await
+            .unwrap();
+        assert!(poor
+            .create_contract(
+                peer,
+                file_uuid,
+                "hash".to_string(),
+                60,
+                sealed(),
+                56,
+                1,
+                None,
+                2,
+                CONTRACT_STAKE,
+            )
+            .await
+            .is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn one_claim_per_verifier() {
+        let path = path("claims");
+        let verifier = PeerId::random();
+        let timing = ResponseTiming {
+            round_trip_ms: 120,
+            bound_ms: 90,
+            suspected_outsourcing: true,
+        };
+        {
+            let mut ledger = EmbeddedLedger::open(&path).unwrap();
+            ledger
+                .create_verified_claim("contract".to_string(), verifier, false, None)
+                .await
+                .unwrap();
+            ledger
+                .create_verified_claim("contract".to_string(), verifier, true, Some(timing))
+                .await
+                .unwrap();
+        }
+        let claims = EmbeddedLedger::open(&path)
+            .unwrap()
+            .get_previous_verified("contract".to_string())
+            .await
+            .unwrap();
+        assert_eq!(claims.len(), 1);
+        assert!(claims.iter().all(|x| x.succeeded));
+        assert!(claims.iter().all(|x| x.timing == Some(timing)));
+        fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn every_audit_is_logged() {
+        let path = path("audits");
+        let (holder, verifier, other) = (PeerId::random(), PeerId::random(), PeerId::random());
+        let entry = |contract_uuid: &str, verified_by_id, audited_at, failure| AuditEntry {
+            contract_uuid: contract_uuid.to_string(),
+            holder_id: holder,
+            verified_by_id,
+            audited_at,
+            succeeded: failure.is_none(),
+            latency_ms: Some(40),
+            failure,
+        };
+        {
+            let mut ledger = EmbeddedLedger::open(&path).unwrap();
+            for entry in [
+                entry("contract", verifier, 3000, Some(AuditFailure::Timeout)),
+                entry("contract", verifier, 1000, None),
+                entry("contract", verifier, 2000, None),
+                entry("other", other, 2500, Some(AuditFailure::InvalidProof)),
+            ] {
+                ledger.append_audit(entry).await.unwrap();
+            }
+        }
+        let mut ledger = EmbeddedLedger::open(&path).unwrap();
+        let audits = ledger
+            .get_contract_audits("contract".to_string(), 1000, 3001)
+            .await
+            .unwrap();
+        assert_eq!(
+            audits.iter().map(|x| x.audited_at).collect::<Vec<_>>(),
+            vec![1000, 2000, 3000]
+        );
+        assert_eq!(
+            audits.last().and_then(|x| x.failure),
+            Some(AuditFailure::Timeout)
+        );
+        assert_eq!(
+            ledger
+                .get_contract_audits("contract".to_string(), 1500, 3000)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert_eq!(
+            ledger
+                .get_peer_audits(holder, 0, i64::MAX)
+                .await
+                .unwrap()
+                .len(),
+            4
+        );
+        assert_eq!(
+            ledger
+                .get_peer_audits(other, 0, i64::MAX)
+                .await
+                .unwrap()
+                .len(),
+            1
+        );
+        assert!(ledger
+            .get_peer_audits(PeerId::random(), 0, i64::MAX)
+            .await
+            .unwrap()
+            .is_empty());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn values_survive_reopening() {
+        let path = path("values");
+        {
+            let mut ledger = EmbeddedLedger::open(&path).unwrap();
+            ledger
+                .verified_set("key".to_string(), vec![1, 2])
+                .await
+                .unwrap();
+        }
+        let mut ledger = EmbeddedLedger::open(&path).unwrap();
+        assert_eq!(
+            ledger.verified_get("key".to_string()).await.unwrap(),
+            vec![1, 2]
+        );
+        assert!(ledger.verified_get("other".to_string()).await.is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn tampering_is_detected() {
+        let path = path("tamper");
+        {
+            let mut ledger = EmbeddedLedger::open(&path).unwrap();
+            let peer = PeerId::random();
+            ledger.increase_reputation(peer, 1).await.unwrap();
+            ledger.increase_reputation(peer, 1).await.unwrap();
+        }
+        let content = fs::read_to_string(&path).unwrap();
+        fs::write(
+            &path,
+            content.replacen(
+                &format!("\"reputation\":{}", INITIAL_REPUTATION + 1),
+                "\"reputation\":100",
+                1,
+            ),
+        )
+        .unwrap();
+        assert!(EmbeddedLedger::open(&path).is_err());
+
+        // dropping an entry breaks the chain as well
+        let second = content.lines().nth(1).unwrap();
+        fs::write(&path, format!("{}\n", second)).unwrap();
+        assert!(EmbeddedLedger::open(&path).is_err());
+        fs::remove_file(path).unwrap();
+    }
+}
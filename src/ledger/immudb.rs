@@ -0,0 +1,2315 @@
+use super::proofs::{self, EntrySpec, TrustedState};
+use super::{new_contract, ILedger};
+use crate::util::consts;
+use crate::util::grpc::immudb_grpc::{
+    immu_service_client::ImmuServiceClient, sql_value::Value, CreateDatabaseRequest, Database,
+    ImmutableState, KeyRequest, KeyValue, LoginRequest, NamedParam, NewTxRequest, NewTxResponse,
+    SetRequest, SqlExecRequest, SqlExecResult, SqlGetRequest, SqlQueryRequest, SqlValue,
+    TxMetadata, VerifiableGetRequest, VerifiableSetRequest, VerifiableSqlGetRequest,
+};
+use crate::util::grpc::immudb_grpc::{OpenSessionRequest, TxMode};
+use crate::util::types::{AuditEntry, AuditFailure, ResponseTiming, VerificationClaim};
+use crate::util::{
+    types::{Bytes, Contract, SealedSecrets, Shard},
+    ErrorKind, Res,
+};
+use crate::verifier::por::POR_VERSION_LEGACY;
+use async_trait::async_trait;
+use futures::TryFutureExt;
+use libp2p_identity::PeerId;
+use log::{error, info, warn};
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::{
+    net::SocketAddr,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::sync::Mutex;
+use tonic::{metadata::MetadataMap, transport::Channel, Code, Extensions};
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub struct ImmuLedger {
+    token: String,
+    client: Mutex<Option<ImmuServiceClient<Channel>>>,
+    /// Where `trusted` is kept between runs.
+    state_path: PathBuf,
+    /// The last state of the database this node verified, the proofs of
+    /// every verified read and write are checked against it.
+    trusted: Option<TrustedState>,
+}
+
+#[async_trait]
+impl ILedger for ImmuLedger {
+    async fn create_contract(
+        &mut self,
+        peer_id: PeerId,
+        file_uuid: Uuid,
+        file_hash: String,
+        ttl: i64,
+        sealed_secrets: SealedSecrets,
+        rows: i64,
+        cols: i64,
+        shard: Option<Shard>,
+        por_version: i64,
+        stake: i64,
+    ) -> Res<()> {
+        let contract = new_contract(
+            peer_id,
+            file_uuid,
+            file_hash,
+            ttl,
+            sealed_secrets,
+            rows,
+            cols,
+            shard,
+            por_version,
+            stake,
+        )?;
+        self.record_reputation_event(
+            peer_id,
+            ReputationEvent::Stake,
+            stake,
+            Some(ContractChange::Create(contract)),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn get_reputation(&mut self, peer_id: PeerId) -> Res<i64> {
+        Ok(self.verified_reputation(peer_id, 0).await?.0)
+    }
+
+    async fn get_staked(&mut self, peer_id: PeerId) -> Res<i64> {
+        Ok(self.verified_reputation(peer_id, 0).await?.1)
+    }
+
+    async fn get_balance(&mut self, peer_id: PeerId) -> Res<(i64, i64)> {
+        self.verified_reputation(peer_id, 0).await
+    }
+
+    async fn increase_reputation(&mut self, peer_id: PeerId, amount: i64) -> Res<()> {
+        self.record_reputation_event(peer_id, ReputationEvent::Reward, amount, None)
+            .await?;
+        Ok(())
+    }
+
+    async fn decrease_reputation(&mut self, peer_id: PeerId, amount: i64) -> Res<()> {
+        self.record_reputation_event(peer_id, ReputationEvent::Penalty, amount, None)
+            .await?;
+        Ok(())
+    }
+
+    async fn decay_reputation(&mut self, peer_id: PeerId, amount: i64) -> Res<()> {
+        let event = if amount < 0 {
+            ReputationEvent::Decay
+        } else {
+            ReputationEvent::Recovery
+        };
+        self.record_reputation_event(peer_id, event, amount.abs(), None)
+            .await?;
+        Ok(())
+    }
+
+    async fn stake_reputation(&mut self, peer_id: PeerId, amount: i64) -> Res<()> {
+        self.record_reputation_event(peer_id, ReputationEvent::Stake, amount, None)
+            .await?;
+        Ok(())
+    }
+
+    async fn unstake_reputation(&mut self, peer_id: PeerId, amount: i64) -> Res<()> {
+        self.record_reputation_event(peer_id, ReputationEvent::Unstake, amount, None)
+            .await?;
+        Ok(())
+    }
+
+    async fn get_contract(&mut self, file_uuid: String) -> Res<Contract> {
+        let sql = "SELECT * FROM replica_contracts WHERE file_uuid = @file_uuid;".to_string();
+
+        let params: Vec<NamedParam> = vec![NamedParam {
+            name: "file_uuid".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::S(file_uuid)),
+            }),
+        }];
+        let response = self.query_execute(sql, params).await?;
+        let contracts: Vec<Contract> = response
+            .into_iter()
+            .map(map_row_to_contract)
+            .collect::<Res<_>>()?;
+        // a file has one contract per holder, the active ones describe it best
+        let position = contracts.iter().position(|x| !x.terminated).unwrap_or(0);
+        contracts
+            .into_iter()
+            .nth(position)
+            .ok_or_else(|| ErrorKind::InvalidSql.into())
+    }
+
+    async fn get_all_contracts(&mut self) -> Res<Vec<Contract>> {
+        let sql = "SELECT contract_uuid FROM replica_contracts;".to_string();
+        self.verified_contracts(sql).await
+    }
+
+    async fn get_active_contracts(&mut self) -> Res<Vec<Contract>> {
+        let sql =
+            "SELECT contract_uuid FROM replica_contracts WHERE terminated = false;".to_string();
+        let contracts = self.verified_contracts(sql).await?;
+        // terminated since the query
+        Ok(contracts.into_iter().filter(|x| !x.terminated).collect())
+    }
+
+    async fn terminate_contracts(&mut self, file_uuid: String) -> Res<()> {
+        let contracts = self.get_contracts(file_uuid).await?;
+        for contract in contracts.into_iter().filter(|x| !x.terminated) {
+            self.terminate_contract(contract.contract_uuid).await?;
+        }
+        Ok(())
+    }
+
+    async fn terminate_contract(&mut self, contract_uuid: String) -> Res<()> {
+        let contract = self.verified_contract(contract_uuid.clone(), 0).await?;
+        self.record_reputation_event(
+            contract.peer_id,
+            ReputationEvent::Unstake,
+            0,
+            Some(ContractChange::Terminate(contract_uuid)),
+        )
+        .await?;
+        Ok(())
+    }
+
+    async fn slash_contract(&mut self, contract_uuid: String, amount: i64) -> Res<i64> {
+        let contract = self.verified_contract(contract_uuid.clone(), 0).await?;
+        self.record_reputation_event(
+            contract.peer_id,
+            ReputationEvent::Slash,
+            amount,
+            Some(ContractChange::Slash(contract_uuid)),
+        )
+        .await
+    }
+
+    async fn renew_contracts(&mut self, file_uuid: String, extend_by: i64) -> Res<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let (session_id, transaction_id) = self.begin_transaction().await?;
+
+        let sql = "UPDATE replica_contracts SET ttl = ttl + @extend_by
+                WHERE file_uuid = @file_uuid AND terminated = false;"
+            .to_string();
+        let params: Vec<NamedParam> = vec![
+            NamedParam {
+                name: "file_uuid".to_string(),
+                value: Some(SqlValue {
+                    value: Some(Value::S(file_uuid.clone())),
+                }),
+            },
+            NamedParam {
+                name: "extend_by".to_string(),
+                value: Some(SqlValue {
+                    value: Some(Value::N(extend_by)),
+                }),
+            },
+        ];
+        self.sql_execute_tx(sql, params, session_id.clone(), transaction_id.clone())
+            .await?;
+
+        // every renewal is kept as its own row, so the history of a lease
+        // can be audited later
+        let sql = "INSERT
+                INTO lease_renewals(renewal_uuid, file_uuid, extend_by, renewal_date)
+                VALUES (@renewal_uuid, @file_uuid, @extend_by, @renewal_date);"
+            .to_string();
+        let params: Vec<NamedParam> = vec![
+            NamedParam {
+                name: "renewal_uuid".to_string(),
+                value: Some(SqlValue {
+                    value: Some(Value::S(Uuid::new_v4().to_string())),
+                }),
+            },
+            NamedParam {
+                name: "file_uuid".to_string(),
+                value: Some(SqlValue {
+                    value: Some(Value::S(file_uuid)),
+                }),
+            },
+            NamedParam {
+                name: "extend_by".to_string(),
+                value: Some(SqlValue {
+                    value: Some(Value::N(extend_by)),
+                }),
+            },
+            NamedParam {
+                name: "renewal_date".to_string(),
+                value: Some(SqlValue {
+                    value: Some(Value::N(now)),
+                }),
+            },
+        ];
+        self.sql_execute_tx(sql, params, session_id.clone(), transaction_id.clone())
+            .await?;
+
+        self.commit_transaction(session_id, transaction_id).await?;
+        Ok(())
+    }
+
+    async fn get_contracts(&mut self, file_uuid: String) -> Res<Vec<Contract>> {
+        let sql = "SELECT * FROM replica_contracts WHERE file_uuid = @file_uuid;".to_string();
+        let params: Vec<NamedParam> = vec![NamedParam {
+            name: "file_uuid".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::S(file_uuid)),
+            }),
+        }];
+
+        let response = self.query_execute(sql, params).await?;
+        let contracts: Res<Vec<_>> = response.into_iter().map(map_row_to_contract).collect();
+        Ok(contracts?)
+    }
+
+    async fn get_previous_verified(
+        &mut self,
+        contract_uuid: String,
+    ) -> Res<Vec<VerificationClaim>> {
+        let sql = "SELECT * FROM verifications WHERE contract_uuid = @contract_uuid;".to_string();
+        let params: Vec<NamedParam> = vec![NamedParam {
+            name: "contract_uuid".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::S(contract_uuid)),
+            }),
+        }];
+
+        let response = self.query_execute(sql, params).await?;
+        let claims: Res<Vec<_>> = response
+            .into_iter()
+            .map(map_row_to_verification_claim)
+            .collect();
+        Ok(claims?)
+    }
+
+    async fn create_verified_claim(
+        &mut self,
+        contract_uuid: String,
+        verified_by_id: PeerId,
+        succeeded: bool,
+        timing: Option<ResponseTiming>,
+    ) -> Res<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+        let nullable = |value: Option<Value>| {
+            Some(SqlValue {
+                value: Some(value.unwrap_or(Value::Null(0))),
+            })
+        };
+        let params: Vec<NamedParam> = vec![
+            NamedParam {
+                name: "contract_uuid".to_string(),
+                value: Some(SqlValue {
+                    value: Some(Value::S(contract_uuid)),
+                }),
+            },
+            NamedParam {
+                name: "verified_by_id".to_string(),
+                value: Some(SqlValue {
+                    value: Some(Value::S(verified_by_id.to_base58())),
+                }),
+            },
+            NamedParam {
+                name: "verification_time".to_string(),
+                value: Some(SqlValue {
+                    value: Some(Value::N(now)),
+                }),
+            },
+            NamedParam {
+                name: "succeeded".to_string(),
+                value: Some(SqlValue {
+                    value: Some(Value::B(succeeded)),
+                }),
+            },
+            NamedParam {
+                name: "round_trip_ms".to_string(),
+                value: nullable(timing.map(|x| Value::N(x.round_trip_ms))),
+            },
+            NamedParam {
+                name: "response_bound_ms".to_string(),
+                value: nullable(timing.map(|x| Value::N(x.bound_ms))),
+            },
+            NamedParam {
+                name: "suspected_outsourcing".to_string(),
+                value: nullable(timing.map(|x| Value::B(x.suspected_outsourcing))),
+            },
+        ];
+
+        let sql = "UPSERT
+                INTO verifications(contract_uuid, verified_by_id, verification_time, succeeded, round_trip_ms, response_bound_ms, suspected_outsourcing)
+                VALUES (@contract_uuid, @verified_by_id, @verification_time, @succeeded, @round_trip_ms, @response_bound_ms, @suspected_outsourcing);"
+            .to_string();
+
+        let _response = self.sql_execute(sql, params).await?;
+        Ok(())
+    }
+
+    async fn append_audit(&mut self, entry: AuditEntry) -> Res<()> {
+        let sql = "INSERT
+                INTO audit_log(audit_uuid, contract_uuid, holder_id, verified_by_id, audited_at, succeeded, latency_ms, failure)
+                VALUES (@audit_uuid, @contract_uuid, @holder_id, @verified_by_id, @audited_at, @succeeded, @latency_ms, @failure);"
+            .to_string();
+        let param = |name: &str, value: Value| NamedParam {
+            name: name.to_string(),
+            value: Some(SqlValue { value: Some(value) }),
+        };
+        let params: Vec<NamedParam> = vec![
+            param("audit_uuid", Value::S(Uuid::new_v4().to_string())),
+            param("contract_uuid", Value::S(entry.contract_uuid)),
+            param("holder_id", Value::S(entry.holder_id.to_base58())),
+            param("verified_by_id", Value::S(entry.verified_by_id.to_base58())),
+            param("audited_at", Value::N(entry.audited_at)),
+            param("succeeded", Value::B(entry.succeeded)),
+            param(
+                "latency_ms",
+                entry.latency_ms.map_or(Value::Null(0), Value::N),
+            ),
+            param(
+                "failure",
+                entry
+                    .failure
+                    .map_or(Value::Null(0), |x| Value::S(x.name().to_string())),
+            ),
+        ];
+        self.sql_execute(sql, params).await
+    }
+
+    async fn get_contract_audits(
+        &mut self,
+        contract_uuid: String,
+        from: i64,
+        to: i64,
+    ) -> Res<Vec<AuditEntry>> {
+        let sql = format!(
+            "SELECT {} FROM audit_log
+                WHERE contract_uuid = @contract_uuid AND audited_at >= @from AND audited_at < @to;",
+            AUDIT_LOG_COLUMNS
+        );
+        let mut params = audit_range_params(from, to);
+        params.push(NamedParam {
+            name: "contract_uuid".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::S(contract_uuid)),
+            }),
+        });
+        self.query_audits(sql, params).await
+    }
+
+    async fn get_peer_audits(
+        &mut self,
+        peer_id: PeerId,
+        from: i64,
+        to: i64,
+    ) -> Res<Vec<AuditEntry>> {
+        let sql = format!(
+            "SELECT {} FROM audit_log
+                WHERE (holder_id = @peer_id OR verified_by_id = @peer_id)
+                AND audited_at >= @from AND audited_at < @to;",
+            AUDIT_LOG_COLUMNS
+        );
+        let mut params = audit_range_params(from, to);
+        params.push(NamedParam {
+            name: "peer_id".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::S(peer_id.to_base58())),
+            }),
+        });
+        self.query_audits(sql, params).await
+    }
+
+    async fn record_verifier_heartbeat(&mut self, peer_id: PeerId, at: i64) -> Res<()> {
+        let sql = "UPSERT
+                INTO verifier_heartbeats(peer_id, last_seen)
+                VALUES (@peer_id, @last_seen);"
+            .to_string();
+        let params: Vec<NamedParam> = vec![
+            NamedParam {
+                name: "peer_id".to_string(),
+                value: Some(SqlValue {
+                    value: Some(Value::S(peer_id.to_base58())),
+                }),
+            },
+            NamedParam {
+                name: "last_seen".to_string(),
+                value: Some(SqlValue {
+                    value: Some(Value::N(at)),
+                }),
+            },
+        ];
+        self.sql_execute(sql, params).await
+    }
+
+    async fn get_live_verifiers(&mut self, since: i64) -> Res<Vec<PeerId>> {
+        let sql = "SELECT peer_id FROM verifier_heartbeats WHERE last_seen >= @since;".to_string();
+        let params: Vec<NamedParam> = vec![NamedParam {
+            name: "since".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::N(since)),
+            }),
+        }];
+
+        let response = self.query_execute(sql, params).await?;
+        response
+            .into_iter()
+            .map(|row| match row.first() {
+                Some(SqlValue {
+                    value: Some(Value::S(x)),
+                }) => Ok(PeerId::from_str(x).map_err(ErrorKind::InvalidPeerId)?),
+                _ => Err(ErrorKind::InvalidSqlRow(row.clone()).into()),
+            })
+            .collect()
+    }
+
+    async fn verified_set(&mut self, key: String, value: Bytes) -> Res<()> {
+        let trusted = self.trusted()?;
+        let verifiable_tx = {
+            let mut client = self.client.lock().await;
+            let client = client.as_mut().ok_or(ErrorKind::MutexIsNotMutable)?;
+
+            let mut map = MetadataMap::new();
+            map.insert("authorization", format!("Bearer {}", self.token).parse()?);
+            let request = tonic::Request::from_parts(
+                map,
+                Extensions::default(),
+                VerifiableSetRequest {
+                    set_request: Some(SetRequest {
+                        k_vs: vec![KeyValue {
+                            key: key.as_bytes().to_vec(),
+                            value: value.clone(),
+                            metadata: None,
+                        }],
+                        no_wait: false,
+                        preconditions: vec![],
+                    }),
+                    prove_since_tx: trusted.tx_id,
+                },
+            );
+            client.verifiable_set(request).await?.into_inner()
+        };
+
+        let entry = EntrySpec::plain(key.as_bytes(), None, &value);
+        self.advance(proofs::verify_write(&trusted, &entry, &verifiable_tx))
+    }
+
+    async fn verified_get(&mut self, key: String) -> Res<Bytes> {
+        let trusted = self.trusted()?;
+        let verifiable_entry = {
+            let mut client = self.client.lock().await;
+            let client = client.as_mut().ok_or(ErrorKind::MutexIsNotMutable)?;
+
+            let mut map = MetadataMap::new();
+            map.insert("authorization", format!("Bearer {}", self.token).parse()?);
+            let request = tonic::Request::from_parts(
+                map,
+                Extensions::default(),
+                VerifiableGetRequest {
+                    key_request: Some(KeyRequest {
+                        key: key.as_bytes().to_vec(),
+                        no_wait: false,
+                        at_revision: 0,
+                        at_tx: 0,
+                        since_tx: 0,
+                    }),
+                    prove_since_tx: trusted.tx_id,
+                },
+            );
+            match client.verifiable_get(request).await {
+                Ok(x) => x.into_inner(),
+                Err(status)
+                    if status.code() == Code::NotFound
+                        || status.message().contains("not found") =>
+                {
+                    return Err(ErrorKind::RecordNotFound(key).into())
+                }
+                Err(e) => Err(e)?,
+            }
+        };
+
+        let entry = verifiable_entry
+            .entry
+            .ok_or_else(|| ErrorKind::LedgerProofInvalid("no entry".to_string()))?;
+        // the proof covers the key the server answered with, which has to be
+        // the one asked for
+        if entry.key != key.as_bytes() || entry.referenced_by.is_some() {
+            return Err(ErrorKind::LedgerValueMismatch(key).into());
+        }
+        let spec = EntrySpec::plain(&entry.key, entry.metadata, &entry.value);
+        self.advance(proofs::verify_read(
+            &trusted,
+            entry.tx,
+            &spec,
+            verifiable_entry.inclusion_proof.as_ref(),
+            verifiable_entry.verifiable_tx.as_ref(),
+        ))?;
+        Ok(entry.value)
+    }
+
+    async fn get_verified_contract(&mut self, contract_uuid: String) -> Res<Contract> {
+        self.verified_contract(contract_uuid, 0).await
+    }
+}
+
+fn map_row_to_contract(row: Vec<SqlValue>) -> Res<Contract> {
+    Ok(Contract {
+        contract_uuid: match row.get(0).as_ref() {
+            Some(SqlValue {
+                value: Some(Value::S(x)),
+            }) => x.to_owned(),
+            _ => Err(ErrorKind::InvalidSqlRow(row.clone()))?,
+        },
+        peer_id: match row.get(1).as_ref() {
+            Some(SqlValue {
+                value: Some(Value::S(x)),
+            }) => PeerId::from_str(x).map_err(ErrorKind::InvalidPeerId)?,
+            _ => Err(ErrorKind::InvalidSqlRow(row.clone()))?,
+        },
+        file_uuid: match row.get(2).as_ref() {
+            Some(SqlValue {
+                value: Some(Value::S(x)),
+            }) => x.to_owned(),
+            _ => Err(ErrorKind::InvalidSqlRow(row.clone()))?,
+        },
+        file_hash: match row.get(3).as_ref() {
+            Some(SqlValue {
+                value: Some(Value::S(x)),
+            }) => x.to_owned(),
+            _ => Err(ErrorKind::InvalidSqlRow(row.clone()))?,
+        },
+        upload_date: match row.get(4).as_ref() {
+            Some(SqlValue {
+                value: Some(Value::N(x)),
+            }) => x.to_owned(),
+            _ => Err(ErrorKind::InvalidSqlRow(row.clone()))?,
+        },
+        ttl: match row.get(5).as_ref() {
+            Some(SqlValue {
+                value: Some(Value::N(x)),
+            }) => x.to_owned(),
+            _ => Err(ErrorKind::InvalidSqlRow(row.clone()))?,
+        },
+        secret_n: match row.get(6).as_ref() {
+            Some(SqlValue {
+                value: Some(Value::Bs(x)),
+            }) => x.to_owned(),
+            _ => Err(ErrorKind::InvalidSqlRow(row.clone()))?,
+        },
+        secret_m: match row.get(7).as_ref() {
+            Some(SqlValue {
+                value: Some(Value::Bs(x)),
+            }) => x.to_owned(),
+            _ => Err(ErrorKind::InvalidSqlRow(row.clone()))?,
+        },
+        rows: match row.get(8).as_ref() {
+            Some(SqlValue {
+                value: Some(Value::N(x)),
+            }) => x.to_owned(),
+            _ => Err(ErrorKind::InvalidSqlRow(row.clone()))?,
+        },
+        cols: match row.get(9).as_ref() {
+            Some(SqlValue {
+                value: Some(Value::N(x)),
+            }) => x.to_owned(),
+            _ => Err(ErrorKind::InvalidSqlRow(row.clone()))?,
+        },
+        terminated: match row.get(10).as_ref() {
+            Some(SqlValue {
+                value: Some(Value::B(x)),
+            }) => x.to_owned(),
+            _ => Err(ErrorKind::InvalidSqlRow(row.clone()))?,
+        },
+        shard: match (row.get(11), row.get(12), row.get(13), row.get(14)) {
+            (
+                Some(SqlValue {
+                    value: Some(Value::N(index)),
+                }),
+                Some(SqlValue {
+                    value: Some(Value::N(data_shards)),
+                }),
+                Some(SqlValue {
+                    value: Some(Value::N(parity_shards)),
+                }),
+                Some(SqlValue {
+                    value: Some(Value::N(file_size)),
+                }),
+            ) if *index >= 0 => Some(Shard {
+                index: *index,
+                data_shards: *data_shards,
+                parity_shards: *parity_shards,
+                file_size: *file_size,
+                // shards stored before their hash was recorded have none
+                hash: match row.get(20) {
+                    Some(SqlValue {
+                        value: Some(Value::S(x)),
+                    }) => x.to_owned(),
+                    _ => String::new(),
+                },
+            }),
+            _ => None,
+        },
+        // rows without a version were made before it was recorded
+        por_version: match row.get(15).as_ref() {
+            Some(SqlValue {
+                value: Some(Value::N(x)),
+            }) => x.to_owned(),
+            _ => POR_VERSION_LEGACY,
+        },
+        por_seed: match row.get(16).as_ref() {
+            Some(SqlValue {
+                value: Some(Value::N(x)),
+            }) => *x as u64,
+            _ => 0,
+        },
+        sealed_secrets: match (row.get(17), row.get(18)) {
+            (
+                Some(SqlValue {
+                    value: Some(Value::Bs(ciphertext)),
+                }),
+                Some(SqlValue {
+                    value: Some(Value::S(commitment)),
+                }),
+            ) if !ciphertext.is_empty() => Some(SealedSecrets {
+                ciphertext: ciphertext.to_owned(),
+                commitment: commitment.to_owned(),
+            }),
+            _ => None,
+        },
+        // rows from before stakes have none
+        stake: integer(row.get(19)),
+    })
+}
+
+/// The columns of the audit log in the order `map_row_to_audit_entry` reads
+/// them.
+const AUDIT_LOG_COLUMNS: &str =
+    "contract_uuid, holder_id, verified_by_id, audited_at, succeeded, latency_ms, failure";
+
+fn audit_range_params(from: i64, to: i64) -> Vec<NamedParam> {
+    [("from", from), ("to", to)]
+        .into_iter()
+        .map(|(name, value)| NamedParam {
+            name: name.to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::N(value)),
+            }),
+        })
+        .collect()
+}
+
+fn map_row_to_audit_entry(row: Vec<SqlValue>) -> Res<AuditEntry> {
+    let peer_id = |index: usize| -> Res<PeerId> {
+        match row.get(index) {
+            Some(SqlValue {
+                value: Some(Value::S(x)),
+            }) => Ok(PeerId::from_str(x).map_err(ErrorKind::InvalidPeerId)?),
+            _ => Err(ErrorKind::InvalidSqlRow(row.clone()).into()),
+        }
+    };
+    Ok(AuditEntry {
+        contract_uuid: match row.first() {
+            Some(SqlValue {
+                value: Some(Value::S(x)),
+            }) => x.to_owned(),
+            _ => Err(ErrorKind::InvalidSqlRow(row.clone()))?,
+        },
+        holder_id: peer_id(1)?,
+        verified_by_id: peer_id(2)?,
+        audited_at: match row.get(3) {
+            Some(SqlValue {
+                value: Some(Value::N(x)),
+            }) => *x,
+            _ => Err(ErrorKind::InvalidSqlRow(row.clone()))?,
+        },
+        succeeded: match row.get(4) {
+            Some(SqlValue {
+                value: Some(Value::B(x)),
+            }) => *x,
+            _ => Err(ErrorKind::InvalidSqlRow(row.clone()))?,
+        },
+        latency_ms: match row.get(5) {
+            Some(SqlValue {
+                value: Some(Value::N(x)),
+            }) => Some(*x),
+            _ => None,
+        },
+        failure: match row.get(6) {
+            Some(SqlValue {
+                value: Some(Value::S(x)),
+            }) => Some(
+                AuditFailure::from_name(x).ok_or_else(|| ErrorKind::InvalidSqlRow(row.clone()))?,
+            ),
+            _ => None,
+        },
+    })
+}
+
+fn map_row_to_verification_claim(row: Vec<SqlValue>) -> Res<VerificationClaim> {
+    Ok(VerificationClaim {
+        contract_uuid: match row.get(0).as_ref() {
+            Some(SqlValue {
+                value: Some(Value::S(x)),
+            }) => x.to_owned(),
+            _ => Err(ErrorKind::InvalidSqlRow(row.clone()))?,
+        },
+        verified_by_id: match row.get(1).as_ref() {
+            Some(SqlValue {
+                value: Some(Value::S(x)),
+            }) => PeerId::from_str(x).map_err(ErrorKind::InvalidPeerId)?,
+            _ => Err(ErrorKind::InvalidSqlRow(row.clone()))?,
+        },
+        verification_time: match row.get(2).as_ref() {
+            Some(SqlValue {
+                value: Some(Value::N(x)),
+            }) => x.to_owned(),
+            _ => Err(ErrorKind::InvalidSqlRow(row.clone()))?,
+        },
+        succeeded: match row.get(3).as_ref() {
+            Some(SqlValue {
+                value: Some(Value::B(x)),
+            }) => x.to_owned(),
+            _ => Err(ErrorKind::InvalidSqlRow(row.clone()))?,
+        },
+        // null for audits which got no answer and ones from before timing
+        timing: match (row.get(4), row.get(5), row.get(6)) {
+            (
+                Some(SqlValue {
+                    value: Some(Value::N(round_trip_ms)),
+                }),
+                Some(SqlValue {
+                    value: Some(Value::N(bound_ms)),
+                }),
+                Some(SqlValue {
+                    value: Some(Value::B(suspected_outsourcing)),
+                }),
+            ) => Some(ResponseTiming {
+                round_trip_ms: *round_trip_ms,
+                bound_ms: *bound_ms,
+                suspected_outsourcing: *suspected_outsourcing,
+            }),
+            _ => None,
+        },
+    })
+}
+
+impl ImmuLedger {
+    async fn create_database(&mut self, name: String) -> Res<()> {
+        let mut client = self.client.lock().await;
+        let client = client.as_mut().ok_or(ErrorKind::MutexIsNotMutable)?;
+
+        let mut map = MetadataMap::new();
+        map.insert("authorization", format!("Bearer {}", self.token).parse()?);
+        let request = tonic::Request::from_parts(
+            map,
+            Extensions::default(),
+            CreateDatabaseRequest {
+                name,
+                settings: None,
+                if_not_exists: true,
+            },
+        );
+        let _response = client.create_database_v2(request).await?;
+        info!("created database");
+        Ok(())
+    }
+
+    async fn use_database(&mut self, name: String) -> Res<()> {
+        let mut client = self.client.lock().await;
+        let client = client.as_mut().ok_or(ErrorKind::MutexIsNotMutable)?;
+
+        let mut map = MetadataMap::new();
+        map.insert("authorization", format!("Bearer {}", self.token).parse()?);
+        let request = tonic::Request::from_parts(
+            map,
+            Extensions::default(),
+            Database {
+                database_name: name,
+            },
+        );
+        let response = client.use_database(request).await?.into_inner();
+        self.token = response.token;
+        Ok(())
+    }
+
+    async fn sql_execute(&mut self, sql: String, params: Vec<NamedParam>) -> Res<()> {
+        self.sql_exec(sql, params).await.map(|_| ())
+    }
+
+    /// Runs the statements and returns the transaction they were committed in.
+    async fn sql_execute_committed(&mut self, sql: String, params: Vec<NamedParam>) -> Res<u64> {
+        self.sql_exec(sql, params)
+            .await?
+            .txs
+            .last()
+            .and_then(|x| x.header.as_ref())
+            .map(|x| x.id)
+            .ok_or_else(|| {
+                ErrorKind::LedgerProofInvalid("no transaction header".to_string()).into()
+            })
+    }
+
+    async fn sql_exec(&mut self, sql: String, params: Vec<NamedParam>) -> Res<SqlExecResult> {
+        let mut client = self.client.lock().await;
+        let client = client.as_mut().ok_or(ErrorKind::MutexIsNotMutable)?;
+
+        let mut map = MetadataMap::new();
+        map.insert("authorization", format!("Bearer {}", self.token).parse()?);
+        let request = tonic::Request::from_parts(
+            map,
+            Extensions::default(),
+            SqlExecRequest {
+                sql,
+                params,
+                no_wait: false,
+            },
+        );
+        Ok(client.sql_exec(request).await?.into_inner())
+    }
+
+    async fn sql_execute_tx(
+        &mut self,
+        sql: String,
+        params: Vec<NamedParam>,
+        session_id: String,
+        transaction_id: String,
+    ) -> Res<()> {
+        let mut client = self.client.lock().await;
+        let client = client.as_mut().ok_or(ErrorKind::MutexIsNotMutable)?;
+
+        let mut map = MetadataMap::new();
+        map.insert("authorization", format!("Bearer {}", self.token).parse()?);
+        map.insert("sessionid", format!("{}", session_id).parse()?);
+        map.insert("transactionid", format!("{}", transaction_id).parse()?);
+        let request = tonic::Request::from_parts(
+            map,
+            Extensions::default(),
+            SqlExecRequest {
+                sql,
+                params,
+                no_wait: false,
+            },
+        );
+        let _response = client.sql_exec(request).await?;
+        Ok(())
+    }
+
+    async fn query_execute(
+        &mut self,
+        sql: String,
+        params: Vec<NamedParam>,
+    ) -> Res<Vec<Vec<SqlValue>>> {
+        let mut client = self.client.lock().await;
+        let client = client.as_mut().ok_or(ErrorKind::MutexIsNotMutable)?;
+
+        let mut map = MetadataMap::new();
+        map.insert("authorization", format!("Bearer {}", self.token).parse()?);
+        let request = tonic::Request::from_parts(
+            map,
+            Extensions::default(),
+            SqlQueryRequest {
+                sql,
+                params,
+                reuse_snapshot: false,
+            },
+        );
+        let response = client.sql_query(request).await?;
+        let result: Vec<_> = response
+            .into_inner()
+            .rows
+            .into_iter()
+            .map(|row| row.values)
+            .collect();
+        Ok(result)
+    }
+
+    async fn query_execute_tx(
+        &mut self,
+        sql: String,
+        params: Vec<NamedParam>,
+        session_id: String,
+        transaction_id: String,
+    ) -> Res<Vec<Vec<SqlValue>>> {
+        let mut client = self.client.lock().await;
+        let client = client.as_mut().ok_or(ErrorKind::MutexIsNotMutable)?;
+
+        let mut map = MetadataMap::new();
+        map.insert("authorization", format!("Bearer {}", self.token).parse()?);
+        map.insert("sessionid", format!("{}", session_id).parse()?);
+        map.insert("transactionid", format!("{}", transaction_id).parse()?);
+        let request = tonic::Request::from_parts(
+            map,
+            Extensions::default(),
+            SqlQueryRequest {
+                sql,
+                params,
+                reuse_snapshot: false,
+            },
+        );
+        let response = client.sql_query(request).await?;
+        let result: Vec<_> = response
+            .into_inner()
+            .rows
+            .into_iter()
+            .map(|row| row.values)
+            .collect();
+        Ok(result)
+    }
+
+    /// Runs a query over the audit log, oldest audit first.
+    async fn query_audits(&mut self, sql: String, params: Vec<NamedParam>) -> Res<Vec<AuditEntry>> {
+        let mut audits = self
+            .query_execute(sql, params)
+            .await?
+            .into_iter()
+            .map(map_row_to_audit_entry)
+            .collect::<Res<Vec<_>>>()?;
+        // ordering in the query would need an index on the time
+        audits.sort_by_key(|x| x.audited_at);
+        Ok(audits)
+    }
+
+    /// Returns the transaction the contract was written in.
+    async fn insert_contract(&mut self, contract: Contract) -> Res<u64> {
+        let (sql, params) = insert_contract_statement(contract);
+        self.sql_execute_committed(sql, params).await
+    }
+
+    async fn table_exists(&mut self, name: &str) -> Res<bool> {
+        let sql = "SELECT name FROM TABLES() WHERE name = @name;".to_string();
+        let params: Vec<NamedParam> = vec![NamedParam {
+            name: "name".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::S(name.to_string())),
+            }),
+        }];
+        Ok(!self.query_execute(sql, params).await?.is_empty())
+    }
+
+    async fn column_exists(&mut self, table: &str, column: &str) -> Res<bool> {
+        let sql = format!("SELECT name FROM COLUMNS('{}') WHERE name = @name;", table);
+        let params: Vec<NamedParam> = vec![NamedParam {
+            name: "name".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::S(column.to_string())),
+            }),
+        }];
+        Ok(!self.query_execute(sql, params).await?.is_empty())
+    }
+
+    async fn applied_migrations(&mut self) -> Res<Vec<i64>> {
+        let sql = "SELECT version FROM migrations;".to_string();
+        let response = self.query_execute(sql, vec![]).await?;
+        Ok(response
+            .into_iter()
+            .filter_map(|row| match row.first() {
+                Some(SqlValue {
+                    value: Some(Value::N(version)),
+                }) => Some(*version),
+                _ => None,
+            })
+            .collect())
+    }
+
+    async fn record_migration(&mut self, version: i64) -> Res<()> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        let sql = "INSERT INTO migrations(version, applied_at) VALUES (@version, @applied_at);"
+            .to_string();
+        let params: Vec<NamedParam> = vec![
+            NamedParam {
+                name: "version".to_string(),
+                value: Some(SqlValue {
+                    value: Some(Value::N(version)),
+                }),
+            },
+            NamedParam {
+                name: "applied_at".to_string(),
+                value: Some(SqlValue {
+                    value: Some(Value::N(now)),
+                }),
+            },
+        ];
+        self.sql_execute(sql, params).await
+    }
+
+    /// Opens a session with a read-write transaction in it.
+    /// Returns the session id and the transaction id.
+    async fn begin_transaction(&mut self) -> Res<(String, String)> {
+        let mut client = self.client.lock().await;
+        let client = client.as_mut().ok_or(ErrorKind::MutexIsNotMutable)?;
+
+        let mut map = MetadataMap::new();
+        map.insert("authorization", format!("Bearer {}", self.token).parse()?);
+        let request = tonic::Request::from_parts(
+            map,
+            Extensions::default(),
+            OpenSessionRequest {
+                username: "immudb".as_bytes().to_vec(),
+                password: "immudb".as_bytes().to_vec(),
+                database_name: consts::DATABASE_NAME.to_string(),
+            },
+        );
+        let session_id = client.open_session(request).await?.into_inner().session_id;
+
+        let mut map = MetadataMap::new();
+        map.insert("authorization", format!("Bearer {}", self.token).parse()?);
+        map.insert("sessionid", session_id.parse()?);
+        let request = tonic::Request::from_parts(
+            map,
+            Extensions::default(),
+            NewTxRequest {
+                mode: TxMode::ReadWrite as i32,
+            },
+        );
+        let transaction_id = client.new_tx(request).await?.into_inner().transaction_id;
+
+        Ok((session_id, transaction_id))
+    }
+
+    /// Drops the transaction and closes the session it was opened in.
+    async fn rollback_transaction(
+        &mut self,
+        session_id: String,
+        transaction_id: String,
+    ) -> Res<()> {
+        let mut client = self.client.lock().await;
+        let client = client.as_mut().ok_or(ErrorKind::MutexIsNotMutable)?;
+
+        let mut map = MetadataMap::new();
+        map.insert("authorization", format!("Bearer {}", self.token).parse()?);
+        map.insert("sessionid", session_id.parse()?);
+        map.insert("transactionid", transaction_id.parse()?);
+        let request = tonic::Request::from_parts(map, Extensions::default(), ());
+        client.rollback(request).await?;
+
+        let mut map = MetadataMap::new();
+        map.insert("authorization", format!("Bearer {}", self.token).parse()?);
+        map.insert("sessionid", session_id.parse()?);
+        let request = tonic::Request::from_parts(map, Extensions::default(), ());
+        client.close_session(request).await?;
+        Ok(())
+    }
+
+    /// Commits the transaction and closes the session it was opened in.
+    /// Returns the id immudb gave the transaction.
+    async fn commit_transaction(&mut self, session_id: String, transaction_id: String) -> Res<u64> {
+        let mut client = self.client.lock().await;
+        let client = client.as_mut().ok_or(ErrorKind::MutexIsNotMutable)?;
+
+        let mut map = MetadataMap::new();
+        map.insert("authorization", format!("Bearer {}", self.token).parse()?);
+        map.insert("sessionid", session_id.parse()?);
+        map.insert("transactionid", transaction_id.parse()?);
+        let request = tonic::Request::from_parts(map, Extensions::default(), ());
+        let committed = client.commit(request).await;
+
+        let mut map = MetadataMap::new();
+        map.insert("authorization", format!("Bearer {}", self.token).parse()?);
+        map.insert("sessionid", session_id.parse()?);
+        let request = tonic::Request::from_parts(map, Extensions::default(), ());
+        client.close_session(request).await?;
+
+        let committed = match committed {
+            Ok(x) => x.into_inner(),
+            Err(status) if status.message().contains("conflict") => {
+                Err(ErrorKind::TransactionConflict)?
+            }
+            Err(e) => Err(e)?,
+        };
+        committed.header.map(|x| x.id).ok_or_else(|| {
+            ErrorKind::LedgerProofInvalid("no transaction header".to_string()).into()
+        })
+    }
+
+    /// Appends the event, moves the balance it changes and makes the change
+    /// to the contract in one transaction. immudb refuses to commit a
+    /// transaction when what it read was changed in the meantime, so
+    /// concurrent updates are retried instead of lost.
+    /// Returns the amount the event moved.
+    async fn record_reputation_event(
+        &mut self,
+        peer_id: PeerId,
+        event: ReputationEvent,
+        amount: i64,
+        contract: Option<ContractChange>,
+    ) -> Res<i64> {
+        for attempt in 1..=consts::REPUTATION_UPDATE_ATTEMPTS {
+            let (session_id, transaction_id) = self.begin_transaction().await?;
+            let applied = match self
+                .apply_reputation_event(
+                    peer_id,
+                    event,
+                    amount,
+                    contract.as_ref(),
+                    session_id.clone(),
+                    transaction_id.clone(),
+                )
+                .await
+            {
+                Ok(Some(applied)) => applied,
+                // nothing to write, so there is nothing to commit either
+                Ok(None) => {
+                    self.rollback_transaction(session_id, transaction_id)
+                        .await?;
+                    return Ok(0);
+                }
+                Err(e) => {
+                    self.rollback_transaction(session_id, transaction_id)
+                        .await?;
+                    return Err(e);
+                }
+            };
+            let tx_id = match self.commit_transaction(session_id, transaction_id).await {
+                Ok(tx_id) => tx_id,
+                Err(e) if matches!(e.kind(), ErrorKind::TransactionConflict) => {
+                    warn!(
+                        "reputation of {} changed during attempt {} of the {}, retrying",
+                        peer_id,
+                        attempt,
+                        event.name()
+                    );
+                    continue;
+                }
+                Err(e) => return Err(e),
+            };
+
+            let (amount, balance) = applied;
+            if let Some((reputation, staked)) = balance {
+                self.check_reputation(peer_id, tx_id, reputation, staked)
+                    .await?;
+            }
+            if let Some(ContractChange::Create(contract)) = &contract {
+                self.check_contract(contract, tx_id).await?;
+            }
+            return Ok(amount);
+        }
+        Err(ErrorKind::TransactionConflict.into())
+    }
+
+    /// Returns the amount the event moved and, unless that's nothing, the
+    /// reputation and stake after it. `None` when there's nothing to write.
+    async fn apply_reputation_event(
+        &mut self,
+        peer_id: PeerId,
+        event: ReputationEvent,
+        amount: i64,
+        contract: Option<&ContractChange>,
+        session_id: String,
+        transaction_id: String,
+    ) -> Res<Option<(i64, Option<(i64, i64)>)>> {
+        // the stake on the contract is read in the transaction as well, so
+        // two verifiers can't both take it
+        let amount = match contract {
+            Some(ContractChange::Terminate(contract_uuid)) => {
+                self.contract_stake(contract_uuid, session_id.clone(), transaction_id.clone())
+                    .await?
+            }
+            Some(ContractChange::Slash(contract_uuid)) => amount.min(
+                self.contract_stake(contract_uuid, session_id.clone(), transaction_id.clone())
+                    .await?,
+            ),
+            _ => amount,
+        };
+        if amount <= 0 && matches!(contract, None | Some(ContractChange::Slash(_))) {
+            return Ok(None);
+        }
+
+        let balance = if amount != 0 {
+            Some(
+                self.write_reputation_event(
+                    peer_id,
+                    event,
+                    amount,
+                    session_id.clone(),
+                    transaction_id.clone(),
+                )
+                .await?,
+            )
+        } else {
+            None
+        };
+        if let Some(contract) = contract {
+            let (sql, params) = contract.statement(amount);
+            self.sql_execute_tx(sql, params, session_id, transaction_id)
+                .await?;
+        }
+        Ok(Some((amount, balance)))
+    }
+
+    /// Returns the reputation and stake after the event.
+    async fn write_reputation_event(
+        &mut self,
+        peer_id: PeerId,
+        event: ReputationEvent,
+        amount: i64,
+        session_id: String,
+        transaction_id: String,
+    ) -> Res<(i64, i64)> {
+        let sql = "SELECT reputation, staked FROM reputation WHERE peer_id = @peer_id;".to_string();
+        let params: Vec<NamedParam> = vec![NamedParam {
+            name: "peer_id".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::S(peer_id.to_base58())),
+            }),
+        }];
+        let response = self
+            .query_execute_tx(sql, params, session_id.clone(), transaction_id.clone())
+            .await?;
+        let (reputation, staked) = match response.first() {
+            Some(row) => (integer(row.first()), integer(row.get(1))),
+            None => (consts::INITIAL_REPUTATION, 0),
+        };
+        let (reputation, staked) = event.apply(reputation, staked, amount)?;
+
+        let params = reputation_event_params(peer_id.to_base58(), event, amount)?;
+        self.sql_execute_tx(
+            INSERT_REPUTATION_EVENT.to_string(),
+            params,
+            session_id.clone(),
+            transaction_id.clone(),
+        )
+        .await?;
+
+        let sql = "UPSERT
+                INTO reputation(peer_id, reputation, staked)
+                VALUES (@peer_id, @reputation, @staked);"
+            .to_string();
+        let params: Vec<NamedParam> = vec![
+            NamedParam {
+                name: "peer_id".to_string(),
+                value: Some(SqlValue {
+                    value: Some(Value::S(peer_id.to_base58())),
+                }),
+            },
+            NamedParam {
+                name: "reputation".to_string(),
+                value: Some(SqlValue {
+                    value: Some(Value::N(reputation)),
+                }),
+            },
+            NamedParam {
+                name: "staked".to_string(),
+                value: Some(SqlValue {
+                    value: Some(Value::N(staked)),
+                }),
+            },
+        ];
+        self.sql_execute_tx(sql, params, session_id, transaction_id)
+            .await?;
+        Ok((reputation, staked))
+    }
+
+    /// The stake left on the contract, read in the transaction.
+    async fn contract_stake(
+        &mut self,
+        contract_uuid: &str,
+        session_id: String,
+        transaction_id: String,
+    ) -> Res<i64> {
+        let sql =
+            "SELECT stake FROM replica_contracts WHERE contract_uuid = @contract_uuid;".to_string();
+        let params: Vec<NamedParam> = vec![NamedParam {
+            name: "contract_uuid".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::S(contract_uuid.to_string())),
+            }),
+        }];
+        let response = self
+            .query_execute_tx(sql, params, session_id, transaction_id)
+            .await?;
+        match response.first() {
+            Some(row) => Ok(integer(row.first())),
+            None => Err(ErrorKind::RecordNotFound(contract_uuid.to_string()).into()),
+        }
+    }
+
+    async fn current_state(&mut self) -> Res<ImmutableState> {
+        let mut client = self.client.lock().await;
+        let client = client.as_mut().ok_or(ErrorKind::MutexIsNotMutable)?;
+
+        let mut map = MetadataMap::new();
+        map.insert("authorization", format!("Bearer {}", self.token).parse()?);
+        let request = tonic::Request::from_parts(map, Extensions::default(), ());
+        Ok(client.current_state(request).await?.into_inner())
+    }
+
+    fn trusted(&self) -> Res<TrustedState> {
+        self.trusted
+            .clone()
+            .ok_or_else(|| ErrorKind::LedgerProofInvalid("no trusted state".to_string()).into())
+    }
+
+    /// Moves the trusted state forward once a proof checks out. A proof
+    /// which doesn't means immudb can't be trusted anymore, so it's logged
+    /// loudly and the state stays where it was.
+    fn advance(&mut self, state: Res<TrustedState>) -> Res<()> {
+        let state = state.map_err(|e| {
+            error!(
+                "immudb failed verification against the trusted state in {}: {}",
+                self.state_path.display(),
+                e
+            );
+            e
+        })?;
+        // most reads prove the transaction the state is already at
+        if self
+            .trusted
+            .as_ref()
+            .is_some_and(|trusted| trusted.tx_id >= state.tx_id)
+        {
+            return Ok(());
+        }
+        state.save(&self.state_path)?;
+        self.trusted = Some(state);
+        Ok(())
+    }
+
+    /// Reads a row by its primary key, as of transaction `at_tx` or the
+    /// latest one when it's 0, and checks its proof. Returns the values in
+    /// the order of the columns, like `SELECT *`, or `None` if there is no
+    /// such row. immudb can't prove that a row is missing.
+    async fn verified_sql_get(
+        &mut self,
+        table: &str,
+        pk_values: Vec<SqlValue>,
+        at_tx: u64,
+    ) -> Res<Option<Vec<SqlValue>>> {
+        let trusted = self.trusted()?;
+        let verifiable_entry = {
+            let mut client = self.client.lock().await;
+            let client = client.as_mut().ok_or(ErrorKind::MutexIsNotMutable)?;
+
+            let mut map = MetadataMap::new();
+            map.insert("authorization", format!("Bearer {}", self.token).parse()?);
+            let request = tonic::Request::from_parts(
+                map,
+                Extensions::default(),
+                VerifiableSqlGetRequest {
+                    sql_get_request: Some(SqlGetRequest {
+                        table: table.to_string(),
+                        pk_values,
+                        at_tx,
+                        since_tx: 0,
+                    }),
+                    prove_since_tx: trusted.tx_id,
+                },
+            );
+            match client.verifiable_sql_get(request).await {
+                Ok(x) => x.into_inner(),
+                Err(status)
+                    if status.code() == Code::NotFound
+                        || status.message().contains("not found") =>
+                {
+                    return Ok(None)
+                }
+                Err(e) => Err(e)?,
+            }
+        };
+
+        let entry = verifiable_entry
+            .sql_entry
+            .ok_or_else(|| ErrorKind::LedgerProofInvalid("no row".to_string()))?;
+        let spec = EntrySpec {
+            key: entry.key,
+            metadata: entry.metadata,
+            value: entry.value,
+        };
+        self.advance(proofs::verify_read(
+            &trusted,
+            entry.tx,
+            &spec,
+            verifiable_entry.inclusion_proof.as_ref(),
+            verifiable_entry.verifiable_tx.as_ref(),
+        ))?;
+        if spec.metadata.as_ref().is_some_and(|x| x.deleted) {
+            return Ok(None);
+        }
+        decode_row(&spec.value, &verifiable_entry.col_types_by_id).map(Some)
+    }
+
+    /// Reputation and stake of the peer as of transaction `at_tx`, or the
+    /// latest one when it's 0.
+    async fn verified_reputation(&mut self, peer_id: PeerId, at_tx: u64) -> Res<(i64, i64)> {
+        let peer = Value::S(peer_id.to_base58());
+        let pk_values = vec![SqlValue {
+            value: Some(peer.clone()),
+        }];
+        let row = match self
+            .verified_sql_get("reputation", pk_values, at_tx)
+            .await?
+        {
+            Some(row) => row,
+            None => return Ok((consts::INITIAL_REPUTATION, 0)),
+        };
+        // the proof is for the row the server answered with
+        if row.first().and_then(|x| x.value.as_ref()) != Some(&peer) {
+            return Err(ErrorKind::LedgerValueMismatch(peer_id.to_base58()).into());
+        }
+        Ok((integer(row.get(1)), integer(row.get(2))))
+    }
+
+    /// Reads the reputation back as of the transaction which wrote it, so a
+    /// server which dropped or changed the write is caught.
+    async fn check_reputation(
+        &mut self,
+        peer_id: PeerId,
+        tx_id: u64,
+        reputation: i64,
+        staked: i64,
+    ) -> Res<()> {
+        if self.verified_reputation(peer_id, tx_id).await? != (reputation, staked) {
+            error!(
+                "immudb holds a different reputation for {} than was written in transaction {}",
+                peer_id, tx_id
+            );
+            return Err(ErrorKind::LedgerValueMismatch(peer_id.to_base58()).into());
+        }
+        Ok(())
+    }
+
+    async fn verified_contract(&mut self, contract_uuid: String, at_tx: u64) -> Res<Contract> {
+        let pk_values = vec![SqlValue {
+            value: Some(Value::S(contract_uuid.clone())),
+        }];
+        let row = self
+            .verified_sql_get("replica_contracts", pk_values, at_tx)
+            .await?
+            .ok_or_else(|| ErrorKind::RecordNotFound(contract_uuid.clone()))?;
+        let contract = map_row_to_contract(row)?;
+        if contract.contract_uuid != contract_uuid {
+            return Err(ErrorKind::LedgerValueMismatch(contract_uuid).into());
+        }
+        Ok(contract)
+    }
+
+    /// Reads the contracts listed by the query one by one with their proofs,
+    /// as these are the contracts which get audited.
+    async fn verified_contracts(&mut self, sql: String) -> Res<Vec<Contract>> {
+        let rows = self.query_execute(sql, vec![]).await?;
+        let mut contracts = Vec::with_capacity(rows.len());
+        for row in rows {
+            let contract_uuid = match row.first() {
+                Some(SqlValue {
+                    value: Some(Value::S(x)),
+                }) => x.to_owned(),
+                _ => Err(ErrorKind::InvalidSqlRow(row.clone()))?,
+            };
+            contracts.push(self.verified_contract(contract_uuid, 0).await?);
+        }
+        Ok(contracts)
+    }
+
+    /// Reads the contract back as of the transaction which wrote it.
+    async fn check_contract(&mut self, contract: &Contract, tx_id: u64) -> Res<()> {
+        let stored = self
+            .verified_contract(contract.contract_uuid.clone(), tx_id)
+            .await?;
+        if stored.peer_id != contract.peer_id
+            || stored.file_uuid != contract.file_uuid
+            || stored.file_hash != contract.file_hash
+            || stored.ttl != contract.ttl
+            || stored.shard != contract.shard
+            || stored.sealed_secrets != contract.sealed_secrets
+            || stored.stake != contract.stake
+        {
+            error!(
+                "immudb holds a different contract {} than was written in transaction {}",
+                contract.contract_uuid, tx_id
+            );
+            return Err(ErrorKind::LedgerValueMismatch(contract.contract_uuid.clone()).into());
+        }
+        Ok(())
+    }
+}
+
+/// A change to the reputation of a peer. Changes are only ever appended to
+/// reputation_events, the reputation table holds the balance they add up to.
+#[derive(Debug, Clone, Copy)]
+enum ReputationEvent {
+    Reward,
+    Penalty,
+    Stake,
+    Unstake,
+    /// Staked reputation lost to failed audits.
+    Slash,
+    /// Reputation above the baseline fading with time.
+    Decay,
+    /// Reputation below the baseline coming back with time.
+    Recovery,
+}
+
+impl ReputationEvent {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Reward => "reward",
+            Self::Penalty => "penalty",
+            Self::Stake => "stake",
+            Self::Unstake => "unstake",
+            Self::Slash => "slash",
+            Self::Decay => "decay",
+            Self::Recovery => "recovery",
+        }
+    }
+
+    /// The reputation and stake after the event.
+    fn apply(self, reputation: i64, staked: i64, amount: i64) -> Res<(i64, i64)> {
+        match self {
+            Self::Reward => Ok((reputation + amount, staked)),
+            Self::Penalty => Ok((reputation - amount, staked)),
+            Self::Stake if reputation < amount => {
+                Err(ErrorKind::InsufficientReputationToStake.into())
+            }
+            Self::Stake => Ok((reputation - amount, staked + amount)),
+            Self::Unstake if staked < amount => {
+                Err(ErrorKind::InsufficientReputationToUnstake.into())
+            }
+            Self::Unstake => Ok((reputation + amount, staked - amount)),
+            Self::Slash => Ok((reputation, staked - amount)),
+            Self::Decay => Ok((reputation - amount, staked)),
+            Self::Recovery => Ok((reputation + amount, staked)),
+        }
+    }
+}
+
+/// A change to a contract, made in the same transaction as the change to
+/// the reputation of its holder.
+enum ContractChange {
+    Create(Contract),
+    /// Ends the contract, its stake goes back to the holder.
+    Terminate(String),
+    Slash(String),
+}
+
+impl ContractChange {
+    /// `amount` is the reputation the change moves.
+    fn statement(&self, amount: i64) -> (String, Vec<NamedParam>) {
+        let contract_uuid = |contract_uuid: &str| NamedParam {
+            name: "contract_uuid".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::S(contract_uuid.to_string())),
+            }),
+        };
+        match self {
+            Self::Create(contract) => insert_contract_statement(contract.clone()),
+            Self::Terminate(uuid) => (
+                "UPDATE replica_contracts SET terminated = true, stake = 0
+                    WHERE contract_uuid = @contract_uuid;"
+                    .to_string(),
+                vec![contract_uuid(uuid)],
+            ),
+            Self::Slash(uuid) => (
+                "UPDATE replica_contracts SET stake = stake - @amount
+                    WHERE contract_uuid = @contract_uuid;"
+                    .to_string(),
+                vec![
+                    contract_uuid(uuid),
+                    NamedParam {
+                        name: "amount".to_string(),
+                        value: Some(SqlValue {
+                            value: Some(Value::N(amount)),
+                        }),
+                    },
+                ],
+            ),
+        }
+    }
+}
+
+const INSERT_REPUTATION_EVENT: &str = "INSERT
+        INTO reputation_events(event_uuid, peer_id, kind, amount, created_at)
+        VALUES (@event_uuid, @peer_id, @kind, @amount, @created_at);";
+
+fn reputation_event_params(
+    peer_id: String,
+    event: ReputationEvent,
+    amount: i64,
+) -> Res<Vec<NamedParam>> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+    Ok(vec![
+        NamedParam {
+            name: "event_uuid".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::S(Uuid::new_v4().to_string())),
+            }),
+        },
+        NamedParam {
+            name: "peer_id".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::S(peer_id)),
+            }),
+        },
+        NamedParam {
+            name: "kind".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::S(event.name().to_string())),
+            }),
+        },
+        NamedParam {
+            name: "amount".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::N(amount)),
+            }),
+        },
+        NamedParam {
+            name: "created_at".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::N(now)),
+            }),
+        },
+    ])
+}
+
+fn insert_contract_statement(contract: Contract) -> (String, Vec<NamedParam>) {
+    // full copies are stored with a negative shard index
+    // contracts with plain secrets are stored with an empty ciphertext
+    let sealed = contract.sealed_secrets.unwrap_or(SealedSecrets {
+        ciphertext: vec![],
+        commitment: String::new(),
+    });
+    let shard = contract.shard.unwrap_or(Shard {
+        index: -1,
+        data_shards: 0,
+        parity_shards: 0,
+        file_size: 0,
+        hash: String::new(),
+    });
+    let params: Vec<NamedParam> = vec![
+        NamedParam {
+            name: "contract_uuid".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::S(contract.contract_uuid)),
+            }),
+        },
+        NamedParam {
+            name: "peer_id".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::S(contract.peer_id.to_base58())),
+            }),
+        },
+        NamedParam {
+            name: "file_uuid".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::S(contract.file_uuid)),
+            }),
+        },
+        NamedParam {
+            name: "file_hash".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::S(contract.file_hash)),
+            }),
+        },
+        NamedParam {
+            name: "upload_date".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::N(contract.upload_date)),
+            }),
+        },
+        NamedParam {
+            name: "ttl".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::N(contract.ttl)),
+            }),
+        },
+        NamedParam {
+            name: "secret_n".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::Bs(contract.secret_n)),
+            }),
+        },
+        NamedParam {
+            name: "secret_m".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::Bs(contract.secret_m)),
+            }),
+        },
+        NamedParam {
+            name: "rows".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::N(contract.rows)),
+            }),
+        },
+        NamedParam {
+            name: "cols".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::N(contract.cols)),
+            }),
+        },
+        NamedParam {
+            name: "terminated".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::B(contract.terminated)),
+            }),
+        },
+        NamedParam {
+            name: "shard_index".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::N(shard.index)),
+            }),
+        },
+        NamedParam {
+            name: "data_shards".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::N(shard.data_shards)),
+            }),
+        },
+        NamedParam {
+            name: "parity_shards".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::N(shard.parity_shards)),
+            }),
+        },
+        NamedParam {
+            name: "file_size".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::N(shard.file_size)),
+            }),
+        },
+        NamedParam {
+            name: "por_version".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::N(contract.por_version)),
+            }),
+        },
+        NamedParam {
+            name: "por_seed".to_string(),
+            value: Some(SqlValue {
+                // stored bit for bit, immudb integers are signed
+                value: Some(Value::N(contract.por_seed as i64)),
+            }),
+        },
+        NamedParam {
+            name: "sealed_secrets".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::Bs(sealed.ciphertext)),
+            }),
+        },
+        NamedParam {
+            name: "secrets_commitment".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::S(sealed.commitment)),
+            }),
+        },
+        NamedParam {
+            name: "stake".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::N(contract.stake)),
+            }),
+        },
+        NamedParam {
+            name: "shard_hash".to_string(),
+            value: Some(SqlValue {
+                value: Some(Value::S(shard.hash)),
+            }),
+        },
+    ];
+
+    let sql = "UPSERT
+            INTO replica_contracts(contract_uuid, peer_id, file_uuid, file_hash, upload_date, ttl, secret_n, secret_m, rows, cols, terminated, shard_index, data_shards, parity_shards, file_size, por_version, por_seed, sealed_secrets, secrets_commitment, stake, shard_hash)
+            VALUES (@contract_uuid, @peer_id, @file_uuid, @file_hash, @upload_date, @ttl, @secret_n, @secret_m, @rows, @cols, @terminated, @shard_index, @data_shards, @parity_shards, @file_size, @por_version, @por_seed, @sealed_secrets, @secrets_commitment, @stake, @shard_hash);"
+        .to_string();
+
+    (sql, params)
+}
+
+fn integer(value: Option<&SqlValue>) -> i64 {
+    match value {
+        Some(SqlValue {
+            value: Some(Value::N(x)),
+        }) => *x,
+        _ => 0,
+    }
+}
+
+/// Decodes a row the way immudb stores it: the number of columns, then the
+/// id, the length and the bytes of every column which isn't null.
+/// Returns the values in the order of the column ids.
+fn decode_row(value: &[u8], types_by_id: &HashMap<u32, String>) -> Res<Vec<SqlValue>> {
+    let mut values: BTreeMap<u32, SqlValue> = types_by_id
+        .keys()
+        .map(|id| {
+            (
+                *id,
+                SqlValue {
+                    value: Some(Value::Null(0)),
+                },
+            )
+        })
+        .collect();
+
+    let mut rest = value;
+    for _ in 0..take_u32(&mut rest)? {
+        let id = take_u32(&mut rest)?;
+        let len = take_u32(&mut rest)? as usize;
+        let bytes = take(&mut rest, len)?;
+        let value = match types_by_id.get(&id).map(String::as_str) {
+            Some("INTEGER") => Value::N(i64::from_be_bytes(to_array(bytes)?)),
+            Some("TIMESTAMP") => Value::Ts(i64::from_be_bytes(to_array(bytes)?)),
+            Some("BOOLEAN") => Value::B(bytes == [1]),
+            Some("VARCHAR") => Value::S(String::from_utf8(bytes.to_vec())?),
+            Some("BLOB") => Value::Bs(bytes.to_vec()),
+            _ => return Err(malformed_row()),
+        };
+        values.insert(id, SqlValue { value: Some(value) });
+    }
+    Ok(values.into_values().collect())
+}
+
+fn malformed_row() -> crate::util::Er {
+    ErrorKind::LedgerProofInvalid("malformed row".to_string()).into()
+}
+
+fn take<'a>(rest: &mut &'a [u8], len: usize) -> Res<&'a [u8]> {
+    let (bytes, remaining) = rest.split_at_checked(len).ok_or_else(malformed_row)?;
+    *rest = remaining;
+    Ok(bytes)
+}
+
+fn take_u32(rest: &mut &[u8]) -> Res<u32> {
+    Ok(u32::from_be_bytes(to_array(take(rest, 4)?)?))
+}
+
+fn to_array<const N: usize>(bytes: &[u8]) -> Res<[u8; N]> {
+    bytes.try_into().map_err(|_| malformed_row())
+}
+
+/// Logs into immudb and brings the database schema up to date.
+/// The state verified last is read from `state_path`.
+pub async fn connect(
+    address: SocketAddr,
+    username: String,
+    password: String,
+    state_path: PathBuf,
+) -> Res<ImmuLedger> {
+    let (client, token) = login(address, username, password).await?;
+    init_database(ImmuLedger {
+        token,
+        client: Mutex::new(Some(client)),
+        state_path,
+        trusted: None,
+    })
+    .await
+}
+
+async fn login(
+    address: SocketAddr,
+    username: String,
+    password: String,
+) -> Res<(ImmuServiceClient<Channel>, String)> {
+    let mut client = Some(ImmuServiceClient::connect(format!("http://{}", address)).await?);
+
+    let client = client.as_mut().ok_or(ErrorKind::MutexIsNotMutable)?;
+    let request = tonic::Request::new(LoginRequest {
+        user: username.as_bytes().to_vec(),
+        password: password.as_bytes().to_vec(),
+    });
+    let response = client.login(request).await?;
+
+    let token = response.into_inner().token;
+    info!("logged into immudb");
+    Ok((client.to_owned(), token))
+}
+
+async fn init_database(ledger: ImmuLedger) -> Res<ImmuLedger> {
+    create_database(ledger)
+        .and_then(use_database)
+        .and_then(load_trusted_state)
+        .and_then(create_migrations_table)
+        .and_then(create_contract_table)
+        .and_then(create_reputations_table)
+        .and_then(create_reputation_events_table)
+        .and_then(create_verifications_table)
+        .and_then(create_lease_renewals_table)
+        .and_then(create_verifier_heartbeats_table)
+        .and_then(create_audit_log_table)
+        .and_then(run_migrations)
+        .await
+}
+
+async fn create_database(mut ledger: ImmuLedger) -> Res<ImmuLedger> {
+    ledger
+        .create_database(consts::DATABASE_NAME.to_string())
+        .await?;
+    Ok(ledger)
+}
+
+async fn use_database(mut ledger: ImmuLedger) -> Res<ImmuLedger> {
+    ledger
+        .use_database(consts::DATABASE_NAME.to_string())
+        .await?;
+    Ok(ledger)
+}
+
+/// Without a saved state the first one the server reports has to be trusted,
+/// everything after is checked against it.
+async fn load_trusted_state(mut ledger: ImmuLedger) -> Res<ImmuLedger> {
+    let trusted = match TrustedState::load(&ledger.state_path)? {
+        Some(state) if state.db == consts::DATABASE_NAME => {
+            info!("trusting immudb up to transaction {}", state.tx_id);
+            state
+        }
+        _ => {
+            let state = TrustedState::from_server(ledger.current_state().await?);
+            warn!(
+                "no trusted state in {}, trusting immudb as it is at transaction {}",
+                ledger.state_path.display(),
+                state.tx_id
+            );
+            state.save(&ledger.state_path)?;
+            state
+        }
+    };
+    ledger.trusted = Some(trusted);
+    Ok(ledger)
+}
+
+async fn create_contract_table(mut ledger: ImmuLedger) -> Res<ImmuLedger> {
+    let query = "CREATE TABLE IF NOT EXISTS replica_contracts (
+            contract_uuid   VARCHAR[36],
+            peer_id         VARCHAR[53],
+            file_uuid       VARCHAR[36],
+            file_hash       VARCHAR[1024],
+            upload_date     INTEGER,
+            ttl             INTEGER,
+            secret_n        BLOB,
+            secret_m        BLOB,
+            rows            INTEGER,
+            cols            INTEGER,
+            terminated      BOOLEAN,
+            shard_index     INTEGER,
+            data_shards     INTEGER,
+            parity_shards   INTEGER,
+            file_size       INTEGER,
+            por_version     INTEGER,
+            por_seed        INTEGER,
+            sealed_secrets  BLOB,
+            secrets_commitment VARCHAR[64],
+            stake           INTEGER,
+            shard_hash      VARCHAR[1024],
+            PRIMARY KEY (contract_uuid)
+        );"
+    .to_string();
+    ledger.sql_execute(query, vec![]).await?;
+
+    let query = "CREATE INDEX IF NOT EXISTS ON replica_contracts(file_uuid);".to_string();
+    ledger.sql_execute(query, vec![]).await?;
+    Ok(ledger)
+}
+
+async fn create_reputations_table(mut ledger: ImmuLedger) -> Res<ImmuLedger> {
+    let query = "CREATE TABLE IF NOT EXISTS reputation (
+            peer_id         VARCHAR[53],
+            reputation      INTEGER,
+            staked          INTEGER,
+            PRIMARY KEY (peer_id)
+        );"
+    .to_string();
+
+    ledger.sql_execute(query, vec![]).await?;
+    Ok(ledger)
+}
+
+async fn create_reputation_events_table(mut ledger: ImmuLedger) -> Res<ImmuLedger> {
+    let query = "CREATE TABLE IF NOT EXISTS reputation_events (
+            event_uuid      VARCHAR[36],
+            peer_id         VARCHAR[53],
+            kind            VARCHAR[16],
+            amount          INTEGER,
+            created_at      INTEGER,
+            PRIMARY KEY (event_uuid)
+        );"
+    .to_string();
+    ledger.sql_execute(query, vec![]).await?;
+
+    let query = "CREATE INDEX IF NOT EXISTS ON reputation_events(peer_id);".to_string();
+    ledger.sql_execute(query, vec![]).await?;
+    Ok(ledger)
+}
+
+async fn create_verifications_table(mut ledger: ImmuLedger) -> Res<ImmuLedger> {
+    let query = "CREATE TABLE IF NOT EXISTS verifications (
+            contract_uuid     VARCHAR[36],
+            verified_by_id    VARCHAR[53],
+            verification_time INTEGER,
+            succeeded         BOOLEAN,
+            round_trip_ms     INTEGER,
+            response_bound_ms INTEGER,
+            suspected_outsourcing BOOLEAN,
+            PRIMARY KEY (contract_uuid, verified_by_id)
+        );"
+    .to_string();
+
+    ledger.sql_execute(query, vec![]).await?;
+    Ok(ledger)
+}
+
+async fn create_lease_renewals_table(mut ledger: ImmuLedger) -> Res<ImmuLedger> {
+    let query = "CREATE TABLE IF NOT EXISTS lease_renewals (
+            renewal_uuid    VARCHAR[36],
+            file_uuid       VARCHAR[36],
+            extend_by       INTEGER,
+            renewal_date    INTEGER,
+            PRIMARY KEY (renewal_uuid)
+        );"
+    .to_string();
+
+    ledger.sql_execute(query, vec![]).await?;
+    Ok(ledger)
+}
+
+async fn create_verifier_heartbeats_table(mut ledger: ImmuLedger) -> Res<ImmuLedger> {
+    let query = "CREATE TABLE IF NOT EXISTS verifier_heartbeats (
+            peer_id         VARCHAR[53],
+            last_seen       INTEGER,
+            PRIMARY KEY (peer_id)
+        );"
+    .to_string();
+
+    ledger.sql_execute(query, vec![]).await?;
+    Ok(ledger)
+}
+
+/// One row per challenge, rows are only ever inserted.
+async fn create_audit_log_table(mut ledger: ImmuLedger) -> Res<ImmuLedger> {
+    let query = "CREATE TABLE IF NOT EXISTS audit_log (
+            audit_uuid      VARCHAR[36],
+            contract_uuid   VARCHAR[36],
+            holder_id       VARCHAR[53],
+            verified_by_id  VARCHAR[53],
+            audited_at      INTEGER,
+            succeeded       BOOLEAN,
+            latency_ms      INTEGER,
+            failure         VARCHAR[16],
+            PRIMARY KEY (audit_uuid)
+        );"
+    .to_string();
+
+    ledger.sql_execute(query, vec![]).await?;
+    Ok(ledger)
+}
+
+async fn create_migrations_table(mut ledger: ImmuLedger) -> Res<ImmuLedger> {
+    let query = "CREATE TABLE IF NOT EXISTS migrations (
+            version         INTEGER,
+            applied_at      INTEGER,
+            PRIMARY KEY (version)
+        );"
+    .to_string();
+
+    ledger.sql_execute(query, vec![]).await?;
+    Ok(ledger)
+}
+
+/// Applies the schema migrations which haven't been recorded in the
+/// migrations table yet, in order.
+async fn run_migrations(mut ledger: ImmuLedger) -> Res<ImmuLedger> {
+    let applied = ledger.applied_migrations().await?;
+    if !applied.contains(&1) {
+        migrate_contracts_per_replica(&mut ledger).await?;
+        ledger.record_migration(1).await?;
+        info!("applied migration 1: contracts per replica");
+    }
+    if !applied.contains(&2) {
+        migrate_por_seed_columns(&mut ledger).await?;
+        ledger.record_migration(2).await?;
+        info!("applied migration 2: proof of retrievability seeds");
+    }
+    if !applied.contains(&3) {
+        migrate_sealed_secrets_columns(&mut ledger).await?;
+        ledger.record_migration(3).await?;
+        info!("applied migration 3: sealed contract secrets");
+    }
+    if !applied.contains(&4) {
+        migrate_reputation_events(&mut ledger).await?;
+        ledger.record_migration(4).await?;
+        info!("applied migration 4: reputation events");
+    }
+    if !applied.contains(&5) {
+        migrate_contract_stake_column(&mut ledger).await?;
+        ledger.record_migration(5).await?;
+        info!("applied migration 5: contract stakes");
+    }
+    if !applied.contains(&6) {
+        migrate_verification_timing_columns(&mut ledger).await?;
+        ledger.record_migration(6).await?;
+        info!("applied migration 6: verification response times");
+    }
+    if !applied.contains(&7) {
+        migrate_shard_hash_column(&mut ledger).await?;
+        ledger.record_migration(7).await?;
+        info!("applied migration 7: shard hashes");
+    }
+    Ok(ledger)
+}
+
+/// The old contracts table was keyed by file, so it held at most one
+/// holder per file. Its rows are copied over to replica_contracts, which is
+/// keyed by contract. The old table is left in place since immudb keeps its
+/// history anyway.
+async fn migrate_contracts_per_replica(ledger: &mut ImmuLedger) -> Res<()> {
+    if !ledger.table_exists("contracts").await? {
+        return Ok(());
+    }
+    let rows = ledger
+        .query_execute("SELECT * FROM contracts;".to_string(), vec![])
+        .await?;
+    for mut row in rows {
+        // rows written before contracts could be terminated
+        if row.len() < 11 {
+            row.resize(
+                11,
+                SqlValue {
+                    value: Some(Value::B(false)),
+                },
+            );
+        }
+        ledger.insert_contract(map_row_to_contract(row)?).await?;
+    }
+    Ok(())
+}
+
+/// Balances from before the event log are carried over as a reward of all
+/// the reputation of the peer and a stake of what it had staked, so the
+/// events add up to the balance.
+async fn migrate_reputation_events(ledger: &mut ImmuLedger) -> Res<()> {
+    let rows = ledger
+        .query_execute(
+            "SELECT peer_id, reputation, staked FROM reputation;".to_string(),
+            vec![],
+        )
+        .await?;
+    for row in rows {
+        let peer_id = match row.first() {
+            Some(SqlValue {
+                value: Some(Value::S(x)),
+            }) => x.to_owned(),
+            _ => Err(ErrorKind::InvalidSqlRow(row.clone()))?,
+        };
+        let (reputation, staked) = (integer(row.get(1)), integer(row.get(2)));
+        let mut events = vec![(ReputationEvent::Reward, reputation + staked)];
+        if staked != 0 {
+            events.push((ReputationEvent::Stake, staked));
+        }
+        for (event, amount) in events {
+            let params = reputation_event_params(peer_id.clone(), event, amount)?;
+            ledger
+                .sql_execute(INSERT_REPUTATION_EVENT.to_string(), params)
+                .await?;
+        }
+    }
+    Ok(())
+}
+
+/// Adds the stake to contracts made before holders staked on them.
+async fn migrate_contract_stake_column(ledger: &mut ImmuLedger) -> Res<()> {
+    if ledger.column_exists("replica_contracts", "stake").await? {
+        return Ok(());
+    }
+    let query = "ALTER TABLE replica_contracts ADD COLUMN stake INTEGER;".to_string();
+    ledger.sql_execute(query, vec![]).await
+}
+
+/// Adds the hash of the shard to contracts made before it. Their shards
+/// are only checked through the file they rebuild.
+async fn migrate_shard_hash_column(ledger: &mut ImmuLedger) -> Res<()> {
+    if ledger
+        .column_exists("replica_contracts", "shard_hash")
+        .await?
+    {
+        return Ok(());
+    }
+    let query = "ALTER TABLE replica_contracts ADD COLUMN shard_hash VARCHAR[1024];".to_string();
+    ledger.sql_execute(query, vec![]).await
+}
+
+/// Adds the response time columns to verifications made before them.
+async fn migrate_verification_timing_columns(ledger: &mut ImmuLedger) -> Res<()> {
+    for (column, kind) in [
+        ("round_trip_ms", "INTEGER"),
+        ("response_bound_ms", "INTEGER"),
+        ("suspected_outsourcing", "BOOLEAN"),
+    ] {
+        if ledger.column_exists("verifications", column).await? {
+            continue;
+        }
+        let query = format!("ALTER TABLE verifications ADD COLUMN {} {};", column, kind);
+        ledger.sql_execute(query, vec![]).await?;
+    }
+    Ok(())
+}
+
+/// Adds the columns for the seed of the proof of retrievability secrets to
+/// tables made before them. Contracts without a version are read as legacy.
+async fn migrate_por_seed_columns(ledger: &mut ImmuLedger) -> Res<()> {
+    for column in ["por_version", "por_seed"] {
+        if ledger.column_exists("replica_contracts", column).await? {
+            continue;
+        }
+        let query = format!(
+            "ALTER TABLE replica_contracts ADD COLUMN {} INTEGER;",
+            column
+        );
+        ledger.sql_execute(query, vec![]).await?;
+    }
+    Ok(())
+}
+
+/// Adds the columns for the sealed secrets to tables made before them.
+/// Contracts already in the table keep their plain secrets: immudb keeps
+/// every past value, so sealing them now wouldn't hide them.
+async fn migrate_sealed_secrets_columns(ledger: &mut ImmuLedger) -> Res<()> {
+    for (column, column_type) in [
+        ("sealed_secrets", "BLOB"),
+        ("secrets_commitment", "VARCHAR[64]"),
+    ] {
+        if ledger.column_exists("replica_contracts", column).await? {
+            continue;
+        }
+        let query = format!(
+            "ALTER TABLE replica_contracts ADD COLUMN {} {};",
+            column, column_type
+        );
+        ledger.sql_execute(query, vec![]).await?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reputation_events_move_the_balance() {
+        assert_eq!(ReputationEvent::Reward.apply(3, 1, 2).unwrap(), (5, 1));
+        assert_eq!(ReputationEvent::Penalty.apply(3, 1, 5).unwrap(), (-2, 1));
+        assert_eq!(ReputationEvent::Stake.apply(3, 1, 2).unwrap(), (1, 3));
+        assert_eq!(ReputationEvent::Unstake.apply(3, 1, 1).unwrap(), (4, 0));
+        assert!(ReputationEvent::Stake.apply(3, 1, 4).is_err());
+        assert!(ReputationEvent::Unstake.apply(3, 1, 2).is_err());
+        assert_eq!(ReputationEvent::Slash.apply(3, 1, 1).unwrap(), (3, 0));
+        assert_eq!(ReputationEvent::Decay.apply(3, 1, 1).unwrap(), (2, 1));
+        assert_eq!(ReputationEvent::Recovery.apply(3, 1, 1).unwrap(), (4, 1));
+    }
+
+    #[test]
+    fn rows_are_decoded_in_column_order() {
+        let types_by_id = HashMap::from([
+            (1, "VARCHAR".to_string()),
+            (2, "INTEGER".to_string()),
+            (3, "INTEGER".to_string()),
+        ]);
+        let mut value = 2u32.to_be_bytes().to_vec();
+        value.extend([0, 0, 0, 2, 0, 0, 0, 8]);
+        value.extend((-5i64).to_be_bytes());
+        value.extend([0, 0, 0, 1, 0, 0, 0, 2]);
+        value.extend(b"id");
+
+        let row = decode_row(&value, &types_by_id).unwrap();
+        let values: Vec<_> = row.into_iter().map(|x| x.value.unwrap()).collect();
+        assert_eq!(
+            values,
+            vec![Value::S("id".to_string()), Value::N(-5), Value::Null(0)]
+        );
+        assert!(decode_row(value.split_last().unwrap().1, &types_by_id).is_err());
+    }
+}
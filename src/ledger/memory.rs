@@ -0,0 +1,258 @@
+use super::{EmbeddedLedger, ILedger};
+use crate::util::types::{
+    AuditEntry, Bytes, Contract, ResponseTiming, SealedSecrets, Shard, VerificationClaim,
+};
+use crate::util::Res;
+use async_trait::async_trait;
+use libp2p_identity::PeerId;
+use std::sync::Arc;
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+/// A ledger kept in memory. Clones share the same entries, so several nodes
+/// in one process see the same contracts and reputation, like they would
+/// with immudb, and tests can look at what the nodes wrote.
+#[derive(Clone)]
+pub struct MemoryLedger {
+    inner: Arc<Mutex<EmbeddedLedger>>,
+}
+
+impl Default for MemoryLedger {
+    fn default() -> Self {
+        Self {
+            inner: Arc::new(Mutex::new(EmbeddedLedger::in_memory())),
+        }
+    }
+}
+
+#[async_trait]
+impl ILedger for MemoryLedger {
+    async fn create_contract(
+        &mut self,
+        peer_id: PeerId,
+        file_uuid: Uuid,
+        file_hash: String,
+        ttl: i64,
+        sealed_secrets: SealedSecrets,
+        rows: i64,
+        cols: i64,
+        shard: Option<Shard>,
+        por_version: i64,
+        stake: i64,
+    ) -> Res<()> {
+        self.inner
+            .lock()
+            .await
+            .create_contract(
+                peer_id,
+                file_uuid,
+                file_hash,
+                ttl,
+                sealed_secrets,
+                rows,
+                cols,
+                shard,
+                por_version,
+                stake,
+            )
+            .await
+    }
+
+    async fn get_contract(&mut self, file_uuid: String) -> Res<Contract> {
+        self.inner.lock().await.get_contract(file_uuid).await
+    }
+
+    async fn get_all_contracts(&mut self) -> Res<Vec<Contract>> {
+        self.inner.lock().await.get_all_contracts().await
+    }
+
+    async fn get_active_contracts(&mut self) -> Res<Vec<Contract>> {
+        self.inner.lock().await.get_active_contracts().await
+    }
+
+    async fn get_contracts(&mut self, file_uuid: String) -> Res<Vec<Contract>> {
+        self.inner.lock().await.get_contracts(file_uuid).await
+    }
+
+    async fn terminate_contracts(&mut self, file_uuid: String) -> Res<()> {
+        self.inner.lock().await.terminate_contracts(file_uuid).await
+    }
+
+    async fn terminate_contract(&mut self, contract_uuid: String) -> Res<()> {
+        self.inner
+            .lock()
+            .await
+            .terminate_contract(contract_uuid)
+            .await
+    }
+
+    async fn slash_contract(&mut self, contract_uuid: String, amount: i64) -> Res<i64> {
+        self.inner
+            .lock()
+            .await
+            .slash_contract(contract_uuid, amount)
+            .await
+    }
+
+    async fn renew_contracts(&mut self, file_uuid: String, extend_by: i64) -> Res<()> {
+        self.inner
+            .lock()
+            .await
+            .renew_contracts(file_uuid, extend_by)
+            .await
+    }
+
+    async fn get_reputation(&mut self, peer_id: PeerId) -> Res<i64> {
+        self.inner.lock().await.get_reputation(peer_id).await
+    }
+
+    async fn get_staked(&mut self, peer_id: PeerId) -> Res<i64> {
+        self.inner.lock().await.get_staked(peer_id).await
+    }
+
+    async fn get_balance(&mut self, peer_id: PeerId) -> Res<(i64, i64)> {
+        self.inner.lock().await.get_balance(peer_id).await
+    }
+
+    async fn increase_reputation(&mut self, peer_id: PeerId, amount: i64) -> Res<()> {
+        self.inner
+            .lock()
+            .await
+            .increase_reputation(peer_id, amount)
+            .await
+    }
+
+    async fn decrease_reputation(&mut self, peer_id: PeerId, amount: i64) -> Res<()> {
+        self.inner
+            .lock()
+            .await
+            .decrease_reputation(peer_id, amount)
+            .await
+    }
+
+    async fn decay_reputation(&mut self, peer_id: PeerId, amount: i64) -> Res<()> {
+        self.inner
+            .lock()
+            .await
+            .decay_reputation(peer_id, amount)
+            .await
+    }
+
+    async fn stake_reputation(&mut self, peer_id: PeerId, amount: i64) -> Res<()> {
+        self.inner
+            .lock()
+            .await
+            .stake_reputation(peer_id, amount)
+            .await
+    }
+
+    async fn unstake_reputation(&mut self, peer_id: PeerId, amount: i64) -> Res<()> {
+        self.inner
+            .lock()
+            .await
+            .unstake_reputation(peer_id, amount)
+            .await
+    }
+
+    async fn get_previous_verified(
+        &mut self,
+        contract_uuid: String,
+    ) -> Res<Vec<VerificationClaim>> {
+        self.inner
+            .lock()
+            .await
+            .get_previous_verified(contract_uuid)
+            .await
+    }
+
+    async fn create_verified_claim(
+        &mut self,
+        contract_uuid: String,
+        verified_by_id: PeerId,
+        succeeded: bool,
+        timing: Option<ResponseTiming>,
+    ) -> Res<()> {
+        self.inner
+            .lock()
+            .await
+            .create_verified_claim(contract_uuid, verified_by_id, succeeded, timing)
+            .await
+    }
+
+    async fn append_audit(&mut self, entry: AuditEntry) -> Res<()> {
+        self.inner.lock().await.append_audit(entry).await
+    }
+
+    async fn get_contract_audits(
+        &mut self,
+        contract_uuid: String,
+        from: i64,
+        to: i64,
+    ) -> Res<Vec<AuditEntry>> {
+        self.inner
+            .lock()
+            .await
+            .get_contract_audits(contract_uuid, from, to)
+            .await
+    }
+
+    async fn get_peer_audits(
+        &mut self,
+        peer_id: PeerId,
+        from: i64,
+        to: i64,
+    ) -> Res<Vec<AuditEntry>> {
+        self.inner
+            .lock()
+            .await
+            .get_peer_audits(peer_id, from, to)
+            .await
+    }
+
+    async fn record_verifier_heartbeat(&mut self, peer_id: PeerId, at: i64) -> Res<()> {
+        self.inner
+            .lock()
+            .await
+            .record_verifier_heartbeat(peer_id, at)
+            .await
+    }
+
+    async fn get_live_verifiers(&mut self, since: i64) -> Res<Vec<PeerId>> {
+        self.inner.lock().await.get_live_verifiers(since).await
+    }
+
+    async fn verified_set(&mut self, key: String, value: Bytes) -> Res<()> {
+        self.inner.lock().await.verified_set(key, value).await
+    }
+
+    async fn verified_get(&mut self, key: String) -> Res<Bytes> {
+        self.inner.lock().await.verified_get(key).await
+    }
+
+    async fn get_verified_contract(&mut self, contract_uuid: String) -> Res<Contract> {
+        self.inner
+            .lock()
+            .await
+            .get_verified_contract(contract_uuid)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::consts::INITIAL_REPUTATION;
+
+    #[tokio::test]
+    async fn clones_share_the_ledger() {
+        let mut first = MemoryLedger::default();
+        let mut second = first.clone();
+        let peer = PeerId::random();
+
+        first.increase_reputation(peer, 3).await.unwrap();
+        second.decrease_reputation(peer, 1).await.unwrap();
+        let reputation = INITIAL_REPUTATION + 2;
+        assert_eq!(first.get_reputation(peer).await.unwrap(), reputation);
+        assert_eq!(second.get_reputation(peer).await.unwrap(), reputation);
+    }
+}
@@ -0,0 +1,743 @@
+//! Checks the proofs immudb hands out with its verifiable reads and writes.
+//! Every transaction commits to the ones before it through its accumulated
+//! linear hash (alh), so a transaction and its alh that were once verified
+//! can be carried forward as the trusted state. Later answers from the
+//! server must come with proofs that they belong to the same history.
+//!
+//! Follows the verification in immudb's `embedded/store`, `htree` and `ahtree`.
+
+use crate::util::grpc::immudb_grpc::{
+    DualProof, ImmutableState, InclusionProof, KvMetadata, LinearProof, TxEntry, TxHeader,
+    VerifiableTx,
+};
+use crate::util::{ErrorKind, Res};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest as _, Sha256};
+use std::fs;
+use std::path::Path;
+
+pub type Digest = [u8; 32];
+
+const LEAF_PREFIX: u8 = 0;
+const NODE_PREFIX: u8 = 1;
+
+/// Prefixes immudb puts in front of the keys and values written with `Set`.
+const SET_KEY_PREFIX: u8 = 0;
+const PLAIN_VALUE_PREFIX: u8 = 0;
+
+const DELETED_ATTRIBUTE: u8 = 0;
+const EXPIRES_AT_ATTRIBUTE: u8 = 1;
+const NON_INDEXABLE_ATTRIBUTE: u8 = 2;
+
+/// The last transaction of the database this node has verified.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TrustedState {
+    pub db: String,
+    pub tx_id: u64,
+    /// Hex encoded alh of the transaction.
+    pub tx_hash: String,
+}
+
+impl TrustedState {
+    pub fn new(db: String, tx_id: u64, alh: &Digest) -> Self {
+        Self {
+            db,
+            tx_id,
+            tx_hash: hex::encode(alh),
+        }
+    }
+
+    /// The state the server reports, which can only be taken on trust.
+    pub fn from_server(state: ImmutableState) -> Self {
+        Self::new(state.db, state.tx_id, &to_digest(&state.tx_hash))
+    }
+
+    fn alh(&self) -> Res<Digest> {
+        let bytes = hex::decode(&self.tx_hash)
+            .map_err(|e| ErrorKind::LedgerProofInvalid(format!("trusted state: {}", e)))?;
+        Ok(to_digest(&bytes))
+    }
+
+    pub fn load(path: &Path) -> Res<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read_to_string(path).map_err(ErrorKind::FilesystemErr)?;
+        serde_json::from_str(&content)
+            .map(Some)
+            .map_err(|e| ErrorKind::LedgerProofInvalid(format!("trusted state: {}", e)).into())
+    }
+
+    /// Replaces the file in one step, so a crash leaves either state behind.
+    pub fn save(&self, path: &Path) -> Res<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).map_err(ErrorKind::FilesystemErr)?;
+        }
+        let content = serde_json::to_string(self).map_err(|e| ErrorKind::Generic(e.to_string()))?;
+        let temporary = path.with_extension("tmp");
+        fs::write(&temporary, content).map_err(ErrorKind::FilesystemErr)?;
+        fs::rename(&temporary, path).map_err(ErrorKind::FilesystemErr)?;
+        Ok(())
+    }
+}
+
+/// Checks a read: the entry is in the transaction its proof leads to, and
+/// that transaction and the trusted one are in one history.
+/// Returns the state to trust from now on.
+pub fn verify_read(
+    trusted: &TrustedState,
+    entry_tx: u64,
+    entry: &EntrySpec,
+    inclusion: Option<&InclusionProof>,
+    verifiable_tx: Option<&VerifiableTx>,
+) -> Res<TrustedState> {
+    let verifiable_tx = verifiable_tx.ok_or_else(|| invalid("no transaction"))?;
+    let version = verifiable_tx
+        .tx
+        .as_ref()
+        .and_then(|x| x.header.as_ref())
+        .ok_or_else(|| invalid("no transaction header"))?
+        .version;
+    let dual = verifiable_tx
+        .dual_proof
+        .as_ref()
+        .ok_or_else(|| invalid("no dual proof"))?;
+
+    let (entries_hash, source_id, source_alh, target_id, target_alh) = if trusted.tx_id <= entry_tx
+    {
+        let target = dual
+            .target_tx_header
+            .as_ref()
+            .ok_or_else(|| invalid("no target header"))?;
+        (
+            to_digest(&target.e_h),
+            trusted.tx_id,
+            trusted.alh()?,
+            entry_tx,
+            tx_alh(target)?,
+        )
+    } else {
+        let source = dual
+            .source_tx_header
+            .as_ref()
+            .ok_or_else(|| invalid("no source header"))?;
+        (
+            to_digest(&source.e_h),
+            entry_tx,
+            tx_alh(source)?,
+            trusted.tx_id,
+            trusted.alh()?,
+        )
+    };
+
+    let inclusion = inclusion.ok_or_else(|| invalid("no inclusion proof"))?;
+    if !verify_inclusion(inclusion, &entry.digest(version)?, &entries_hash) {
+        return Err(invalid("entry isn't part of its transaction"));
+    }
+    if trusted.tx_id > 0
+        && !verify_dual_proof(dual, source_id, target_id, &source_alh, &target_alh)?
+    {
+        return Err(ErrorKind::LedgerHistoryDiverged(trusted.tx_id).into());
+    }
+    Ok(TrustedState::new(
+        trusted.db.clone(),
+        target_id,
+        &target_alh,
+    ))
+}
+
+/// Checks a write: the transaction holds the entry, and it extends the
+/// trusted history. Returns the state to trust from now on.
+pub fn verify_write(
+    trusted: &TrustedState,
+    entry: &EntrySpec,
+    verifiable_tx: &VerifiableTx,
+) -> Res<TrustedState> {
+    let tx = verifiable_tx
+        .tx
+        .as_ref()
+        .ok_or_else(|| invalid("no transaction"))?;
+    let header = tx
+        .header
+        .as_ref()
+        .ok_or_else(|| invalid("no transaction header"))?;
+
+    let digests = tx
+        .entries
+        .iter()
+        .map(|x| tx_entry_digest(header.version, x))
+        .collect::<Res<Vec<_>>>()?;
+    if !digests.contains(&entry.digest(header.version)?) {
+        return Err(invalid("entry isn't part of its transaction"));
+    }
+    if htree_root(&digests) != Some(to_digest(&header.e_h)) {
+        return Err(invalid("entries don't match the transaction"));
+    }
+
+    let target_alh = tx_alh(header)?;
+    if trusted.tx_id > 0 {
+        let dual = verifiable_tx
+            .dual_proof
+            .as_ref()
+            .ok_or_else(|| invalid("no dual proof"))?;
+        if !verify_dual_proof(dual, trusted.tx_id, header.id, &trusted.alh()?, &target_alh)? {
+            return Err(ErrorKind::LedgerHistoryDiverged(trusted.tx_id).into());
+        }
+    }
+    debug!("verified write in transaction {}", header.id);
+    Ok(TrustedState::new(
+        trusted.db.clone(),
+        header.id,
+        &target_alh,
+    ))
+}
+
+/// A key and value as they are stored in a transaction.
+pub struct EntrySpec {
+    pub key: Vec<u8>,
+    pub metadata: Option<KvMetadata>,
+    pub value: Vec<u8>,
+}
+
+impl EntrySpec {
+    /// An entry written with `Set`.
+    pub fn plain(key: &[u8], metadata: Option<KvMetadata>, value: &[u8]) -> Self {
+        Self {
+            key: [&[SET_KEY_PREFIX][..], key].concat(),
+            metadata,
+            value: [&[PLAIN_VALUE_PREFIX][..], value].concat(),
+        }
+    }
+
+    fn digest(&self, version: i32) -> Res<Digest> {
+        entry_digest(
+            version,
+            &self.key,
+            self.metadata.as_ref(),
+            &sha256(&[&self.value]),
+        )
+    }
+}
+
+fn invalid(reason: &str) -> crate::util::Er {
+    ErrorKind::LedgerProofInvalid(reason.to_string()).into()
+}
+
+fn sha256(parts: &[&[u8]]) -> Digest {
+    let mut hasher = Sha256::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().into()
+}
+
+/// Digests missing from a message are all zeros, like in the immudb client.
+fn to_digest(bytes: &[u8]) -> Digest {
+    let mut digest = [0_u8; 32];
+    digest
+        .iter_mut()
+        .zip(bytes.iter())
+        .for_each(|(x, y)| *x = *y);
+    digest
+}
+
+fn kv_metadata_bytes(metadata: &KvMetadata) -> Vec<u8> {
+    let mut bytes = vec![];
+    if metadata.deleted {
+        bytes.push(DELETED_ATTRIBUTE);
+    }
+    if let Some(expiration) = &metadata.expiration {
+        bytes.push(EXPIRES_AT_ATTRIBUTE);
+        bytes.extend(expiration.expires_at.to_be_bytes());
+    }
+    if metadata.non_indexable {
+        bytes.push(NON_INDEXABLE_ATTRIBUTE);
+    }
+    bytes
+}
+
+fn entry_digest(
+    version: i32,
+    key: &[u8],
+    metadata: Option<&KvMetadata>,
+    value_hash: &Digest,
+) -> Res<Digest> {
+    let metadata = metadata.map(kv_metadata_bytes).unwrap_or_default();
+    match version {
+        0 if metadata.is_empty() => Ok(sha256(&[key, value_hash])),
+        0 => Err(invalid("metadata in a version 0 transaction")),
+        1 => Ok(sha256(&[
+            &(metadata.len() as u16).to_be_bytes(),
+            &metadata,
+            &(key.len() as u16).to_be_bytes(),
+            key,
+            value_hash,
+        ])),
+        _ => Err(invalid("unsupported transaction version")),
+    }
+}
+
+fn tx_entry_digest(version: i32, entry: &TxEntry) -> Res<Digest> {
+    entry_digest(
+        version,
+        &entry.key,
+        entry.metadata.as_ref(),
+        &to_digest(&entry.h_value),
+    )
+}
+
+/// The accumulated linear hash of the transaction, which covers the ones
+/// before it through `prev_alh`.
+pub fn tx_alh(header: &TxHeader) -> Res<Digest> {
+    Ok(sha256(&[
+        &header.id.to_be_bytes(),
+        &to_digest(&header.prev_alh),
+        &tx_inner_hash(header)?,
+    ]))
+}
+
+fn tx_inner_hash(header: &TxHeader) -> Res<Digest> {
+    let mut bytes = vec![];
+    bytes.extend(header.ts.to_be_bytes());
+    bytes.extend((header.version as u16).to_be_bytes());
+    match header.version {
+        0 => bytes.extend((header.nentries as u16).to_be_bytes()),
+        1 => {
+            // transaction metadata has no attributes yet
+            bytes.extend(0_u16.to_be_bytes());
+            bytes.extend((header.nentries as u32).to_be_bytes());
+        }
+        _ => return Err(invalid("unsupported transaction version")),
+    }
+    bytes.extend(to_digest(&header.e_h));
+    bytes.extend(header.bl_tx_id.to_be_bytes());
+    bytes.extend(to_digest(&header.bl_root));
+    Ok(sha256(&[&bytes]))
+}
+
+fn node(left: &Digest, right: &Digest) -> Digest {
+    sha256(&[&[NODE_PREFIX], left, right])
+}
+
+fn leaf(digest: &Digest) -> Digest {
+    sha256(&[&[LEAF_PREFIX], digest])
+}
+
+/// Pairs up the nodes of a level. A node without a sibling moves up as it is.
+fn next_level(level: &[Digest]) -> Vec<Digest> {
+    level
+        .chunks(2)
+        .map(|pair| match pair {
+            [left, right] => node(left, right),
+            _ => pair.first().copied().unwrap_or_default(),
+        })
+        .collect()
+}
+
+/// Root of the tree over the entries of a transaction.
+fn htree_root(digests: &[Digest]) -> Option<Digest> {
+    let mut level: Vec<Digest> = digests.iter().map(leaf).collect();
+    while level.len() > 1 {
+        level = next_level(&level);
+    }
+    level.first().copied()
+}
+
+/// Inclusion of an entry in the tree over the entries of a transaction.
+fn verify_inclusion(proof: &InclusionProof, digest: &Digest, root: &Digest) -> bool {
+    if proof.leaf < 0 || proof.width <= proof.leaf {
+        return false;
+    }
+    let mut i = proof.leaf;
+    let mut r = proof.width - 1;
+    let mut calculated = leaf(digest);
+    for term in proof.terms.iter().map(|x| to_digest(x)) {
+        calculated = if i % 2 == 0 && i != r {
+            node(&calculated, &term)
+        } else {
+            node(&term, &calculated)
+        };
+        i /= 2;
+        r /= 2;
+    }
+    i == r && *root == calculated
+}
+
+/// Inclusion of the `i`th leaf in the append-only tree of the first `j`
+/// transactions, which transactions commit to through `bl_root`.
+fn verify_ah_inclusion(terms: &[Vec<u8>], i: u64, j: u64, leaf: &Digest, root: &Digest) -> bool {
+    if i > j || i == 0 || (i < j && terms.is_empty()) {
+        return false;
+    }
+    let (mut i1, mut j1) = (i - 1, j - 1);
+    let mut calculated = *leaf;
+    for term in terms.iter().map(|x| to_digest(x)) {
+        calculated = if i1 % 2 == 0 && i1 != j1 {
+            node(&calculated, &term)
+        } else {
+            node(&term, &calculated)
+        };
+        i1 >>= 1;
+        j1 >>= 1;
+    }
+    *root == calculated
+}
+
+fn verify_last_inclusion(terms: &[Vec<u8>], i: u64, leaf: &Digest, root: &Digest) -> bool {
+    if i == 0 {
+        return false;
+    }
+    let calculated = terms
+        .iter()
+        .map(|x| to_digest(x))
+        .fold(*leaf, |calculated, term| node(&term, &calculated));
+    *root == calculated
+}
+
+/// The append-only tree of the first `j` transactions extends the one of
+/// the first `i`.
+fn verify_consistency(terms: &[Vec<u8>], i: u64, j: u64, i_root: &Digest, j_root: &Digest) -> bool {
+    if i > j || i == 0 || (i < j && terms.is_empty()) {
+        return false;
+    }
+    let Some((first, rest)) = terms.split_first() else {
+        return i_root == j_root;
+    };
+
+    let (mut first_node, mut second_node) = (i - 1, j - 1);
+    while first_node % 2 == 1 {
+        first_node >>= 1;
+        second_node >>= 1;
+    }
+    let mut calculated_i = to_digest(first);
+    let mut calculated_j = calculated_i;
+    for term in rest.iter().map(|x| to_digest(x)) {
+        if first_node % 2 == 1 || first_node == second_node {
+            calculated_i = node(&term, &calculated_i);
+            calculated_j = node(&term, &calculated_j);
+            while first_node % 2 == 0 && first_node != 0 {
+                first_node >>= 1;
+                second_node >>= 1;
+            }
+        } else {
+            calculated_j = node(&calculated_j, &term);
+        }
+        first_node >>= 1;
+        second_node >>= 1;
+    }
+    *i_root == calculated_i && *j_root == calculated_j
+}
+
+/// Every transaction from the source to the target, chained by their alh.
+fn verify_linear_proof(
+    proof: &LinearProof,
+    source_id: u64,
+    target_id: u64,
+    source_alh: &Digest,
+    target_alh: &Digest,
+) -> bool {
+    if proof.source_tx_id != source_id
+        || proof.target_tx_id != target_id
+        || source_id == 0
+        || source_id > target_id
+        || proof.terms.len() as u64 != target_id - source_id + 1
+    {
+        return false;
+    }
+    let Some((first, rest)) = proof.terms.split_first() else {
+        return false;
+    };
+    if to_digest(first) != *source_alh {
+        return false;
+    }
+    let calculated = rest
+        .iter()
+        .zip(source_id + 1..)
+        .fold(to_digest(first), |alh, (inner_hash, id)| {
+            sha256(&[&id.to_be_bytes(), &alh, &to_digest(inner_hash)])
+        });
+    calculated == *target_alh
+}
+
+/// The source transaction is in the history of the target one.
+fn verify_dual_proof(
+    proof: &DualProof,
+    source_id: u64,
+    target_id: u64,
+    source_alh: &Digest,
+    target_alh: &Digest,
+) -> Res<bool> {
+    let (Some(source), Some(target)) = (&proof.source_tx_header, &proof.target_tx_header) else {
+        return Ok(false);
+    };
+    if source.id != source_id || target.id != target_id || source.id == 0 || source.id > target.id {
+        return Ok(false);
+    }
+    if tx_alh(source)? != *source_alh || tx_alh(target)? != *target_alh {
+        return Ok(false);
+    }
+
+    let target_bl_root = to_digest(&target.bl_root);
+    if source_id < target.bl_tx_id
+        && !verify_ah_inclusion(
+            &proof.inclusion_proof,
+            source_id,
+            target.bl_tx_id,
+            &leaf(source_alh),
+            &target_bl_root,
+        )
+    {
+        return Ok(false);
+    }
+    if source.bl_tx_id > 0
+        && !verify_consistency(
+            &proof.consistency_proof,
+            source.bl_tx_id,
+            target.bl_tx_id,
+            &to_digest(&source.bl_root),
+            &target_bl_root,
+        )
+    {
+        return Ok(false);
+    }
+    let target_bl_tx_alh = to_digest(&proof.target_bl_tx_alh);
+    if target.bl_tx_id > 0
+        && !verify_last_inclusion(
+            &proof.last_inclusion_proof,
+            target.bl_tx_id,
+            &leaf(&target_bl_tx_alh),
+            &target_bl_root,
+        )
+    {
+        return Ok(false);
+    }
+
+    let Some(linear) = &proof.linear_proof else {
+        return Ok(false);
+    };
+    Ok(if source_id < target.bl_tx_id {
+        verify_linear_proof(
+            linear,
+            target.bl_tx_id,
+            target_id,
+            &target_bl_tx_alh,
+            target_alh,
+        )
+    } else {
+        verify_linear_proof(linear, source_id, target_id, source_alh, target_alh)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::grpc::immudb_grpc::Tx;
+
+    fn digest(x: u8) -> Digest {
+        sha256(&[&[x]])
+    }
+
+    /// Siblings on the way from the leaf to the root, skipping the levels
+    /// where the node moves up without one.
+    fn inclusion_terms(leaves: &[Digest], mut index: usize) -> Vec<Vec<u8>> {
+        let mut level = leaves.to_vec();
+        let mut terms = vec![];
+        while level.len() > 1 {
+            let sibling = if index % 2 == 0 { index + 1 } else { index - 1 };
+            if let Some(x) = level.get(sibling) {
+                terms.push(x.to_vec());
+            }
+            level = next_level(&level);
+            index /= 2;
+        }
+        terms
+    }
+
+    fn header(id: u64, prev_alh: &Digest) -> TxHeader {
+        TxHeader {
+            id,
+            prev_alh: prev_alh.to_vec(),
+            ts: 1700000000 + id as i64,
+            nentries: 1,
+            e_h: digest(id as u8).to_vec(),
+            bl_tx_id: 0,
+            bl_root: vec![],
+            version: 1,
+            metadata: None,
+        }
+    }
+
+    /// A chain of `n` transactions with their alh.
+    fn chain(n: u64) -> Vec<(TxHeader, Digest)> {
+        let mut alh = [0_u8; 32];
+        (1..=n)
+            .map(|id| {
+                let header = header(id, &alh);
+                alh = tx_alh(&header).unwrap();
+                (header, alh)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn entry_inclusion() {
+        let digests: Vec<Digest> = (0..5).map(digest).collect();
+        let root = htree_root(&digests).unwrap();
+        let leaves: Vec<Digest> = digests.iter().map(leaf).collect();
+        for (index, entry) in digests.iter().enumerate() {
+            let proof = InclusionProof {
+                leaf: index as i32,
+                width: digests.len() as i32,
+                terms: inclusion_terms(&leaves, index),
+            };
+            assert!(verify_inclusion(&proof, entry, &root));
+            assert!(!verify_inclusion(&proof, &digest(9), &root));
+        }
+    }
+
+    #[test]
+    fn transaction_inclusion() {
+        let alhs: Vec<Digest> = (0..7).map(digest).collect();
+        let leaves: Vec<Digest> = alhs.iter().map(leaf).collect();
+        let root = htree_root(&alhs).unwrap();
+        for (index, alh) in alhs.iter().enumerate() {
+            let terms = inclusion_terms(&leaves, index);
+            let i = index as u64 + 1;
+            assert!(verify_ah_inclusion(&terms, i, 7, &leaf(alh), &root));
+            assert!(!verify_ah_inclusion(&terms, i, 7, &leaf(&digest(9)), &root));
+        }
+        let last = alhs.last().unwrap();
+        let terms = inclusion_terms(&leaves, 6);
+        assert!(verify_last_inclusion(&terms, 7, &leaf(last), &root));
+    }
+
+    #[test]
+    fn linear_proof() {
+        let chain = chain(5);
+        let (_, source_alh) = chain.get(1).unwrap();
+        let (_, target_alh) = chain.last().unwrap();
+        let terms: Vec<Vec<u8>> = std::iter::once(source_alh.to_vec())
+            .chain(
+                chain
+                    .iter()
+                    .skip(2)
+                    .map(|(header, _)| tx_inner_hash(header).unwrap().to_vec()),
+            )
+            .collect();
+        let proof = LinearProof {
+            source_tx_id: 2,
+            target_tx_id: 5,
+            terms,
+        };
+        assert!(verify_linear_proof(&proof, 2, 5, source_alh, target_alh));
+        assert!(!verify_linear_proof(&proof, 2, 5, source_alh, &digest(1)));
+
+        // a rewritten transaction in between breaks the chain
+        let mut forged = proof.clone();
+        if let Some(term) = forged.terms.get_mut(2) {
+            *term = digest(1).to_vec();
+        }
+        assert!(!verify_linear_proof(&forged, 2, 5, source_alh, target_alh));
+    }
+
+    #[test]
+    fn dual_proof_without_binary_links() {
+        let chain = chain(4);
+        let (source, source_alh) = chain.first().unwrap().clone();
+        let (target, target_alh) = chain.last().unwrap().clone();
+        let proof = DualProof {
+            source_tx_header: Some(source),
+            target_tx_header: Some(target.clone()),
+            inclusion_proof: vec![],
+            consistency_proof: vec![],
+            target_bl_tx_alh: vec![],
+            last_inclusion_proof: vec![],
+            linear_proof: Some(LinearProof {
+                source_tx_id: 1,
+                target_tx_id: 4,
+                terms: std::iter::once(source_alh.to_vec())
+                    .chain(
+                        chain
+                            .iter()
+                            .skip(1)
+                            .map(|(header, _)| tx_inner_hash(header).unwrap().to_vec()),
+                    )
+                    .collect(),
+            }),
+        };
+        assert!(verify_dual_proof(&proof, 1, 4, &source_alh, &target_alh).unwrap());
+
+        // a server which rewrote the target transaction can't prove it
+        let mut rewritten = target;
+        rewritten.ts += 1;
+        let rewritten_alh = tx_alh(&rewritten).unwrap();
+        let forged = DualProof {
+            target_tx_header: Some(rewritten),
+            ..proof
+        };
+        assert!(!verify_dual_proof(&forged, 1, 4, &source_alh, &rewritten_alh).unwrap());
+    }
+
+    #[test]
+    fn write_must_extend_the_trusted_state() {
+        let (first, first_alh) = chain(1).first().unwrap().clone();
+        let trusted = TrustedState::new("kiss".to_string(), 1, &first_alh);
+
+        let entry = EntrySpec::plain(b"key", None, b"value");
+        let mut header = header(2, &first_alh);
+        let tx_entry = TxEntry {
+            key: entry.key.clone(),
+            h_value: sha256(&[&entry.value]).to_vec(),
+            v_len: entry.value.len() as i32,
+            metadata: None,
+            value: vec![],
+        };
+        header.e_h = htree_root(&[tx_entry_digest(1, &tx_entry).unwrap()])
+            .unwrap()
+            .to_vec();
+        let alh = tx_alh(&header).unwrap();
+        let verifiable_tx = VerifiableTx {
+            tx: Some(Tx {
+                header: Some(header.clone()),
+                entries: vec![tx_entry],
+                kv_entries: vec![],
+                z_entries: vec![],
+            }),
+            dual_proof: Some(DualProof {
+                source_tx_header: Some(first),
+                target_tx_header: Some(header.clone()),
+                inclusion_proof: vec![],
+                consistency_proof: vec![],
+                target_bl_tx_alh: vec![],
+                last_inclusion_proof: vec![],
+                linear_proof: Some(LinearProof {
+                    source_tx_id: 1,
+                    target_tx_id: 2,
+                    terms: vec![first_alh.to_vec(), tx_inner_hash(&header).unwrap().to_vec()],
+                }),
+            }),
+            signature: None,
+        };
+
+        let next = verify_write(&trusted, &entry, &verifiable_tx).unwrap();
+        assert_eq!(next, TrustedState::new("kiss".to_string(), 2, &alh));
+
+        let other = EntrySpec::plain(b"key", None, b"other value");
+        assert!(verify_write(&trusted, &other, &verifiable_tx).is_err());
+
+        // a server which forgot the trusted transaction can't extend it
+        let diverged = TrustedState::new("kiss".to_string(), 1, &digest(1));
+        assert!(verify_write(&diverged, &entry, &verifiable_tx).is_err());
+    }
+
+    #[test]
+    fn trusted_state_is_persisted() {
+        let path = std::env::temp_dir().join(format!("kiss-{}.state", uuid::Uuid::new_v4()));
+        assert_eq!(TrustedState::load(&path).unwrap(), None);
+        let state = TrustedState::new("kiss".to_string(), 3, &digest(3));
+        state.save(&path).unwrap();
+        assert_eq!(TrustedState::load(&path).unwrap(), Some(state));
+        fs::remove_file(path).unwrap();
+    }
+}
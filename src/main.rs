@@ -16,13 +16,17 @@ mod grpc;
 mod ledger;
 mod malice;
 mod p2p;
+mod repair;
+mod reputation;
 mod settings;
 mod storage;
+#[cfg(test)]
+mod testing;
 mod types;
 mod util;
 mod verifier;
 
-use crate::ledger::{ILedger, ImmuLedger};
+use crate::ledger::ILedger;
 use crate::settings::ISettings;
 use crate::util::{Er, ErrorKind};
 use base64::Engine as _;
@@ -34,6 +38,7 @@ use log::{debug, info, warn};
 use malice::IMalice;
 use p2p::swarm::ISwarm;
 use runtime_injector::Svc;
+use storage::sweeper::ISweeper;
 use time::OffsetDateTime;
 use tokio::{sync::Mutex, try_join};
 use util::{die, Res};
@@ -54,8 +59,9 @@ async fn run() -> Res<()> {
     let kad: Svc<dyn ISwarm> = injector.get()?;
     let verifier: Svc<dyn IVerifier> = injector.get()?;
     let malice: Svc<Box<dyn IMalice>> = injector.get()?;
+    let sweeper: Svc<dyn ISweeper> = injector.get()?;
     let settings: Svc<dyn ISettings> = injector.get()?;
-    let ledger = injector.get::<Svc<Mutex<ImmuLedger>>>()?;
+    let ledger = injector.get::<Svc<Mutex<Box<dyn ILedger>>>>()?;
 
     if settings.verifier().enabled {
         try_join!(
@@ -63,6 +69,7 @@ async fn run() -> Res<()> {
             kad.start(),
             verifier.start(),
             malice.start(),
+            sweeper.start(),
             start(ledger, settings),
         )
         .map(|_| ())
@@ -71,13 +78,14 @@ async fn run() -> Res<()> {
             grpc_handler.start(),
             kad.start(),
             malice.start(),
+            sweeper.start(),
             start(ledger, settings),
         )
         .map(|_| ())
     }
 }
 
-async fn start(ledger: Svc<Mutex<ImmuLedger>>, settings: Svc<dyn ISettings>) -> Res<()> {
+async fn start(ledger: Svc<Mutex<Box<dyn ILedger>>>, settings: Svc<dyn ISettings>) -> Res<()> {
     let local_key = match settings.swarm().keypair {
         Some(keypair) => Keypair::from_protobuf_encoding(
             &base64::engine::general_purpose::STANDARD_NO_PAD
@@ -0,0 +1,182 @@
+use async_trait::async_trait;
+use futures::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use libp2p::request_response::Codec;
+use libp2p::StreamProtocol;
+use serde::{de::DeserializeOwned, Serialize};
+use std::{io, marker::PhantomData};
+
+/// Request-response codec encoding messages with bincode, with explicit
+/// limits on their size. The cbor codec of libp2p caps responses at 10 MiB,
+/// too little for the records fetched whole from their holders.
+pub struct BincodeCodec<Req, Resp> {
+    max_request_size: usize,
+    max_response_size: usize,
+    phantom: PhantomData<fn() -> (Req, Resp)>,
+}
+
+impl<Req, Resp> BincodeCodec<Req, Resp> {
+    pub fn new(max_request_size: usize, max_response_size: usize) -> Self {
+        Self {
+            max_request_size,
+            max_response_size,
+            phantom: PhantomData,
+        }
+    }
+}
+
+impl<Req, Resp> Clone for BincodeCodec<Req, Resp> {
+    fn clone(&self) -> Self {
+        Self::new(self.max_request_size, self.max_response_size)
+    }
+}
+
+/// Reads a whole message, refusing ones larger than `max_size` bytes.
+async fn read_message<T, M>(io: &mut T, max_size: usize) -> io::Result<M>
+where
+    T: AsyncRead + Unpin + Send,
+    M: DeserializeOwned,
+{
+    let mut message = Vec::new();
+    // one byte more than allowed tells an oversized message apart
+    io.take(max_size as u64 + 1)
+        .read_to_end(&mut message)
+        .await?;
+    if message.len() > max_size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("message is larger than {} bytes", max_size),
+        ));
+    }
+    bincode::deserialize(&message).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+async fn write_message<T, M>(io: &mut T, message: &M, max_size: usize) -> io::Result<()>
+where
+    T: AsyncWrite + Unpin + Send,
+    M: Serialize,
+{
+    let message =
+        bincode::serialize(message).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    if message.len() > max_size {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("message is larger than {} bytes", max_size),
+        ));
+    }
+    io.write_all(&message).await
+}
+
+#[async_trait]
+impl<Req, Resp> Codec for BincodeCodec<Req, Resp>
+where
+    Req: Send + Serialize + DeserializeOwned,
+    Resp: Send + Serialize + DeserializeOwned,
+{
+    type Protocol = StreamProtocol;
+    type Request = Req;
+    type Response = Resp;
+
+    async fn read_request<T>(&mut self, _: &Self::Protocol, io: &mut T) -> io::Result<Req>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        read_message(io, self.max_request_size).await
+    }
+
+    async fn read_response<T>(&mut self, _: &Self::Protocol, io: &mut T) -> io::Result<Resp>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        read_message(io, self.max_response_size).await
+    }
+
+    async fn write_request<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        request: Req,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_message(io, &request, self.max_request_size).await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &Self::Protocol,
+        io: &mut T,
+        response: Resp,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_message(io, &response, self.max_response_size).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::p2p::swarm::{RecordRequest, RecordResponse};
+    use crate::util::consts::{MAX_FILE_SIZE, MAX_RECORD_RESPONSE_SIZE};
+    use futures::io::Cursor;
+
+    fn protocol() -> StreamProtocol {
+        StreamProtocol::new("/record/1.0.0")
+    }
+
+    fn response(value: Vec<u8>) -> RecordResponse {
+        RecordResponse {
+            file_name: "a2c4e9d0-2b6f-4a53-9c1e-7f3d5b8a6e21.shard.0".to_string(),
+            succeeded: true,
+            value: Some(value),
+        }
+    }
+
+    #[tokio::test]
+    async fn responses_are_read_back() {
+        let mut codec = BincodeCodec::<RecordRequest, RecordResponse>::new(64, 1024);
+        let mut io = Cursor::new(Vec::new());
+        codec
+            .write_response(&protocol(), &mut io, response(vec![7; 512]))
+            .await
+            .unwrap();
+
+        io.set_position(0);
+        let read = codec.read_response(&protocol(), &mut io).await.unwrap();
+        assert_eq!(read.value, Some(vec![7; 512]));
+    }
+
+    #[tokio::test]
+    async fn oversized_responses_are_refused() {
+        let mut large = BincodeCodec::<RecordRequest, RecordResponse>::new(64, 1024);
+        let mut io = Cursor::new(Vec::new());
+        large
+            .write_response(&protocol(), &mut io, response(vec![7; 512]))
+            .await
+            .unwrap();
+
+        io.set_position(0);
+        let mut small = BincodeCodec::<RecordRequest, RecordResponse>::new(64, 256);
+        let error = small.read_response(&protocol(), &mut io).await.unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        let error = small
+            .write_response(
+                &protocol(),
+                &mut Cursor::new(Vec::new()),
+                response(vec![7; 512]),
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidInput);
+    }
+
+    #[test]
+    fn the_largest_file_fits_in_a_response() {
+        // bincode writes the value as its length and its bytes, so a
+        // response grows byte for byte with the file it carries
+        let framing = bincode::serialized_size(&response(vec![])).unwrap() as usize;
+        assert!(framing + MAX_FILE_SIZE <= MAX_RECORD_RESPONSE_SIZE);
+    }
+}
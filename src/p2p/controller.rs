@@ -1,8 +1,9 @@
 use std::collections::HashSet;
+use std::time::Instant;
 
-use crate::p2p::swarm::{QueryGetResponse, VerificationResponse};
+use crate::p2p::swarm::{QueryGetResponse, RecordResponse, VerificationResponse};
 use crate::util::types::{Bytes, OneReceiver};
-use crate::util::{types::CommandToSwarm, Res};
+use crate::util::{types::CommandToSwarm, ErrorKind, Res};
 use async_trait::async_trait;
 use libp2p_identity::PeerId;
 use log::{debug, info};
@@ -36,8 +37,14 @@ impl ServiceFactory<()> for SwarmControllerProvider {
 
 #[async_trait]
 pub trait ISwarmController: Service {
-    async fn put(&self, key: String, value: Bytes) -> Res<()>;
-    async fn put_to(&self, key: String, value: Bytes, peers: Vec<PeerId>) -> Res<()>;
+    async fn put(&self, key: String, value: Bytes, expires: Option<Instant>) -> Res<()>;
+    async fn put_to(
+        &self,
+        key: String,
+        value: Bytes,
+        expires: Option<Instant>,
+        peers: Vec<PeerId>,
+    ) -> Res<()>;
     async fn get(&self, key: String) -> Res<QueryGetResponse>;
     async fn get_providers(&self, key: String) -> Res<HashSet<PeerId>>;
     async fn get_closest_peers(&self, key: Uuid) -> Res<Vec<PeerId>>;
@@ -48,6 +55,9 @@ pub trait ISwarmController: Service {
         file_uuid: String,
         secret_vector: Vec<u64>,
     ) -> Res<Vec<u64>>;
+    async fn request_removal(&self, peer: PeerId, file_uuid: String) -> Res<bool>;
+    async fn request_fetch(&self, peer: PeerId, file_uuid: String) -> Res<Bytes>;
+    async fn request_renewal(&self, peer: PeerId, file_uuid: String, expires_at: u64) -> Res<bool>;
 }
 
 pub struct SwarmController {
@@ -56,7 +66,7 @@ pub struct SwarmController {
 
 #[async_trait]
 impl ISwarmController for SwarmController {
-    async fn put(&self, key: String, value: Bytes) -> Res<()> {
+    async fn put(&self, key: String, value: Bytes, expires: Option<Instant>) -> Res<()> {
         let (sender, receiver) = oneshot::channel::<OneReceiver<Res<()>>>();
 
         self.commands_to_swarm
@@ -65,6 +75,7 @@ impl ISwarmController for SwarmController {
             .send(CommandToSwarm::PutLocal {
                 key,
                 value,
+                expires,
                 resp: sender,
             })
             .await?;
@@ -75,7 +86,13 @@ impl ISwarmController for SwarmController {
         result
     }
 
-    async fn put_to(&self, key: String, value: Bytes, peer_ids: Vec<PeerId>) -> Res<()> {
+    async fn put_to(
+        &self,
+        key: String,
+        value: Bytes,
+        expires: Option<Instant>,
+        peer_ids: Vec<PeerId>,
+    ) -> Res<()> {
         let (sender, receiver) = oneshot::channel::<OneReceiver<Res<()>>>();
 
         self.commands_to_swarm
@@ -84,6 +101,7 @@ impl ISwarmController for SwarmController {
             .send(CommandToSwarm::PutRemote {
                 key,
                 value,
+                expires,
                 resp: sender,
                 remotes: peer_ids,
             })
@@ -170,4 +188,61 @@ impl ISwarmController for SwarmController {
         debug!("request verification result: {:?}", result);
         Ok(result?.response_vector)
     }
+
+    async fn request_removal(&self, peer: PeerId, file_uuid: String) -> Res<bool> {
+        let (sender, receiver) = oneshot::channel::<OneReceiver<Res<RecordResponse>>>();
+        self.commands_to_swarm
+            .lock()
+            .await
+            .send(CommandToSwarm::RequestRemoval {
+                peer,
+                file_uuid,
+                resp: sender,
+            })
+            .await?;
+        let receiving_channel = receiver.await?;
+        let result = receiving_channel.await?;
+        debug!("request removal result: {:?}", result);
+        Ok(result?.succeeded)
+    }
+
+    async fn request_renewal(&self, peer: PeerId, file_uuid: String, expires_at: u64) -> Res<bool> {
+        let (sender, receiver) = oneshot::channel::<OneReceiver<Res<RecordResponse>>>();
+        self.commands_to_swarm
+            .lock()
+            .await
+            .send(CommandToSwarm::RequestRenewal {
+                peer,
+                file_uuid,
+                expires_at,
+                resp: sender,
+            })
+            .await?;
+        let receiving_channel = receiver.await?;
+        let result = receiving_channel.await?;
+        debug!("request renewal result: {:?}", result);
+        Ok(result?.succeeded)
+    }
+
+    async fn request_fetch(&self, peer: PeerId, file_uuid: String) -> Res<Bytes> {
+        let (sender, receiver) = oneshot::channel::<OneReceiver<Res<RecordResponse>>>();
+        self.commands_to_swarm
+            .lock()
+            .await
+            .send(CommandToSwarm::RequestFetch {
+                peer,
+                file_uuid,
+                resp: sender,
+            })
+            .await?;
+        let receiving_channel = receiver.await?;
+        let result = receiving_channel.await?;
+        debug!(
+            "request fetch result: {:?}",
+            result.as_ref().map(|x| x.succeeded)
+        );
+        result?
+            .value
+            .ok_or_else(|| ErrorKind::RecordFetchFailed(peer.to_string()).into())
+    }
 }
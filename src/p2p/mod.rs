@@ -7,6 +7,7 @@ use runtime_injector::{constant, define_module, IntoSingleton};
 
 use tokio::sync::{mpsc, Mutex};
 
+mod codec;
 pub mod controller;
 mod memorystore;
 pub mod peer_id;
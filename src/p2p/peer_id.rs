@@ -1,5 +1,5 @@
 use base64::Engine;
-use libp2p_identity::{ed25519, Keypair};
+use libp2p_identity::{ed25519, Keypair, PeerId, PublicKey};
 use log::debug;
 
 use crate::util::Res;
@@ -34,6 +34,28 @@ pub fn keypair_with_leading_zeros(leading_zeros: usize) -> Keypair {
     keypair
 }
 
+/// Checks that the peer id was derived from an ed25519 key whose hash has
+/// at least `leading_zeros` leading zeros, like the ones
+/// generated by `keypair_with_leading_zeros`.
+pub fn peer_id_has_leading_zeros(peer_id: &PeerId, leading_zeros: usize) -> bool {
+    // ed25519 keys are short enough to be inlined in the peer id
+    // as an identity multihash: 0x00, the length, then the encoded key
+    let bytes = peer_id.to_bytes();
+    let encoded_key = match bytes.as_slice() {
+        [0x00, length, encoded_key @ ..] if usize::from(*length) == encoded_key.len() => {
+            encoded_key
+        }
+        _ => return false,
+    };
+    match PublicKey::try_decode_protobuf(encoded_key).map(PublicKey::try_into_ed25519) {
+        Ok(Ok(public)) => crate::util::hasher::hash(public.to_bytes().as_slice())
+            .chars()
+            .take(leading_zeros)
+            .all(|c| c == '0'),
+        _ => false,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -72,6 +94,41 @@ mod tests {
         assert!(hashed.chars().take(2).all(|c| c == '0'));
     }
 
+    #[test]
+    fn peer_id_with_leading_zeros() {
+        let keypair = keypair_with_leading_zeros(2);
+        let peer_id = PeerId::from(keypair.public());
+        assert!(peer_id_has_leading_zeros(&peer_id, 2));
+        assert!(peer_id_has_leading_zeros(&peer_id, 0));
+    }
+
+    #[test]
+    fn peer_id_without_leading_zeros() {
+        let keypair = loop {
+            let keypair = Keypair::generate_ed25519();
+            let hashed = hash(
+                keypair
+                    .public()
+                    .try_into_ed25519()
+                    .unwrap()
+                    .to_bytes()
+                    .as_slice(),
+            );
+            if !hashed.starts_with('0') {
+                break keypair;
+            }
+        };
+        let peer_id = PeerId::from(keypair.public());
+        assert!(!peer_id_has_leading_zeros(&peer_id, 1));
+        assert!(peer_id_has_leading_zeros(&peer_id, 0));
+    }
+
+    #[test]
+    fn random_peer_id_fails_leading_zeros() {
+        // not backed by a public key
+        assert!(!peer_id_has_leading_zeros(&PeerId::random(), 1));
+    }
+
     #[bench]
     fn bench_peer_id_1_leading_zero(b: &mut Bencher) {
         b.iter(|| {
@@ -1,35 +1,45 @@
-use crate::util::{ErrorKind, Res};
+use crate::util::{consts, ErrorKind, Res};
 use futures::executor::block_on;
 use libp2p::kad::record::Key;
 use libp2p::kad::store::{Error, RecordStore, Result};
 use libp2p::kad::{KBucketKey, ProviderRecord, Record, K_VALUE};
+use libp2p::Multiaddr;
 use libp2p_identity::PeerId;
-use log::debug;
+use log::{debug, warn};
 use object_store::path::Path;
 use runtime_injector::Svc;
+use serde::{Deserialize, Serialize};
 use smallvec::SmallVec;
 use std::borrow::Cow;
 use std::collections::{hash_map, hash_set, HashMap, HashSet};
 use std::path::PathBuf;
-use std::{iter, str};
+use std::str::FromStr;
+use std::time::Instant;
+use std::{fs, iter, str, vec};
 use tokio::runtime::Handle;
 
+use crate::storage::local::{instant_to_unix_secs, unix_secs_to_instant};
 use crate::storage::IStorage;
 
 /// Local implementation of a `RecordStore`.
 pub struct LocalStore {
     /// The identity of the peer owning the store.
-    _local_key: KBucketKey<PeerId>,
+    local_key: KBucketKey<PeerId>,
     /// The configuration of the store.
     config: LocalStoreConfig,
     /// The stored (regular) records.
     storage: Svc<dyn IStorage>,
     /// The stored provider records.
-    _providers: HashMap<Key, SmallVec<[ProviderRecord; K_VALUE.get()]>>,
+    providers: HashMap<Key, SmallVec<[ProviderRecord; K_VALUE.get()]>>,
     /// The set of all provider records for the node identified by `local_key`.
     ///
     /// Must be kept in sync with `providers`.
-    _provided: HashSet<ProviderRecord>,
+    provided: HashSet<ProviderRecord>,
+    /// Whether the provider records changed since they were last persisted.
+    providers_dirty: bool,
+    /// The records the local node holds active contracts for, as of the
+    /// last `set_held`. Only these are replicated.
+    held: HashSet<Key>,
 }
 
 /// Configuration for a `LocalStore`.
@@ -46,6 +56,10 @@ pub struct LocalStoreConfig {
     /// The maximum number of provider records for which the
     /// local node is the provider.
     pub max_provided_keys: usize,
+    /// The file the provider records are persisted to.
+    ///
+    /// Provider records are only kept in memory when it is not set.
+    pub providers_path: Option<PathBuf>,
 }
 
 impl Default for LocalStoreConfig {
@@ -55,6 +69,7 @@ impl Default for LocalStoreConfig {
             max_value_bytes: 1024 * 1024 * 1024,
             max_provided_keys: 1024,
             max_providers_per_key: K_VALUE.get(),
+            providers_path: None,
         }
     }
 }
@@ -66,13 +81,222 @@ impl LocalStore {
         config: LocalStoreConfig,
         storage: Svc<dyn IStorage>,
     ) -> Self {
-        LocalStore {
-            _local_key: KBucketKey::from(local_id),
+        let mut store = LocalStore {
+            local_key: KBucketKey::from(local_id),
             config,
             storage,
-            _provided: HashSet::default(),
-            _providers: HashMap::default(),
+            provided: HashSet::default(),
+            providers: HashMap::default(),
+            providers_dirty: false,
+            held: HashSet::default(),
+        };
+        store.load_providers();
+        store
+    }
+
+    /// Restores the provider records persisted by a previous run,
+    /// dropping the ones which expired in the meantime.
+    fn load_providers(&mut self) {
+        let Some(path) = self.config.providers_path.as_ref() else {
+            return;
+        };
+        if !path.exists() {
+            return;
+        }
+        let persisted = match fs::read(path)
+            .map_err(ErrorKind::FilesystemErr)
+            .and_then(|bytes| {
+                serde_yaml::from_slice::<Vec<PersistedProvider>>(&bytes)
+                    .map_err(ErrorKind::StorageGetSerdeError)
+            }) {
+            Ok(persisted) => persisted,
+            Err(e) => {
+                warn!("failed to load provider records from {:?}: {}", path, e);
+                return;
+            }
+        };
+
+        let now = Instant::now();
+        for record in persisted
+            .into_iter()
+            .filter_map(PersistedProvider::into_record)
+            .filter(|record| !record.is_expired(now))
+        {
+            if let Err(e) = self.insert_provider(record) {
+                warn!("dropping persisted provider record: {:?}", e);
+            }
+        }
+        debug!("loaded provider records for {} keys", self.providers.len());
+    }
+
+    /// Writes the provider records to `providers_path` if they changed
+    /// since the last write. The swarm calls it periodically, so a burst of
+    /// provider updates results in a single write.
+    pub fn persist_providers(&mut self) {
+        if !self.providers_dirty {
+            return;
+        }
+        self.providers_dirty = false;
+        let Some(path) = self.config.providers_path.as_ref() else {
+            return;
+        };
+        let persisted: Vec<PersistedProvider> = self
+            .providers
+            .values()
+            .flatten()
+            .map(PersistedProvider::from)
+            .collect();
+        let result = serde_yaml::to_string(&persisted)
+            .map_err(ErrorKind::StoragePutSerdeError)
+            .and_then(|serialized| fs::write(path, serialized).map_err(ErrorKind::FilesystemErr));
+        if let Err(e) = result {
+            warn!("failed to persist provider records to {:?}: {}", path, e);
+        }
+    }
+
+    /// Replaces the records the local node holds active contracts for.
+    pub fn set_held(&mut self, held: HashSet<Key>) {
+        self.held = held;
+    }
+
+    /// Removes the expired provider records.
+    /// Returns whether anything was removed.
+    fn prune_expired_providers(&mut self) -> bool {
+        let now = Instant::now();
+        let mut pruned = false;
+        self.providers.retain(|_, providers| {
+            let before = providers.len();
+            providers.retain(|p| !p.is_expired(now));
+            pruned |= providers.len() != before;
+            !providers.is_empty()
+        });
+        self.provided.retain(|p| !p.is_expired(now));
+        pruned
+    }
+
+    fn insert_provider(&mut self, record: ProviderRecord) -> Result<()> {
+        let num_keys = self.providers.len();
+
+        // Obtain the entry
+        let providers = match self.providers.entry(record.key.clone()) {
+            e @ hash_map::Entry::Occupied(_) => e,
+            e @ hash_map::Entry::Vacant(_) => {
+                if self.config.max_provided_keys == num_keys {
+                    return Err(Error::MaxProvidedKeys);
+                }
+                e
+            }
         }
+        .or_insert_with(Default::default);
+
+        if let Some(existing) = providers.iter_mut().find(|p| p.provider == record.provider) {
+            // In-place update of an existing provider record.
+            if self.local_key.preimage() == &record.provider {
+                self.provided.remove(&*existing);
+                self.provided.insert(record.clone());
+            }
+            *existing = record;
+        } else {
+            // It is a new provider record for that key.
+            let local_key = self.local_key.clone();
+            let key = KBucketKey::new(record.key.clone());
+            let provider = KBucketKey::from(record.provider);
+            if let Some(i) = providers.iter().position(|p| {
+                let pk = KBucketKey::from(p.provider);
+                provider.distance(&key) < pk.distance(&key)
+            }) {
+                // Insert the new provider.
+                if local_key.preimage() == &record.provider {
+                    self.provided.insert(record.clone());
+                }
+                providers.insert(i, record);
+                // Remove the excess provider, if any.
+                if providers.len() > self.config.max_providers_per_key {
+                    if let Some(p) = providers.pop() {
+                        self.provided.remove(&p);
+                    }
+                }
+            } else if providers.len() < self.config.max_providers_per_key {
+                // The distance of the new provider to the key is larger than
+                // the distance of any existing provider, but there is still room.
+                if local_key.preimage() == &record.provider {
+                    self.provided.insert(record.clone());
+                }
+                providers.push(record);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for LocalStore {
+    fn drop(&mut self) {
+        self.persist_providers();
+    }
+}
+
+/// The on-disk form of a `ProviderRecord`.
+#[derive(Serialize, Deserialize)]
+struct PersistedProvider {
+    key: Vec<u8>,
+    provider: String,
+    /// Unix timestamp in seconds.
+    expires_at: Option<u64>,
+    addresses: Vec<String>,
+}
+
+impl From<&ProviderRecord> for PersistedProvider {
+    fn from(record: &ProviderRecord) -> Self {
+        PersistedProvider {
+            key: record.key.to_vec(),
+            provider: record.provider.to_base58(),
+            expires_at: record.expires.map(instant_to_unix_secs),
+            addresses: record.addresses.iter().map(|x| x.to_string()).collect(),
+        }
+    }
+}
+
+impl PersistedProvider {
+    fn into_record(self) -> Option<ProviderRecord> {
+        let provider = PeerId::from_str(&self.provider).ok()?;
+        Some(ProviderRecord {
+            key: Key::from(self.key),
+            provider,
+            expires: self.expires_at.map(unix_secs_to_instant),
+            addresses: self
+                .addresses
+                .iter()
+                .filter_map(|x| Multiaddr::from_str(x).ok())
+                .collect(),
+        })
+    }
+}
+
+/// Reads the listed records one at a time.
+pub struct LocalRecords {
+    storage: Svc<dyn IStorage>,
+    paths: vec::IntoIter<Path>,
+}
+
+impl Iterator for LocalRecords {
+    type Item = Record;
+
+    fn next(&mut self) -> Option<Record> {
+        let handle = Handle::current();
+        for path in self.paths.by_ref() {
+            let records = self.storage.clone();
+            let read = path.clone();
+            match block_on(async {
+                handle
+                    .spawn(async move { records.get(PathBuf::from(read.as_ref())).await })
+                    .await
+            }) {
+                Ok(Ok(record)) => return Some(record),
+                Ok(Err(e)) => debug!("skipping unreadable record {}: {}", path, e),
+                Err(e) => debug!("skipping unreadable record {}: {}", path, e),
+            }
+        }
+        None
     }
 }
 
@@ -83,8 +307,7 @@ fn key_to_path(key: &Key) -> Res<PathBuf> {
 }
 
 impl RecordStore for LocalStore {
-    type RecordsIter<'a> =
-        iter::Map<hash_map::Values<'a, Key, Record>, fn(&'a Record) -> Cow<'a, Record>>;
+    type RecordsIter<'a> = iter::Map<LocalRecords, fn(Record) -> Cow<'a, Record>>;
 
     type ProvidedIter<'a> = iter::Map<
         hash_set::Iter<'a, ProviderRecord>,
@@ -139,23 +362,83 @@ impl RecordStore for LocalStore {
         }
     }
 
+    /// Lists the records small enough to be replicated by kademlia which
+    /// the local node holds active contracts for, so deleted and renewed
+    /// records aren't pushed back as they were. Their values are only read
+    /// as the iterator reaches them.
     fn records(&self) -> Self::RecordsIter<'_> {
-        todo!();
+        let handle = Handle::current();
+        let records = self.storage.clone();
+        let held = self.held.clone();
+        let result = block_on(async {
+            handle
+                .spawn(async move {
+                    let mut result = vec![];
+                    for path in records.list().await? {
+                        if !held.contains(&Key::new(&path.as_ref())) {
+                            continue;
+                        }
+                        match records.size(&path).await {
+                            Ok(size) if size <= consts::MAX_REPLICATED_RECORD_SIZE => {
+                                result.push(path)
+                            }
+                            Ok(_) => {}
+                            Err(e) => debug!("skipping unreadable record {}: {}", path, e),
+                        }
+                    }
+                    Res::Ok(result)
+                })
+                .await
+        });
+        let paths = match result {
+            Ok(Ok(paths)) => paths,
+            Ok(Err(e)) => {
+                warn!("failed to list records: {}", e);
+                vec![]
+            }
+            Err(e) => {
+                warn!("failed to list records: {}", e);
+                vec![]
+            }
+        };
+        LocalRecords {
+            storage: self.storage.clone(),
+            paths: paths.into_iter(),
+        }
+        .map(Cow::Owned)
     }
 
-    fn add_provider(&mut self, _record: ProviderRecord) -> Result<()> {
-        todo!();
+    fn add_provider(&mut self, record: ProviderRecord) -> Result<()> {
+        self.prune_expired_providers();
+        self.insert_provider(record)?;
+        self.providers_dirty = true;
+        Ok(())
     }
 
-    fn providers(&self, _key: &Key) -> Vec<ProviderRecord> {
-        todo!();
+    fn providers(&self, key: &Key) -> Vec<ProviderRecord> {
+        let now = Instant::now();
+        self.providers.get(key).map_or_else(Vec::new, |ps| {
+            ps.iter().filter(|p| !p.is_expired(now)).cloned().collect()
+        })
     }
 
     fn provided(&self) -> Self::ProvidedIter<'_> {
-        todo!();
+        self.provided.iter().map(Cow::Borrowed)
     }
 
-    fn remove_provider(&mut self, _key: &Key, _provider: &PeerId) {
-        todo!();
+    fn remove_provider(&mut self, key: &Key, provider: &PeerId) {
+        let mut removed = self.prune_expired_providers();
+        if let hash_map::Entry::Occupied(mut e) = self.providers.entry(key.clone()) {
+            let providers = e.get_mut();
+            if let Some(i) = providers.iter().position(|p| &p.provider == provider) {
+                let p = providers.remove(i);
+                self.provided.remove(&p);
+                removed = true;
+            }
+            if providers.is_empty() {
+                e.remove();
+            }
+        }
+        self.providers_dirty |= removed;
     }
 }
@@ -1,12 +1,13 @@
 use crate::bench::Bench;
-use crate::p2p::peer_id::keypair_with_leading_zeros;
+use crate::ledger::ILedger;
+use crate::p2p::peer_id::{keypair_with_leading_zeros, peer_id_has_leading_zeros};
 use crate::p2p::store::LocalStoreConfig;
 // use crate::p2p::memorystore::{MemoryStore, MemoryStoreConfig};
-use crate::settings::ISettings;
+use crate::settings::{ISettings, Storage as StorageSettings};
 use crate::storage::IStorage;
 use crate::util::consts;
 use crate::util::{
-    types::{Bytes, CommandToSwarm, OneReceiver, OneSender},
+    types::{record_file_uuid, Bytes, CommandToSwarm, Contract, OneReceiver, OneSender},
     Er, ErrorKind, Res,
 };
 use crate::verifier::por::{VerificationServer, VerificationServerConfig};
@@ -24,17 +25,20 @@ use libp2p::{
         PeerRecord, PutRecordResult, QueryId, QueryResult, Quorum, Record,
     },
     mdns::{self, tokio::Behaviour},
+    multiaddr::Protocol,
     noise, request_response,
     swarm::{NetworkBehaviour, SwarmBuilder, SwarmEvent},
     tcp::tokio::Transport,
-    yamux, PeerId, Transport as _,
+    yamux, Multiaddr, PeerId, Transport as _,
 };
 use libp2p_identity::Keypair;
 use libp2p_kad::{
-    AddProviderOk, AddProviderResult, GetClosestPeersOk, GetClosestPeersResult, GetProvidersOk,
-    GetProvidersResult, Mode,
+    AddProviderOk, AddProviderResult, BootstrapError, BootstrapOk, GetClosestPeersOk,
+    GetClosestPeersResult, GetProvidersOk, GetProvidersResult, InboundRequest,
+    KademliaStoreInserts, Mode, NoKnownPeers,
 };
 use log::{debug, info, warn};
+use object_store::path::Path;
 use runtime_injector::{
     interface, InjectError, InjectResult, Injector, RequestInfo, Service, ServiceFactory,
     ServiceInfo, Svc,
@@ -43,15 +47,23 @@ use serde::{Deserialize, Serialize};
 use std::{
     collections::{HashMap, HashSet},
     fmt::Debug,
-    time::Duration,
+    path::PathBuf,
+    sync::OnceLock,
+    time::{Duration, Instant},
 };
+use time::OffsetDateTime;
 use tokio::{
     select,
-    sync::{mpsc::Receiver, oneshot, Mutex, MutexGuard},
+    sync::{
+        mpsc::{self, Receiver, UnboundedReceiver, UnboundedSender},
+        oneshot, Mutex, MutexGuard,
+    },
 };
 use uuid::Uuid;
 
+use super::codec::BincodeCodec;
 use super::store::LocalStore;
+use crate::storage::local::unix_secs_to_instant;
 
 interface! {
     dyn ISwarm = [
@@ -72,6 +84,7 @@ impl ServiceFactory<()> for SwarmProvider {
         let settings: Svc<dyn ISettings> = injector.get()?;
         let commands_from_controller: Svc<Mutex<Receiver<CommandToSwarm>>> = injector.get()?;
         let storage: Svc<dyn IStorage> = injector.get()?;
+        let ledger: Svc<Mutex<Box<dyn ILedger>>> = injector.get()?;
         let bench = injector.get::<Svc<Mutex<Bench>>>()?;
 
         let local_key = match settings.swarm().keypair {
@@ -87,16 +100,33 @@ impl ServiceFactory<()> for SwarmProvider {
                 service_info: ServiceInfo::of::<Swarm>(),
                 inner: Box::<Er>::new(ErrorKind::KeypairBase64DecodingError(e).into()),
             })?,
-            None => keypair_with_leading_zeros(consts::DEFAULT_LEADING_ZEROS),
+            None => keypair_with_leading_zeros(settings.swarm().leading_zeros),
         };
 
+        let leading_zeros = settings.swarm().leading_zeros;
         let local_peer_id = PeerId::from(local_key.public());
+        if !peer_id_has_leading_zeros(&local_peer_id, leading_zeros) {
+            warn!(
+                "local peer id {} doesn't have {} leading zeros, other peers will reject it",
+                local_peer_id, leading_zeros
+            );
+        }
         info!("starting peer with id: {}", local_peer_id);
 
         let mut swarm = {
+            // records carry the expiry of their contract, so kademlia must
+            // not impose its own default ttl on them, and they are replicated
+            // often, so they follow the churn of peers; large records are
+            // left to their contracts, see `LocalStore::records`. Kademlia
+            // drops the expiry of records from other peers, so it hands them
+            // over instead of storing them, see `RecordKeeper::accept`
             let cfg = KademliaConfig::default()
                 .set_query_timeout(Duration::from_secs(60))
                 .set_max_packet_size(1024 * 1024 * 1024)
+                .set_record_ttl(None)
+                .set_record_filtering(KademliaStoreInserts::FilterBoth)
+                .set_replication_interval(Some(consts::RECORD_REPLICATION_INTERVAL.unsigned_abs()))
+                .set_publication_interval(Some(consts::RECORD_PUBLICATION_INTERVAL.unsigned_abs()))
                 .to_owned();
             // let store = MemoryStore::with_config(
             //     local_peer_id,
@@ -108,6 +138,15 @@ impl ServiceFactory<()> for SwarmProvider {
             //     },
             // );
 
+            // kept next to the storage directory rather than inside it,
+            // so it isn't mistaken for a record
+            let providers_path = match settings.storage() {
+                StorageSettings::Local { path, .. } => Some(PathBuf::from(format!(
+                    "{}.providers.yaml",
+                    path.trim_end_matches('/')
+                ))),
+                StorageSettings::Docker | StorageSettings::Memory => None,
+            };
             let store = LocalStore::with_config(
                 local_peer_id,
                 LocalStoreConfig {
@@ -115,6 +154,7 @@ impl ServiceFactory<()> for SwarmProvider {
                     max_value_bytes: 1024 * 1024 * 1024,
                     max_provided_keys: 150000,
                     max_providers_per_key: 20,
+                    providers_path,
                 },
                 storage.clone(),
             );
@@ -134,10 +174,19 @@ impl ServiceFactory<()> for SwarmProvider {
                 )],
                 request_response::Config::default(),
             );
+            let records = request_response::Behaviour::with_codec(
+                BincodeCodec::new(
+                    consts::MAX_RECORD_REQUEST_SIZE,
+                    consts::MAX_RECORD_RESPONSE_SIZE,
+                ),
+                [(StreamProtocol::new("/record/1.0.0"), ProtocolSupport::Full)],
+                request_response::Config::default(),
+            );
             let behaviour = CombinedBehaviour {
                 kademlia,
                 mdns,
                 req_res,
+                records,
             };
             let transport = Transport::default()
                 .upgrade(Version::V1)
@@ -168,14 +217,44 @@ impl ServiceFactory<()> for SwarmProvider {
                 ),
             })?;
 
+        let port = settings.swarm().port;
+        let bootstrap: Vec<Multiaddr> = settings
+            .swarm()
+            .bootstrap
+            .into_iter()
+            // the shared configs list every peer, including this one
+            .filter(|addr| {
+                !(addr.port() == port && (addr.ip().is_loopback() || addr.ip().is_unspecified()))
+            })
+            .map(|addr| Multiaddr::from(addr.ip()).with(Protocol::Tcp(addr.port())))
+            .collect();
+        for addr in bootstrap.iter() {
+            match swarm.dial(addr.clone()) {
+                Ok(()) => info!("dialing bootstrap peer {}", addr),
+                Err(e) => warn!("failed to dial bootstrap peer {}: {}", addr, e),
+            }
+        }
+
+        let (deferred_sender, deferred) = mpsc::unbounded_channel();
         Ok(Swarm {
             local_peer_id,
-            storage,
+            storage: storage.clone(),
+            records: RecordKeeper {
+                local_peer_id,
+                storage,
+                ledger,
+            },
             inner: Mutex::new(swarm),
             commands_from_controller,
             bench,
             queries: Mutex::new(HashMap::new()),
             requests: Mutex::new(HashMap::new()),
+            record_requests: Mutex::new(HashMap::new()),
+            bootstrap,
+            leading_zeros,
+            listen_port: OnceLock::new(),
+            deferred_sender,
+            deferred: Mutex::new(deferred),
         })
     }
 }
@@ -183,16 +262,36 @@ impl ServiceFactory<()> for SwarmProvider {
 #[async_trait]
 pub trait ISwarm: Service {
     async fn start(&self) -> Res<()>;
+    /// The port the swarm listens on, once it does. Tells which port was
+    /// picked when the settings ask for port 0.
+    fn listen_port(&self) -> Option<u16>;
 }
 
 pub struct Swarm {
     local_peer_id: PeerId,
     storage: Svc<dyn IStorage>,
+    records: RecordKeeper,
     inner: Mutex<libp2p::Swarm<CombinedBehaviour>>,
     commands_from_controller: Svc<Mutex<Receiver<CommandToSwarm>>>,
     bench: Svc<Mutex<Bench>>,
     queries: Mutex<HashMap<QueryId, QueryResponse>>,
     requests: Mutex<HashMap<RequestId, QueryResponse>>,
+    record_requests: Mutex<HashMap<RequestId, QueryResponse>>,
+    bootstrap: Vec<Multiaddr>,
+    leading_zeros: usize,
+    listen_port: OnceLock<u16>,
+    /// Work finished off the event loop comes back through here.
+    deferred_sender: UnboundedSender<Deferred>,
+    deferred: Mutex<UnboundedReceiver<Deferred>>,
+}
+
+/// Work done off the event loop which needs the swarm to be completed.
+enum Deferred {
+    RecordResponse {
+        channel: ResponseChannel<RecordResponse>,
+        response: RecordResponse,
+    },
+    HeldRecords(HashSet<Key>),
 }
 
 #[derive(Debug)]
@@ -224,6 +323,9 @@ enum QueryResponse {
     VerificationRequest {
         sender: OneSender<Res<VerificationResponse>>,
     },
+    RecordRequest {
+        sender: OneSender<Res<RecordResponse>>,
+    },
 }
 
 #[async_trait]
@@ -231,6 +333,15 @@ impl ISwarm for Swarm {
     async fn start(&self) -> Res<()> {
         let mut swarm = self.inner.lock().await;
         let mut receiver = self.commands_from_controller.lock().await;
+        let mut deferred = self.deferred.lock().await;
+        // the first bootstrap runs once a bootstrap peer is connected
+        let period = consts::BOOTSTRAP_INTERVAL.unsigned_abs();
+        let mut bootstrap_interval =
+            tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+        let mut persist_interval =
+            tokio::time::interval(consts::PROVIDERS_PERSIST_INTERVAL.unsigned_abs());
+        let mut held_interval =
+            tokio::time::interval(consts::HELD_RECORDS_REFRESH_INTERVAL.unsigned_abs());
         loop {
             select! {
                 instruction = receiver.recv() => {
@@ -239,12 +350,38 @@ impl ISwarm for Swarm {
                 event = swarm.select_next_some() => {
                     self.handle_swarm_event(event, &mut swarm).await?;
                 }
+                _ = bootstrap_interval.tick() => {
+                    self.bootstrap(&mut swarm);
+                }
+                _ = persist_interval.tick() => {
+                    swarm.behaviour_mut().kademlia.store_mut().persist_providers();
+                }
+                _ = held_interval.tick() => {
+                    self.refresh_held_records();
+                }
+                Some(deferred) = deferred.recv() => {
+                    self.handle_deferred(deferred, &mut swarm)?;
+                }
             }
         }
     }
 }
 
 impl Swarm {
+    /// Peers must prove they did the work of generating their identity.
+    fn is_valid_peer(&self, peer_id: &PeerId) -> bool {
+        peer_id_has_leading_zeros(peer_id, self.leading_zeros)
+    }
+
+    fn bootstrap(&self, swarm: &mut MutexGuard<'_, libp2p::Swarm<CombinedBehaviour>>) {
+        match swarm.behaviour_mut().kademlia.bootstrap() {
+            Ok(query_id) => debug!("started bootstrap query {:?}", query_id),
+            Err(NoKnownPeers()) => {
+                warn!("cannot bootstrap: no known peers, check the bootstrap addresses")
+            }
+        }
+    }
+
     async fn handle_swarm_event<'t, SwarmError: Debug>(
         &self,
         event: SwarmEvent<WireEvent, SwarmError>,
@@ -254,10 +391,44 @@ impl Swarm {
         match event {
             SwarmEvent::NewListenAddr { address, .. } => {
                 info!("listening on {address:?}");
+                if let Some(Protocol::Tcp(port)) =
+                    address.iter().find(|x| matches!(x, Protocol::Tcp(_)))
+                {
+                    let _ = self.listen_port.set(port);
+                }
             }
+            SwarmEvent::ConnectionEstablished {
+                peer_id, endpoint, ..
+            } => {
+                if !self.is_valid_peer(&peer_id) {
+                    warn!("disconnecting {}: not enough leading zeros", peer_id);
+                    swarm.behaviour_mut().kademlia.remove_peer(&peer_id);
+                    if swarm.disconnect_peer_id(peer_id).is_err() {
+                        debug!("{} already disconnected", peer_id);
+                    }
+                    return Ok(());
+                }
+                let address = endpoint.get_remote_address();
+                if self.bootstrap.contains(address) {
+                    info!("connected to bootstrap peer {} at {}", peer_id, address);
+                    swarm
+                        .behaviour_mut()
+                        .kademlia
+                        .add_address(&peer_id, address.clone());
+                    self.bootstrap(swarm);
+                }
+            }
+            SwarmEvent::OutgoingConnectionError { peer_id, error, .. } => match peer_id {
+                Some(peer_id) => warn!("failed to connect to {}: {}", peer_id, error),
+                None => warn!("failed to connect: {}", error),
+            },
             SwarmEvent::Behaviour(WireEvent::Mdns(mdns::Event::Discovered(list))) => {
                 debug!("discovered peers: {list:?}");
                 for (peer_id, multiaddr) in list {
+                    if !self.is_valid_peer(&peer_id) {
+                        warn!("ignoring discovered {}: not enough leading zeros", peer_id);
+                        continue;
+                    }
                     swarm
                         .behaviour_mut()
                         .kademlia
@@ -277,11 +448,34 @@ impl Swarm {
                     QueryResult::StartProviding(message) => {
                         self.handle_start_providing(message, id).await?
                     }
+                    QueryResult::Bootstrap(Ok(BootstrapOk {
+                        peer,
+                        num_remaining,
+                    })) => debug!("bootstrapped {}, {} remaining", peer, num_remaining),
+                    QueryResult::Bootstrap(Err(BootstrapError::Timeout {
+                        peer,
+                        num_remaining,
+                    })) => warn!(
+                        "bootstrap timed out at {}, {:?} remaining",
+                        peer, num_remaining
+                    ),
                     _ => warn!("unhandled query result: {:?}", result),
                 },
-                KademliaEvent::RoutingUpdated { .. } => {
+                KademliaEvent::RoutingUpdated { peer, .. } => {
                     debug!("routing updated",);
+                    if !self.is_valid_peer(&peer) {
+                        warn!("removing {} from routing: not enough leading zeros", peer);
+                        swarm.behaviour_mut().kademlia.remove_peer(&peer);
+                    }
                 }
+                KademliaEvent::InboundRequest {
+                    request:
+                        InboundRequest::PutRecord {
+                            source,
+                            record: Some(record),
+                            ..
+                        },
+                } => self.handle_inbound_record(source, record),
                 KademliaEvent::InboundRequest { request, .. } => {
                     debug!("inbound request: {:?}", request);
                 }
@@ -322,6 +516,36 @@ impl Swarm {
                         .await?
                 }
             },
+            SwarmEvent::Behaviour(WireEvent::Records(event)) => match event {
+                request_response::Event::Message { peer, message } => match message {
+                    request_response::Message::Request {
+                        request, channel, ..
+                    } => self.handle_record_request(peer, request, channel),
+                    request_response::Message::Response {
+                        request_id,
+                        response,
+                    } => self.handle_record_response(response, request_id).await?,
+                },
+                request_response::Event::ResponseSent { peer, request_id } => {
+                    self.handle_response_sent(peer, request_id).await?
+                }
+                request_response::Event::InboundFailure {
+                    peer,
+                    request_id,
+                    error,
+                } => warn!(
+                    "record request {:?} from {} failed: {}",
+                    request_id, peer, error
+                ),
+                request_response::Event::OutboundFailure {
+                    peer: _,
+                    request_id,
+                    error,
+                } => {
+                    self.handle_record_outbound_failure(request_id, error)
+                        .await?
+                }
+            },
             _ => {}
         }
         Ok(())
@@ -395,6 +619,105 @@ impl Swarm {
         //     .map_err(|_| ErrorKind::SwarmReqResSendResponseError.into())
     }
 
+    /// Answering may need the ledger, so it is done off the event loop
+    /// and the response is sent once it comes back.
+    fn handle_record_request(
+        &self,
+        peer: PeerId,
+        request: RecordRequest,
+        channel: ResponseChannel<RecordResponse>,
+    ) {
+        let records = self.records.clone();
+        let deferred = self.deferred_sender.clone();
+        tokio::spawn(async move {
+            let response = records.answer(peer, request).await;
+            if deferred
+                .send(Deferred::RecordResponse { channel, response })
+                .is_err()
+            {
+                warn!(
+                    "the swarm stopped before a record request from {} was answered",
+                    peer
+                );
+            }
+        });
+    }
+
+    /// Storing the record needs the ledger, so it is done off the event loop.
+    fn handle_inbound_record(&self, peer: PeerId, record: Record) {
+        let records = self.records.clone();
+        tokio::spawn(async move {
+            if let Err(e) = records.accept(peer, record).await {
+                warn!("failed to store a record from {}: {}", peer, e);
+            }
+        });
+    }
+
+    /// Reading the contracts needs the ledger, so it is done off the event
+    /// loop and the store is updated once they come back.
+    fn refresh_held_records(&self) {
+        let records = self.records.clone();
+        let deferred = self.deferred_sender.clone();
+        tokio::spawn(async move {
+            match records.held_records().await {
+                Ok(held) => {
+                    if deferred.send(Deferred::HeldRecords(held)).is_err() {
+                        warn!("the swarm stopped before the held records were read");
+                    }
+                }
+                Err(e) => warn!("failed to read the held records: {}", e),
+            }
+        });
+    }
+
+    fn handle_deferred(
+        &self,
+        deferred: Deferred,
+        swarm: &mut MutexGuard<'_, libp2p::Swarm<CombinedBehaviour>>,
+    ) -> Res<()> {
+        match deferred {
+            Deferred::RecordResponse { channel, response } => swarm
+                .behaviour_mut()
+                .records
+                .send_response(channel, response)
+                .map_err(|_| ErrorKind::SwarmReqResSendResponseError.into()),
+            Deferred::HeldRecords(held) => {
+                swarm.behaviour_mut().kademlia.store_mut().set_held(held);
+                Ok(())
+            }
+        }
+    }
+
+    async fn handle_record_response(
+        &self,
+        response: RecordResponse,
+        request_id: RequestId,
+    ) -> Res<()> {
+        let response_channel = match self.record_requests.lock().await.remove(&request_id) {
+            Some(QueryResponse::RecordRequest { sender }) => sender,
+            _ => Err(ErrorKind::InvalidResponseChannelForRequest(request_id))?,
+        };
+        response_channel
+            .send(Ok(response))
+            .map_err(|_| ErrorKind::SwarmReqResSendResponseError)?;
+        Ok(())
+    }
+
+    async fn handle_record_outbound_failure(
+        &self,
+        request_id: RequestId,
+        error: OutboundFailure,
+    ) -> Res<()> {
+        let response_channel = match self.record_requests.lock().await.remove(&request_id) {
+            Some(QueryResponse::RecordRequest { sender }) => sender,
+            _ => Err(ErrorKind::InvalidResponseChannelForRequest(request_id))?,
+        };
+        response_channel
+            .send(Err(ErrorKind::RequestOutboundFailure(error).into()))
+            .map_err(|_| ErrorKind::SwarmReqResSendResponseError)?;
+        Ok(())
+    }
+
     async fn handle_reqres_message_response(
         &self,
         response: VerificationResponse,
@@ -551,16 +874,23 @@ impl Swarm {
         let instruction = instruction.ok_or(ErrorKind::MissingInstruction)?;
         debug!("instruction {}", instruction);
         match instruction {
-            CommandToSwarm::PutLocal { key, value, resp } => {
-                self.handle_controller_put(swarm, key, value, resp).await
+            CommandToSwarm::PutLocal {
+                key,
+                value,
+                expires,
+                resp,
+            } => {
+                self.handle_controller_put(swarm, key, value, expires, resp)
+                    .await
             }
             CommandToSwarm::PutRemote {
                 key,
                 value,
+                expires,
                 remotes,
                 resp,
             } => {
-                self.handle_controller_put_record_to(swarm, key, value, remotes, resp)
+                self.handle_controller_put_record_to(swarm, key, value, expires, remotes, resp)
                     .await
             }
             CommandToSwarm::Get { key, resp } => self.handle_controller_get(swarm, key, resp).await,
@@ -590,9 +920,63 @@ impl Swarm {
                 )
                 .await
             }
+            CommandToSwarm::RequestRemoval {
+                peer,
+                file_uuid,
+                resp,
+            } => {
+                let request = RecordRequest::Remove {
+                    file_name: file_uuid,
+                };
+                self.handle_controller_record_request(swarm, peer, request, resp)
+                    .await
+            }
+            CommandToSwarm::RequestFetch {
+                peer,
+                file_uuid,
+                resp,
+            } => {
+                let request = RecordRequest::Fetch {
+                    file_name: file_uuid,
+                };
+                self.handle_controller_record_request(swarm, peer, request, resp)
+                    .await
+            }
+            CommandToSwarm::RequestRenewal {
+                peer,
+                file_uuid,
+                expires_at,
+                resp,
+            } => {
+                let request = RecordRequest::Renew {
+                    file_name: file_uuid,
+                    expires_at,
+                };
+                self.handle_controller_record_request(swarm, peer, request, resp)
+                    .await
+            }
         }
     }
 
+    async fn handle_controller_record_request(
+        &self,
+        swarm: &mut MutexGuard<'_, libp2p::Swarm<CombinedBehaviour>>,
+        peer: PeerId,
+        request: RecordRequest,
+        resp: OneSender<OneReceiver<Res<RecordResponse>>>,
+    ) -> Res<()> {
+        let (sender, receiver) = oneshot::channel::<Res<RecordResponse>>();
+        resp.send(receiver)?;
+
+        let request_id = swarm.behaviour_mut().records.send_request(&peer, request);
+
+        self.record_requests
+            .lock()
+            .await
+            .insert(request_id, QueryResponse::RecordRequest { sender });
+        Ok(())
+    }
+
     async fn handle_controller_request_verification(
         &self,
         swarm: &mut MutexGuard<'_, libp2p::Swarm<CombinedBehaviour>>,
@@ -684,6 +1068,7 @@ impl Swarm {
         swarm: &mut MutexGuard<'t, libp2p::Swarm<CombinedBehaviour>>,
         key: String,
         value: Vec<u8>,
+        expires: Option<Instant>,
         remotes: Vec<PeerId>,
         resp: OneSender<OneReceiver<Res<()>>>,
     ) -> Res<()> {
@@ -694,7 +1079,7 @@ impl Swarm {
             key: key.clone(),
             value,
             publisher: None,
-            expires: None,
+            expires,
         };
 
         let query_id =
@@ -715,6 +1100,7 @@ impl Swarm {
         swarm: &mut MutexGuard<'t, libp2p::Swarm<CombinedBehaviour>>,
         key: String,
         value: Vec<u8>,
+        expires: Option<Instant>,
         resp: OneSender<OneReceiver<Res<()>>>,
     ) -> Res<()> {
         info!("putting key {:?} val {:?}", key, value);
@@ -723,7 +1109,7 @@ impl Swarm {
             key: key.clone(),
             value,
             publisher: None,
-            expires: None,
+            expires,
         };
         let (sender, receiver) = oneshot::channel::<Res<()>>();
         resp.send(receiver)?;
@@ -759,6 +1145,179 @@ impl Swarm {
     }
 }
 
+/// Answers the requests other peers make about the records this peer
+/// keeps. They are checked against the contracts in the ledger.
+#[derive(Clone)]
+struct RecordKeeper {
+    local_peer_id: PeerId,
+    storage: Svc<dyn IStorage>,
+    ledger: Svc<Mutex<Box<dyn ILedger>>>,
+}
+
+impl RecordKeeper {
+    async fn answer(&self, peer: PeerId, request: RecordRequest) -> RecordResponse {
+        match request {
+            RecordRequest::Remove { file_name } => {
+                info!("peer {} requested removal of {}", peer, file_name);
+                let result = match self.removal_allowed(&file_name).await {
+                    Ok(true) => self.storage.remove(&Path::from(file_name.as_str())).await,
+                    Ok(false) => Err(ErrorKind::RemovalRefused(file_name.clone()).into()),
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = &result {
+                    warn!("failed to remove {}: {}", file_name, e);
+                }
+                RecordResponse {
+                    file_name,
+                    succeeded: result.is_ok(),
+                    value: None,
+                }
+            }
+            RecordRequest::Fetch { file_name } => {
+                debug!("peer {} fetched {}", peer, file_name);
+                let result = match self.fetch_allowed(peer, &file_name).await {
+                    Ok(true) => self.storage.get(file_name.clone().into()).await,
+                    Ok(false) => {
+                        Err(ErrorKind::FetchRefused(file_name.clone(), peer.to_string()).into())
+                    }
+                    Err(e) => Err(e),
+                };
+                match result {
+                    Ok(record) => RecordResponse {
+                        file_name,
+                        succeeded: true,
+                        value: Some(record.value),
+                    },
+                    Err(e) => {
+                        warn!("failed to fetch {}: {}", file_name, e);
+                        RecordResponse {
+                            file_name,
+                            succeeded: false,
+                            value: None,
+                        }
+                    }
+                }
+            }
+            RecordRequest::Renew {
+                file_name,
+                expires_at,
+            } => {
+                info!("peer {} renewed the lease of {}", peer, file_name);
+                let result = match self.renewal_allowed(&file_name, expires_at).await {
+                    Ok(true) => self.renew_record(&file_name, expires_at).await,
+                    Ok(false) => Err(ErrorKind::RenewalRefused(file_name.clone()).into()),
+                    Err(e) => Err(e),
+                };
+                if let Err(e) = &result {
+                    warn!("failed to renew {}: {}", file_name, e);
+                }
+                RecordResponse {
+                    file_name,
+                    succeeded: result.is_ok(),
+                    value: None,
+                }
+            }
+        }
+    }
+
+    /// Stores a record another peer put on this one. It is kept until the
+    /// last active contract this peer holds it under expires, records
+    /// without contracts are kept as they come. Replication can bring back
+    /// copies of deleted or renewed records, so records whose contracts here
+    /// are terminated are refused, as are copies expiring before the kept one.
+    async fn accept(&self, peer: PeerId, mut record: Record) -> Res<()> {
+        let file_name = String::from_utf8(record.key.to_vec())?;
+        let contracts = self.held_contracts(&file_name).await?;
+        let active: Vec<&Contract> = contracts.iter().filter(|x| !x.terminated).collect();
+        if active.is_empty() && !contracts.is_empty() {
+            return Err(ErrorKind::RecordRefused(file_name).into());
+        }
+        if !active.is_empty() {
+            // a contract without an expiry keeps the record forever
+            record.expires = active
+                .iter()
+                .map(|x| x.expires_at())
+                .collect::<Option<Vec<i64>>>()
+                .and_then(|expiries| expiries.into_iter().max())
+                .map(|expiry| unix_secs_to_instant(expiry.max(0).unsigned_abs()));
+        }
+        if let Ok(Some(kept)) = self.storage.expiry(&Path::from(file_name.as_str())).await {
+            if record.expires.map_or(false, |expires| expires < kept) {
+                return Err(ErrorKind::RecordRefused(file_name).into());
+            }
+        }
+        debug!("storing {} from {}", file_name, peer);
+        self.storage.put(record).await
+    }
+
+    /// The keys of the records this peer holds active contracts for.
+    async fn held_records(&self) -> Res<HashSet<Key>> {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let contracts = self.ledger.lock().await.get_active_contracts().await?;
+        Ok(contracts
+            .iter()
+            .filter(|x| x.peer_id == self.local_peer_id && !x.is_expired(now))
+            .map(|x| Key::new(&x.record_key()))
+            .collect())
+    }
+
+    /// The contracts this peer holds the record under.
+    async fn held_contracts(&self, file_name: &str) -> Res<Vec<Contract>> {
+        let contracts = self
+            .ledger
+            .lock()
+            .await
+            .get_contracts(record_file_uuid(file_name).to_string())
+            .await?;
+        Ok(contracts
+            .into_iter()
+            .filter(|x| x.peer_id == self.local_peer_id && x.record_key() == file_name)
+            .collect())
+    }
+
+    /// Only verifiers and the holders of the file may fetch it, to audit it
+    /// or to rebuild a copy.
+    async fn fetch_allowed(&self, peer: PeerId, file_name: &str) -> Res<bool> {
+        let mut ledger = self.ledger.lock().await;
+        let contracts = ledger
+            .get_contracts(record_file_uuid(file_name).to_string())
+            .await?;
+        if contracts.iter().any(|x| x.peer_id == peer && !x.terminated) {
+            return Ok(true);
+        }
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let verifiers = ledger
+            .get_live_verifiers(now - consts::VERIFIER_HEARTBEAT_TIMEOUT.whole_seconds())
+            .await?;
+        Ok(verifiers.contains(&peer))
+    }
+
+    /// Any peer can ask for a removal, so a record is only removed once the
+    /// contracts this peer holds it under are all terminated.
+    async fn removal_allowed(&self, file_name: &str) -> Res<bool> {
+        let held = self.held_contracts(file_name).await?;
+        Ok(!held.is_empty() && held.iter().all(|x| x.terminated))
+    }
+
+    /// Any peer can ask for a renewal, so the record is only kept until an
+    /// active contract this peer holds it under expires.
+    async fn renewal_allowed(&self, file_name: &str, expires_at: u64) -> Res<bool> {
+        let held = self.held_contracts(file_name).await?;
+        Ok(held.iter().any(|x| {
+            !x.terminated
+                && x.expires_at().map_or(false, |expiry| {
+                    expiry >= 0 && expiry.unsigned_abs() >= expires_at
+                })
+        }))
+    }
+
+    async fn renew_record(&self, file_name: &str, expires_at: u64) -> Res<()> {
+        let mut record = self.storage.get(file_name.into()).await?;
+        record.expires = Some(unix_secs_to_instant(expires_at));
+        self.storage.put(record).await
+    }
+}
+
 #[derive(NetworkBehaviour)]
 #[behaviour(to_swarm = "WireEvent")]
 pub struct CombinedBehaviour {
@@ -766,6 +1325,7 @@ pub struct CombinedBehaviour {
     kademlia: Kademlia<LocalStore>,
     mdns: Behaviour,
     req_res: request_response::cbor::Behaviour<VerificationRequest, VerificationResponse>,
+    records: request_response::Behaviour<BincodeCodec<RecordRequest, RecordResponse>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -780,6 +1340,30 @@ pub struct VerificationResponse {
     pub response_vector: Vec<u64>,
 }
 
+/// Requests a peer makes about the records another peer keeps on its behalf.
+#[derive(Debug, Serialize, Deserialize)]
+pub enum RecordRequest {
+    Remove {
+        file_name: String,
+    },
+    Fetch {
+        file_name: String,
+    },
+    /// `expires_at` is a unix timestamp in seconds.
+    Renew {
+        file_name: String,
+        expires_at: u64,
+    },
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecordResponse {
+    pub file_name: String,
+    pub succeeded: bool,
+    /// The content of the record, only set in response to a fetch.
+    pub value: Option<Bytes>,
+}
+
 impl From<KademliaEvent> for WireEvent {
     fn from(event: KademliaEvent) -> Self {
         WireEvent::Kademlia(event)
@@ -798,9 +1382,16 @@ impl From<request_response::Event<VerificationRequest, VerificationResponse>> fo
     }
 }
 
+impl From<request_response::Event<RecordRequest, RecordResponse>> for WireEvent {
+    fn from(event: request_response::Event<RecordRequest, RecordResponse>) -> Self {
+        WireEvent::Records(event)
+    }
+}
+
 #[derive(Debug)]
 pub enum WireEvent {
     Kademlia(KademliaEvent),
     Mdns(mdns::Event),
     ReqRes(request_response::Event<VerificationRequest, VerificationResponse>),
+    Records(request_response::Event<RecordRequest, RecordResponse>),
 }
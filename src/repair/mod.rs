@@ -0,0 +1,290 @@
+use crate::ledger::ILedger;
+use crate::p2p::controller::ISwarmController;
+use crate::reputation::ReputationModel;
+use crate::settings::ISettings;
+use crate::util::erasure;
+use crate::util::hasher::hash;
+use crate::util::types::{record_expiry, shard_record_key, Bytes, Contract, Shard};
+use crate::util::{consts, ErrorKind, Res};
+use crate::verifier::por::VerificationClientConfig;
+use crate::verifier::secrets::AuditorKeys;
+use async_trait::async_trait;
+use libp2p::PeerId;
+use log::{debug, info, warn};
+use runtime_injector::{
+    interface, InjectResult, Injector, RequestInfo, Service, ServiceFactory, Svc,
+};
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::sync::Mutex;
+use uuid::Uuid;
+
+interface! {
+    dyn IRepairer = [
+        Repairer,
+    ]
+}
+
+pub struct RepairerProvider;
+impl ServiceFactory<()> for RepairerProvider {
+    type Result = Repairer;
+
+    fn invoke(
+        &mut self,
+        injector: &Injector,
+        _request_info: &RequestInfo,
+    ) -> InjectResult<Self::Result> {
+        let ledger: Svc<Mutex<Box<dyn ILedger>>> = injector.get()?;
+        let swarm_controller = injector.get::<Svc<dyn ISwarmController>>()?;
+        let auditor_keys = injector.get::<Svc<AuditorKeys>>()?;
+        let reputation = ReputationModel::new(injector.get::<Svc<dyn ISettings>>()?.reputation());
+
+        Ok(Repairer {
+            ledger,
+            swarm_controller,
+            auditor_keys,
+            reputation,
+        })
+    }
+}
+
+#[async_trait]
+pub trait IRepairer: Service {
+    /// Moves the file of a contract whose holder can no longer be trusted
+    /// to a new peer, and replaces the contract.
+    /// Returns the peer now holding the file.
+    async fn repair(&self, contract: &Contract) -> Res<PeerId>;
+}
+
+pub struct Repairer {
+    ledger: Svc<Mutex<Box<dyn ILedger>>>,
+    swarm_controller: Svc<dyn ISwarmController>,
+    auditor_keys: Svc<AuditorKeys>,
+    reputation: ReputationModel,
+}
+
+#[async_trait]
+impl IRepairer for Repairer {
+    async fn repair(&self, contract: &Contract) -> Res<PeerId> {
+        info!(
+            "repairing {} held by {}",
+            contract.file_uuid, contract.peer_id
+        );
+        let contracts: Vec<Contract> = self
+            .ledger
+            .lock()
+            .await
+            .get_contracts(contract.file_uuid.clone())
+            .await?
+            .into_iter()
+            .filter(|x| !x.terminated)
+            .collect();
+        let holders: Vec<PeerId> = contracts.iter().map(|x| x.peer_id).collect();
+
+        let content = match &contract.shard {
+            Some(shard) => self.rebuild_shard(contract, shard, &contracts).await?,
+            None => self.fetch_healthy_copy(contract, &holders).await?,
+        };
+        let replacement = self.find_replacement(contract, &holders).await?;
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
+        // the new contract keeps the expiry of the one it replaces
+        let ttl = match contract.expires_at() {
+            Some(expires_at) => i64::max(expires_at - now, 1),
+            None => contract.ttl,
+        };
+
+        let client_config = VerificationClientConfig::from_file(&content);
+        let sealed_secrets = client_config.seal(self.auditor_keys.public()?)?;
+        let (rows, cols) = client_config.dimensions();
+        let file_uuid = Uuid::from_str(contract.file_uuid.as_str())
+            .map_err(|e| ErrorKind::Generic(e.to_string()))?;
+        // the replacement looks for its contract when the record arrives,
+        // and the file must stay under contract somewhere all along, so the
+        // old contract is only terminated once the file has moved
+        self.ledger
+            .lock()
+            .await
+            .create_contract(
+                replacement,
+                file_uuid,
+                contract.file_hash.clone(),
+                ttl,
+                sealed_secrets,
+                rows,
+                cols,
+                contract.shard.clone(),
+                client_config.version(),
+                consts::CONTRACT_STAKE,
+            )
+            .await?;
+        if let Err(e) = self
+            .swarm_controller
+            .put_to(
+                contract.record_key(),
+                content,
+                record_expiry(ttl),
+                vec![replacement],
+            )
+            .await
+        {
+            self.terminate_held(contract, replacement).await;
+            return Err(e);
+        }
+        self.ledger
+            .lock()
+            .await
+            .terminate_contract(contract.contract_uuid.clone())
+            .await?;
+
+        // the old holder only gives up its copy once its contract is terminated
+        match self
+            .swarm_controller
+            .request_removal(contract.peer_id, contract.record_key())
+            .await
+        {
+            Ok(true) => debug!("{} removed {}", contract.peer_id, contract.record_key()),
+            Ok(false) => warn!(
+                "{} refused to remove {}",
+                contract.peer_id,
+                contract.record_key()
+            ),
+            Err(e) => warn!(
+                "failed to ask {} to remove {}: {}",
+                contract.peer_id,
+                contract.record_key(),
+                e
+            ),
+        }
+
+        info!(
+            "moved {} from {} to {}",
+            contract.file_uuid, contract.peer_id, replacement
+        );
+        Ok(replacement)
+    }
+}
+
+impl Repairer {
+    /// Terminates the contracts `peer` got for the record of the contract,
+    /// when the record couldn't be moved to it after all.
+    async fn terminate_held(&self, contract: &Contract, peer: PeerId) {
+        let mut ledger = self.ledger.lock().await;
+        let held = match ledger.get_contracts(contract.file_uuid.clone()).await {
+            Ok(contracts) => contracts,
+            Err(e) => {
+                warn!(
+                    "failed to read the contracts of {}: {}",
+                    contract.file_uuid, e
+                );
+                return;
+            }
+        };
+        for x in held.iter().filter(|x| {
+            x.peer_id == peer && !x.terminated && x.record_key() == contract.record_key()
+        }) {
+            if let Err(e) = ledger.terminate_contract(x.contract_uuid.clone()).await {
+                warn!("failed to terminate {}: {}", x.contract_uuid, e);
+            }
+        }
+    }
+
+    /// Fetches the file from the other holders until one of them returns
+    /// content matching the hash in the contract.
+    async fn fetch_healthy_copy(&self, contract: &Contract, holders: &[PeerId]) -> Res<Bytes> {
+        for holder in holders.iter().filter(|x| **x != contract.peer_id) {
+            match self
+                .swarm_controller
+                .request_fetch(*holder, contract.file_uuid.clone())
+                .await
+            {
+                Ok(content) if hash(&content) == contract.file_hash => return Ok(content),
+                Ok(_) => warn!("{} holds a modified copy of {}", holder, contract.file_uuid),
+                Err(e) => debug!(
+                    "failed fetching {} from {}: {}",
+                    contract.file_uuid, holder, e
+                ),
+            }
+        }
+        Err(ErrorKind::NoHealthyCopy(contract.file_uuid.clone()).into())
+    }
+
+    /// Rebuilds the shard of a contract from the shards held by the other
+    /// peers, checking the reconstructed file against the hash in the contract.
+    async fn rebuild_shard(
+        &self,
+        contract: &Contract,
+        shard: &Shard,
+        contracts: &[Contract],
+    ) -> Res<Bytes> {
+        let data_shards = shard.data_shards as usize;
+        let parity_shards = shard.parity_shards as usize;
+        let mut shards: Vec<Option<Bytes>> = vec![None; data_shards + parity_shards];
+        let mut fetched = 0;
+        for other in contracts.iter().filter(|x| x.peer_id != contract.peer_id) {
+            let Some(other_shard) = other.shard.as_ref() else {
+                continue;
+            };
+            let index = other_shard.index as usize;
+            if fetched == data_shards || index == shard.index as usize {
+                continue;
+            }
+            let record_key = shard_record_key(&contract.file_uuid, index as i64);
+            match self
+                .swarm_controller
+                .request_fetch(other.peer_id, record_key.clone())
+                .await
+            {
+                Ok(content) if !other_shard.holds(&content) => {
+                    warn!("{} holds a modified copy of {}", other.peer_id, record_key)
+                }
+                Ok(content) => {
+                    if let Some(slot) = shards.get_mut(index) {
+                        *slot = Some(content);
+                        fetched += 1;
+                    }
+                }
+                Err(e) => debug!(
+                    "failed fetching {} from {}: {}",
+                    record_key, other.peer_id, e
+                ),
+            }
+        }
+
+        let file =
+            erasure::reconstruct(shards, data_shards, parity_shards, shard.file_size as usize)
+                .map_err(|_| ErrorKind::NoHealthyCopy(contract.file_uuid.clone()))?;
+        if hash(&file) != contract.file_hash {
+            warn!("shards of {} rebuild a modified file", contract.file_uuid);
+            return Err(ErrorKind::NoHealthyCopy(contract.file_uuid.clone()).into());
+        }
+        erasure::encode(&file, data_shards, parity_shards)?
+            .into_iter()
+            .nth(shard.index as usize)
+            .ok_or_else(|| ErrorKind::NoHealthyCopy(contract.file_uuid.clone()).into())
+    }
+
+    /// Picks the closest peer to the file which doesn't hold it yet, isn't
+    /// banned and has enough reputation to stake on the contract.
+    async fn find_replacement(&self, contract: &Contract, holders: &[PeerId]) -> Res<PeerId> {
+        let file_uuid = Uuid::from_str(contract.file_uuid.as_str())
+            .map_err(|e| ErrorKind::Generic(e.to_string()))?;
+        let candidates = self.swarm_controller.get_closest_peers(file_uuid).await?;
+        for candidate in candidates
+            .into_iter()
+            .filter(|x| *x != contract.peer_id && !holders.contains(x))
+        {
+            let mut ledger = self.ledger.lock().await;
+            if self
+                .reputation
+                .is_banned(ledger.get_total_reputation(candidate).await?)
+            {
+                continue;
+            }
+            if ledger.can_stake(candidate, consts::CONTRACT_STAKE).await? {
+                return Ok(candidate);
+            }
+        }
+        Err(ErrorKind::NoReplacementPeer(contract.file_uuid.clone()).into())
+    }
+}
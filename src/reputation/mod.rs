@@ -0,0 +1,158 @@
+use crate::settings::Reputation;
+
+/// The rules reputation follows, on plain numbers so they don't need a
+/// ledger. Reputation here is all of it, staked or not.
+#[derive(Debug, Clone)]
+pub struct ReputationModel {
+    settings: Reputation,
+}
+
+/// Decay due to the reputation of a peer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Decay {
+    /// What to add to the reputation, negative when it decays and positive
+    /// when it recovers.
+    pub amount: i64,
+    /// Up to when the decay is applied, in seconds since the epoch. Time
+    /// short of a whole interval carries over to the next decay.
+    pub decayed_at: i64,
+}
+
+impl ReputationModel {
+    pub fn new(settings: Reputation) -> Self {
+        Self { settings }
+    }
+
+    /// Reputation gained for a passed audit, never past the ceiling.
+    pub fn reward(&self, reputation: i64) -> i64 {
+        (self.settings.ceiling - reputation)
+            .min(self.settings.audit_reward)
+            .max(0)
+    }
+
+    /// Reputation lost for a failed audit.
+    pub fn penalty(&self) -> i64 {
+        self.settings.audit_penalty
+    }
+
+    /// Reputation lost by a verifier for a claim against the quorum.
+    pub fn disagreement_penalty(&self) -> i64 {
+        self.settings.disagreement_penalty
+    }
+
+    /// Banned peers get no new files and their reputation doesn't recover.
+    pub fn is_banned(&self, reputation: i64) -> bool {
+        reputation < self.settings.ban_below
+    }
+
+    /// The decay due from `decayed_at` to `now`. Every whole interval moves
+    /// the reputation a step towards the baseline, so neither good nor bad
+    /// behavior counts forever. Banned peers don't move.
+    pub fn decay(&self, reputation: i64, decayed_at: i64, now: i64) -> Decay {
+        let interval = self.settings.decay_interval;
+        if interval <= 0 || now <= decayed_at {
+            return Decay {
+                amount: 0,
+                decayed_at,
+            };
+        }
+        let intervals = (now - decayed_at) / interval;
+        let decayed_at = decayed_at + intervals * interval;
+        if self.is_banned(reputation) {
+            return Decay {
+                amount: 0,
+                decayed_at,
+            };
+        }
+        let most = intervals.saturating_mul(self.settings.decay_step).max(0);
+        Decay {
+            amount: (self.settings.baseline - reputation).clamp(-most, most),
+            decayed_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn model() -> ReputationModel {
+        ReputationModel::new(Reputation {
+            audit_reward: 2,
+            audit_penalty: 5,
+            baseline: 30,
+            decay_interval: 60,
+            decay_step: 1,
+            ceiling: 40,
+            ban_below: 0,
+            disagreement_penalty: 2,
+        })
+    }
+
+    #[test]
+    fn rewards_stop_at_the_ceiling() {
+        let model = model();
+        assert_eq!(model.reward(30), 2);
+        assert_eq!(model.reward(39), 1);
+        assert_eq!(model.reward(40), 0);
+        assert_eq!(model.reward(45), 0);
+    }
+
+    #[test]
+    fn reputation_decays_and_recovers_towards_the_baseline() {
+        let model = model();
+        assert_eq!(
+            model.decay(35, 1000, 1000 + 3 * 60),
+            Decay {
+                amount: -3,
+                decayed_at: 1000 + 3 * 60
+            }
+        );
+        assert_eq!(model.decay(20, 1000, 1000 + 3 * 60).amount, 3);
+        // never past the baseline
+        assert_eq!(model.decay(31, 1000, 1000 + 3 * 60).amount, -1);
+        assert_eq!(model.decay(30, 1000, 1000 + 3 * 60).amount, 0);
+    }
+
+    #[test]
+    fn partial_intervals_carry_over() {
+        let model = model();
+        assert_eq!(
+            model.decay(35, 1000, 1059),
+            Decay {
+                amount: 0,
+                decayed_at: 1000
+            }
+        );
+        assert_eq!(
+            model.decay(35, 1000, 1000 + 90),
+            Decay {
+                amount: -1,
+                decayed_at: 1060
+            }
+        );
+    }
+
+    #[test]
+    fn banned_peers_dont_recover() {
+        let model = model();
+        assert!(model.is_banned(-1));
+        assert!(!model.is_banned(0));
+        assert_eq!(
+            model.decay(-1, 1000, 1000 + 10 * 60),
+            Decay {
+                amount: 0,
+                decayed_at: 1000 + 10 * 60
+            }
+        );
+    }
+
+    #[test]
+    fn decay_can_be_turned_off() {
+        let model = ReputationModel::new(Reputation {
+            decay_interval: 0,
+            ..Reputation::default()
+        });
+        assert_eq!(model.decay(90, 1000, 1_000_000).amount, 0);
+    }
+}
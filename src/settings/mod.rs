@@ -28,6 +28,8 @@ pub enum Storage {
         create: bool,
     },
     Docker,
+    /// Records are only kept in memory, for tests.
+    Memory,
 }
 
 impl Default for Storage {
@@ -60,6 +62,25 @@ pub struct Swarm {
 pub struct Verifier {
     pub enabled: bool,
     pub corrupt: bool,
+    /// Base64 x25519 key which opens the secrets of the contracts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auditor_secret_key: Option<String>,
+    /// How many of the live verifiers audit every contract each cycle.
+    #[serde(default = "Verifier::default_audits_per_contract")]
+    pub audits_per_contract: usize,
+    /// How many of them have to agree before reputation moves.
+    #[serde(default = "Verifier::default_quorum")]
+    pub quorum: usize,
+}
+
+impl Verifier {
+    fn default_audits_per_contract() -> usize {
+        consts::AUDITS_PER_CONTRACT
+    }
+
+    fn default_quorum() -> usize {
+        consts::AUDIT_QUORUM
+    }
 }
 
 pub trait ISettings: Service {
@@ -70,6 +91,9 @@ pub trait ISettings: Service {
     fn malicious_behavior(&self) -> MaliciousBehavior;
     fn verifier(&self) -> Verifier;
     fn por(&self) -> Por;
+    fn redundancy(&self) -> Redundancy;
+    fn selection(&self) -> Selection;
+    fn reputation(&self) -> Reputation;
 }
 
 #[derive(Debug, Deserialize, Serialize, Clone)]
@@ -79,7 +103,15 @@ pub enum Ledger {
         username: String,
         password: String,
         address: SocketAddr,
+        /// Where the last verified state of the database is kept,
+        /// `data/<config name>.immudb.state` when it isn't set.
+        #[serde(default)]
+        state_path: String,
     },
+    /// A hash chained file on this node, for running without immudb.
+    Embedded { path: String },
+    /// Only kept in memory, for tests.
+    Memory,
 }
 
 impl Default for Ledger {
@@ -88,10 +120,18 @@ impl Default for Ledger {
             username: "".to_string(),
             password: "".to_string(),
             address: SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), 3322)),
+            state_path: format!("{}/immudb.state", consts::DATA_DIR),
         }
     }
 }
 
+impl Ledger {
+    /// Every node has to trust its own state, so it's kept per config.
+    fn state_path_for(config_name: &str) -> String {
+        format!("{}/{}.immudb.state", consts::DATA_DIR, config_name)
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize, Clone)]
 #[serde(tag = "type", rename_all = "snake_case")]
 pub enum MaliciousBehavior {
@@ -116,6 +156,94 @@ pub struct Grpc {
 #[serde(rename_all = "snake_case")]
 pub struct Por {
     pub enabled: bool,
+    /// Base64 x25519 key the secrets of new contracts are sealed to.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auditor_public_key: Option<String>,
+}
+
+/// How a stored file is spread over the peers.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Redundancy {
+    /// Full copies of the file on `factor` peers.
+    Replication { factor: usize },
+    /// The file is split into `data_shards`, any of which can be rebuilt
+    /// from the others and the `parity_shards`. Each shard goes to a different peer.
+    ErasureCoding {
+        data_shards: usize,
+        parity_shards: usize,
+    },
+}
+
+impl Default for Redundancy {
+    fn default() -> Self {
+        Self::Replication {
+            factor: consts::REPLICATION_FACTOR,
+        }
+    }
+}
+
+/// How the peers which hold a new file are picked among the closest ones.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectionPolicy {
+    /// The closest peers to the file, as Kademlia would pick them.
+    Closest,
+    /// The peers with the most reputation.
+    HighestReputation,
+    /// Random peers, more likely the more reputation they have.
+    WeightedRandom,
+}
+
+impl Default for SelectionPolicy {
+    fn default() -> Self {
+        Self::Closest
+    }
+}
+
+#[derive(Default, Debug, Deserialize, Serialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub struct Selection {
+    #[serde(default)]
+    pub policy: SelectionPolicy,
+    /// Peers with less reputation don't get new files. Peers which can't
+    /// stake on a contract are skipped regardless.
+    #[serde(default)]
+    pub min_reputation: i64,
+}
+
+/// How reputation moves with audits and time, see `ReputationModel`.
+#[derive(Debug, Deserialize, Serialize, Clone, PartialEq)]
+#[serde(default, rename_all = "snake_case")]
+pub struct Reputation {
+    pub audit_reward: i64,
+    pub audit_penalty: i64,
+    /// Reputation moves back towards this over time.
+    pub baseline: i64,
+    /// Seconds it takes reputation to move a step towards the baseline,
+    /// 0 turns decay off.
+    pub decay_interval: i64,
+    pub decay_step: i64,
+    pub ceiling: i64,
+    /// Peers with less reputation are banned.
+    pub ban_below: i64,
+    /// Lost by a verifier whose claim goes against the quorum.
+    pub disagreement_penalty: i64,
+}
+
+impl Default for Reputation {
+    fn default() -> Self {
+        Self {
+            audit_reward: consts::AUDIT_REWARD,
+            audit_penalty: consts::AUDIT_PENALTY,
+            baseline: consts::INITIAL_REPUTATION,
+            decay_interval: consts::REPUTATION_DECAY_INTERVAL.whole_seconds(),
+            decay_step: consts::REPUTATION_DECAY_STEP,
+            ceiling: consts::REPUTATION_CEILING,
+            ban_below: consts::BAN_BELOW,
+            disagreement_penalty: consts::DISAGREEMENT_PENALTY,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Serialize, Default, Clone)]
@@ -127,6 +255,12 @@ pub struct Settings {
     pub malicious_behavior: Option<MaliciousBehavior>,
     pub verifier: Verifier,
     pub por: Por,
+    #[serde(default)]
+    pub redundancy: Redundancy,
+    #[serde(default)]
+    pub selection: Selection,
+    #[serde(default)]
+    pub reputation: Reputation,
 }
 
 impl ISettings for Settings {
@@ -157,6 +291,18 @@ impl ISettings for Settings {
     fn por(&self) -> Por {
         self.por.clone()
     }
+
+    fn redundancy(&self) -> Redundancy {
+        self.redundancy.clone()
+    }
+
+    fn selection(&self) -> Selection {
+        self.selection.clone()
+    }
+
+    fn reputation(&self) -> Reputation {
+        self.reputation.clone()
+    }
 }
 
 fn random_string(len: usize) -> String {
@@ -196,13 +342,23 @@ impl Settings {
                 username: "immudb".to_string(),
                 password: "immudb".to_string(),
                 address: SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(127, 0, 0, 1), 3322)),
+                state_path: Ledger::state_path_for(config_name),
             },
             malicious_behavior: MaliciousBehavior::None.into(),
             verifier: Verifier {
                 enabled: true,
                 corrupt: false,
+                auditor_secret_key: None,
+                audits_per_contract: consts::AUDITS_PER_CONTRACT,
+                quorum: consts::AUDIT_QUORUM,
             },
-            por: Por { enabled: true },
+            por: Por {
+                enabled: true,
+                auditor_public_key: None,
+            },
+            redundancy: Redundancy::default(),
+            selection: Selection::default(),
+            reputation: Reputation::default(),
         }
     }
 }
@@ -231,7 +387,7 @@ impl ServiceFactory<()> for SettingsProvider {
             builder = builder.add_source(File::with_name(consts::BASE_CONFIG));
         }
 
-        builder
+        let mut settings: Settings = builder
             .add_source(File::with_name(&format!("{}/{}", CONFIG_DIR, env_conf)).required(false))
             .add_source(
                 Environment::with_prefix("KISS")
@@ -248,7 +404,24 @@ impl ServiceFactory<()> for SettingsProvider {
             .map_err(|err| InjectError::ActivationFailed {
                 service_info: ServiceInfo::of::<Settings>(),
                 inner: Box::<Er>::new(ErrorKind::ConfigErr(err).into()),
-            })
+            })?;
+
+        if let Ledger::Immudb { state_path, .. } = &mut settings.ledger {
+            if state_path.is_empty() {
+                *state_path = Ledger::state_path_for(&env_conf);
+            }
+        }
+        if let Ok(key) = env::var(consts::AUDITOR_SECRET_KEY_ENV) {
+            settings.verifier.auditor_secret_key = Some(key);
+        }
+        // anyone holding the secret can open the contracts and forge proofs
+        if !settings.verifier.enabled && settings.verifier.auditor_secret_key.is_some() {
+            return Err(InjectError::ActivationFailed {
+                service_info: ServiceInfo::of::<Settings>(),
+                inner: Box::<Er>::new(ErrorKind::AuditorSecretOnStorageNode.into()),
+            });
+        }
+        Ok(settings)
     }
 }
 
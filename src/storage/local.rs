@@ -20,7 +20,10 @@ use serde::{
 };
 use std::path;
 use std::{fmt, str::FromStr, time::Instant};
-use std::{path::PathBuf, time::Duration};
+use std::{
+    path::PathBuf,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
 
 #[derive(Default)]
 pub struct LocalStorage {
@@ -39,11 +42,11 @@ impl Serialize for RecordWrapper {
         let key = String::from_utf8(self.0.key.to_vec()).unwrap_or_default();
         let value = base64::engine::general_purpose::STANDARD.encode(&self.0.value);
         let publisher = self.0.publisher.as_ref().map(|peer_id| peer_id.to_base58());
-        let expires = self.0.expires.as_ref().map(|expires| expires.elapsed());
+        let expires_at = self.0.expires.map(instant_to_unix_secs);
 
         serialized.serialize_field("key", &key)?;
         serialized.serialize_field("publisher", &publisher)?;
-        serialized.serialize_field("expires", &expires)?;
+        serialized.serialize_field("expires_at", &expires_at)?;
         serialized.serialize_field("value", &value)?;
         serialized.end()
     }
@@ -55,11 +58,14 @@ impl<'de> Deserialize<'de> for RecordWrapper {
         D: Deserializer<'de>,
     {
         #[derive(Deserialize)]
-        #[serde(field_identifier, rename_all = "lowercase")]
+        #[serde(field_identifier, rename_all = "snake_case")]
         enum Field {
             Key,
             Publisher,
+            // written by older versions as the time elapsed since the expiry,
+            // which is meaningless after a restart, so it is read and ignored
             Expires,
+            ExpiresAt,
             Value,
         }
 
@@ -79,6 +85,7 @@ impl<'de> Deserialize<'de> for RecordWrapper {
                 let mut key: Option<String> = None;
                 let mut publisher: Option<Option<String>> = None;
                 let mut expires: Option<Option<Duration>> = None;
+                let mut expires_at: Option<Option<u64>> = None;
                 let mut value: Option<String> = None;
                 while let Some(yaml_key) = map.next_key()? {
                     match yaml_key {
@@ -100,6 +107,12 @@ impl<'de> Deserialize<'de> for RecordWrapper {
                             }
                             expires = Some(map.next_value()?);
                         }
+                        Field::ExpiresAt => {
+                            if expires_at.is_some() {
+                                return Err(de::Error::duplicate_field("expires_at"));
+                            }
+                            expires_at = Some(map.next_value()?);
+                        }
                         Field::Value => {
                             if value.is_some() {
                                 return Err(de::Error::duplicate_field("value"));
@@ -118,9 +131,10 @@ impl<'de> Deserialize<'de> for RecordWrapper {
                         })?),
                         None => None,
                     };
-                let expires = expires
-                    .unwrap_or_default()
-                    .map(|duration| Instant::now() + duration);
+                if expires.is_some() {
+                    debug!("ignoring legacy expires field");
+                }
+                let expires = expires_at.unwrap_or_default().map(unix_secs_to_instant);
                 let value = base64::engine::general_purpose::STANDARD
                     .decode(value.ok_or_else(|| de::Error::missing_field("value"))?)
                     .unwrap_or_default();
@@ -134,7 +148,7 @@ impl<'de> Deserialize<'de> for RecordWrapper {
             }
         }
 
-        const FIELDS: &[&str] = &["key", "publisher", "expires", "value"];
+        const FIELDS: &[&str] = &["key", "publisher", "expires", "expires_at", "value"];
         deserializer.deserialize_struct("Record", FIELDS, RecordVisitor)
     }
 }
@@ -211,6 +225,78 @@ impl IStorage for LocalStorage {
         }
         Ok(result)
     }
+
+    async fn expiry(&self, path: &Path) -> Res<Option<Instant>> {
+        // the expiry is written before the value, so it's in the first bytes
+        let size = self
+            .local_storage
+            .head(path)
+            .await
+            .map_err(ErrorKind::ObjectStoreError)?
+            .size;
+        let head = self
+            .local_storage
+            .get_range(path, 0..size.min(RECORD_HEAD_BYTES))
+            .await
+            .map_err(ErrorKind::ObjectStoreError)?;
+        match parse_expiry(&head) {
+            Some(expiry) => Ok(expiry),
+            // records with an unusually long key or publisher
+            None => Ok(self.get(PathBuf::from(path.as_ref())).await?.expires),
+        }
+    }
+
+    async fn size(&self, path: &Path) -> Res<usize> {
+        // the serialized record, so a little over the size of the value
+        Ok(self
+            .local_storage
+            .head(path)
+            .await
+            .map_err(ErrorKind::ObjectStoreError)?
+            .size)
+    }
+}
+
+/// How much of a record is read to find its expiry.
+const RECORD_HEAD_BYTES: usize = 1024;
+
+/// The expiry in the beginning of a serialized record, `None` when it isn't
+/// there.
+fn parse_expiry(head: &[u8]) -> Option<Option<Instant>> {
+    let value = String::from_utf8_lossy(head)
+        .lines()
+        .take_while(|line| !line.starts_with("value:"))
+        .find_map(|line| {
+            line.strip_prefix("expires_at:")
+                .map(|x| x.trim().to_string())
+        })?;
+    match value.as_str() {
+        "null" | "~" | "" => Some(None),
+        secs => secs
+            .parse()
+            .ok()
+            .map(|secs| Some(unix_secs_to_instant(secs))),
+    }
+}
+
+/// `Instant`s are only meaningful within a single run of the process,
+/// so expiries are persisted as unix timestamps in seconds.
+pub fn instant_to_unix_secs(instant: Instant) -> u64 {
+    let now = Instant::now();
+    SystemTime::now()
+        .checked_add(instant.saturating_duration_since(now))
+        .and_then(|time| time.checked_sub(now.saturating_duration_since(instant)))
+        .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+        .map(|duration| duration.as_secs())
+        .unwrap_or_default()
+}
+
+pub fn unix_secs_to_instant(secs: u64) -> Instant {
+    let now = Instant::now();
+    match (UNIX_EPOCH + Duration::from_secs(secs)).duration_since(SystemTime::now()) {
+        Ok(remaining) => now + remaining,
+        Err(passed) => now.checked_sub(passed.duration()).unwrap_or(now),
+    }
 }
 
 impl LocalStorage {
@@ -227,3 +313,48 @@ impl LocalStorage {
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn storage() -> (LocalStorage, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("kiss-storage-{}", uuid::Uuid::new_v4()));
+        (LocalStorage::new(dir.to_str().unwrap(), true).unwrap(), dir)
+    }
+
+    #[tokio::test]
+    async fn expiry_is_read_from_the_head_of_the_record() {
+        let (storage, dir) = storage();
+        let expires = Instant::now() + Duration::from_secs(3600);
+        let mut record = Record::new(RecordKey::new(&"file"), vec![7; 1024 * 1024]);
+        record.expires = Some(expires);
+        storage.put(record).await.unwrap();
+        storage
+            .put(Record::new(RecordKey::new(&"forever"), vec![7; 16]))
+            .await
+            .unwrap();
+
+        let expiry = storage.expiry(&Path::from("file")).await.unwrap().unwrap();
+        // the expiry is kept in whole seconds
+        assert!(expiry.max(expires) - expiry.min(expires) <= Duration::from_secs(1));
+        assert_eq!(storage.expiry(&Path::from("forever")).await.unwrap(), None);
+        assert!(storage.expiry(&Path::from("missing")).await.is_err());
+        assert!(storage.size(&Path::from("file")).await.unwrap() > 1024 * 1024);
+        assert!(storage.size(&Path::from("forever")).await.unwrap() < 1024);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn expiry_needs_the_field() {
+        assert_eq!(
+            parse_expiry(b"key: file\nexpires_at: null\nvalue: AQID\n"),
+            Some(None)
+        );
+        assert!(parse_expiry(b"key: file\nexpires_at: 1700000000\n")
+            .unwrap()
+            .is_some());
+        assert_eq!(parse_expiry(b"key: file\npublisher: null\n"), None);
+        assert_eq!(parse_expiry(b"key: file\nvalue: expires_at: 1\n"), None);
+    }
+}
@@ -0,0 +1,113 @@
+use super::{key_to_path, IStorage};
+use crate::util::{ErrorKind, Res};
+use async_trait::async_trait;
+use libp2p::kad::Record;
+use log::debug;
+use object_store::path::Path;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::RwLock;
+
+/// Records kept in memory, listed in the order of their paths.
+/// Clones share the records, so a test can tamper with what a node holds.
+#[derive(Clone, Default)]
+pub struct MemoryStorage {
+    records: Arc<RwLock<BTreeMap<Path, Record>>>,
+}
+
+fn to_object_path(path: &std::path::Path) -> Res<Path> {
+    Ok(Path::from(
+        path.to_str().ok_or(ErrorKind::InvalidRecordName)?,
+    ))
+}
+
+#[async_trait]
+impl IStorage for MemoryStorage {
+    async fn put(&self, data: Record) -> Res<()> {
+        let path = to_object_path(&key_to_path(&data.key)?)?;
+        debug!("storing: {}", path);
+        self.records.write().await.insert(path, data);
+        Ok(())
+    }
+
+    async fn get(&self, path: PathBuf) -> Res<Record> {
+        let path = to_object_path(&path)?;
+        debug!("retrieving: {}", path);
+        self.records
+            .read()
+            .await
+            .get(&path)
+            .cloned()
+            .ok_or_else(|| ErrorKind::RecordNotFound(path.to_string()).into())
+    }
+
+    async fn remove(&self, path: &Path) -> Res<()> {
+        debug!("removing: {}", path);
+        self.records
+            .write()
+            .await
+            .remove(path)
+            .map(|_| ())
+            .ok_or_else(|| ErrorKind::RecordNotFound(path.to_string()).into())
+    }
+
+    async fn list(&self) -> Res<Vec<Path>> {
+        Ok(self.records.read().await.keys().cloned().collect())
+    }
+
+    async fn expiry(&self, path: &Path) -> Res<Option<Instant>> {
+        self.records
+            .read()
+            .await
+            .get(path)
+            .map(|x| x.expires)
+            .ok_or_else(|| ErrorKind::RecordNotFound(path.to_string()).into())
+    }
+
+    async fn size(&self, path: &Path) -> Res<usize> {
+        self.records
+            .read()
+            .await
+            .get(path)
+            .map(|x| x.value.len())
+            .ok_or_else(|| ErrorKind::RecordNotFound(path.to_string()).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use libp2p::kad::record::Key;
+
+    fn record(key: &str) -> Record {
+        Record::new(Key::new(&key), vec![1, 2, 3])
+    }
+
+    #[tokio::test]
+    async fn put_get_remove() {
+        let storage = MemoryStorage::default();
+        storage.put(record("b")).await.unwrap();
+        storage.put(record("a")).await.unwrap();
+        assert_eq!(
+            storage.list().await.unwrap(),
+            vec![Path::from("a"), Path::from("b")]
+        );
+        assert_eq!(
+            storage.get(PathBuf::from("a")).await.unwrap().value,
+            vec![1, 2, 3]
+        );
+
+        storage.remove(&Path::from("a")).await.unwrap();
+        assert!(storage.get(PathBuf::from("a")).await.is_err());
+        assert!(storage.remove(&Path::from("a")).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn clones_share_records() {
+        let storage = MemoryStorage::default();
+        storage.clone().put(record("a")).await.unwrap();
+        assert_eq!(storage.list().await.unwrap().len(), 1);
+    }
+}
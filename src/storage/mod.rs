@@ -3,8 +3,12 @@ use async_trait::async_trait;
 use libp2p::kad::Record;
 use object_store::path::Path;
 use std::path::PathBuf;
+use std::time::Instant;
 pub mod local;
+mod memory;
+pub mod sweeper;
 use self::local::LocalStorage;
+pub use self::memory::MemoryStorage;
 use crate::settings::{ISettings, Storage as StorageSettings};
 use runtime_injector::{
     interface, InjectError, InjectResult, Injector, RequestInfo, Service, ServiceFactory,
@@ -20,6 +24,10 @@ pub trait IStorage: Service {
     async fn get(&self, path: PathBuf) -> Res<Record>;
     async fn remove(&self, path: &Path) -> Res<()>;
     async fn list(&self) -> Res<Vec<Path>>;
+    /// When the record expires, read without loading its value.
+    async fn expiry(&self, path: &Path) -> Res<Option<Instant>>;
+    /// The size of the record's value in bytes, read without loading it.
+    async fn size(&self, path: &Path) -> Res<usize>;
 }
 
 pub struct StorageProvider;
@@ -40,6 +48,16 @@ impl ServiceFactory<()> for StorageProvider {
                     inner: Box::<Er>::new(err),
                 })?),
             StorageSettings::Docker => todo!(),
+            // shared with the test which builds the node, see `deps::memory_dependency_injector`
+            StorageSettings::Memory => Err(InjectError::ActivationFailed {
+                service_info: ServiceInfo::of::<MemoryStorage>(),
+                inner: Box::<Er>::new(
+                    ErrorKind::Generic(
+                        "memory storage has to be given to the injector".to_string(),
+                    )
+                    .into(),
+                ),
+            }),
         }
     }
 }
@@ -53,5 +71,6 @@ fn key_to_path(key: &Key) -> Res<PathBuf> {
 interface! {
     dyn IStorage = [
         LocalStorage,
+        MemoryStorage,
     ]
 }
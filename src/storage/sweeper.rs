@@ -0,0 +1,118 @@
+use super::IStorage;
+use crate::util::{consts, Res};
+use async_trait::async_trait;
+use log::{debug, info, warn};
+use runtime_injector::{
+    interface, InjectResult, Injector, RequestInfo, Service, ServiceFactory, Svc,
+};
+use std::time::Instant;
+
+interface! {
+    dyn ISweeper = [
+        Sweeper,
+    ]
+}
+
+pub struct SweeperProvider;
+impl ServiceFactory<()> for SweeperProvider {
+    type Result = Sweeper;
+
+    fn invoke(
+        &mut self,
+        injector: &Injector,
+        _request_info: &RequestInfo,
+    ) -> InjectResult<Self::Result> {
+        let storage: Svc<dyn IStorage> = injector.get()?;
+        Ok(Sweeper { storage })
+    }
+}
+
+#[async_trait]
+pub trait ISweeper: Service {
+    async fn start(&self) -> Res<()>;
+    /// Removes the expired records and returns how many. A record which
+    /// can't be read or removed is left for the next sweep.
+    async fn sweep(&self) -> Res<usize>;
+}
+
+/// Periodically removes the records whose expiry has passed.
+pub struct Sweeper {
+    storage: Svc<dyn IStorage>,
+}
+
+#[async_trait]
+impl ISweeper for Sweeper {
+    async fn start(&self) -> Res<()> {
+        loop {
+            tokio::time::sleep(consts::SWEEP_INTERVAL.unsigned_abs()).await;
+            match self.sweep().await {
+                Ok(0) => debug!("no expired records"),
+                Ok(removed) => info!("removed {} expired records", removed),
+                Err(e) => warn!("failed to sweep expired records: {}", e),
+            }
+        }
+    }
+
+    async fn sweep(&self) -> Res<usize> {
+        let now = Instant::now();
+        let mut removed = 0;
+        for path in self.storage.list().await? {
+            let expiry = match self.storage.expiry(&path).await {
+                Ok(expiry) => expiry,
+                Err(e) => {
+                    debug!("skipping unreadable record {}: {}", path, e);
+                    continue;
+                }
+            };
+            if expiry.map_or(false, |expiry| expiry <= now) {
+                debug!("removing expired record {}", path);
+                match self.storage.remove(&path).await {
+                    Ok(()) => removed += 1,
+                    Err(e) => warn!("failed to remove expired record {}: {}", path, e),
+                }
+            }
+        }
+        Ok(removed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::MemoryStorage;
+    use crate::util::types::record_expiry;
+    use libp2p::kad::{record::Key, Record};
+    use object_store::path::Path;
+    use std::time::Duration;
+
+    fn record(key: &str, expires: Option<Instant>) -> Record {
+        let mut record = Record::new(Key::new(&key), vec![1, 2, 3]);
+        record.expires = expires;
+        record
+    }
+
+    #[tokio::test]
+    async fn only_expired_records_are_removed() {
+        let storage = MemoryStorage::default();
+        let past = Instant::now().checked_sub(Duration::from_secs(1));
+        storage.put(record("expired", past)).await.unwrap();
+        storage
+            .put(record("fresh", record_expiry(3600)))
+            .await
+            .unwrap();
+        storage
+            .put(record("forever", record_expiry(0)))
+            .await
+            .unwrap();
+        let sweeper = Sweeper {
+            storage: Svc::new(storage.clone()),
+        };
+
+        assert_eq!(sweeper.sweep().await.unwrap(), 1);
+        assert_eq!(
+            storage.list().await.unwrap(),
+            vec![Path::from("forever"), Path::from("fresh")]
+        );
+        assert_eq!(sweeper.sweep().await.unwrap(), 0);
+    }
+}
@@ -0,0 +1,659 @@
+//! Several full nodes in one process. They find each other over localhost,
+//! share one `MemoryLedger` and keep their records in a `MemoryStorage`, so
+//! neither immudb nor the filesystem is needed. Verifiers are not started,
+//! tests run the audits themselves, one round at a time.
+
+use crate::deps::memory_dependency_injector;
+use crate::grpc::IGrpcHandler;
+use crate::ledger::{ILedger, MemoryLedger};
+use crate::p2p::controller::ISwarmController;
+use crate::p2p::peer_id::{keypair_to_base64_proto, keypair_with_leading_zeros};
+use crate::p2p::swarm::ISwarm;
+use crate::settings::{
+    Grpc, Ledger, MaliciousBehavior, Por, Redundancy, Reputation, Selection, Settings, Storage,
+    Swarm, Verifier,
+};
+use crate::storage::sweeper::ISweeper;
+use crate::storage::{IStorage, MemoryStorage};
+use crate::util::consts::AUDITS_PER_CONTRACT;
+use crate::util::grpc::kiss_grpc::kiss_service_client::KissServiceClient;
+use crate::util::grpc::kiss_grpc::{
+    redundancy, GetClosestPeersRequest, Redundancy as RedundancyRequest, StoreRequest,
+};
+use crate::util::types::Contract;
+use crate::util::Res;
+use crate::verifier::IVerifier;
+use base64::Engine as _;
+use libp2p::PeerId;
+use libp2p_identity::Keypair;
+use rand::rngs::OsRng;
+use runtime_injector::Svc;
+use std::net::{Ipv4Addr, SocketAddr, SocketAddrV4};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tonic::transport::Channel;
+use uuid::Uuid;
+use x25519_dalek::{PublicKey, StaticSecret};
+
+const RETRIES: usize = 50;
+const RETRY_INTERVAL: Duration = Duration::from_millis(200);
+
+pub struct Node {
+    pub peer_id: PeerId,
+    pub storage: MemoryStorage,
+    pub verifier: Svc<dyn IVerifier>,
+    pub sweeper: Svc<dyn ISweeper>,
+    pub controller: Svc<dyn ISwarmController>,
+    grpc_addr: SocketAddr,
+    swarm_port: u16,
+    tasks: Vec<JoinHandle<Res<()>>>,
+}
+
+impl Drop for Node {
+    fn drop(&mut self) {
+        self.tasks.iter().for_each(|task| task.abort());
+    }
+}
+
+impl Node {
+    /// Connects to the grpc server of the node, once it's listening.
+    pub async fn client(&self) -> KissServiceClient<Channel> {
+        let address = format!("http://{}", self.grpc_addr);
+        for _ in 0..RETRIES {
+            if let Ok(client) = KissServiceClient::connect(address.clone()).await {
+                return client;
+            }
+            tokio::time::sleep(RETRY_INTERVAL).await;
+        }
+        KissServiceClient::connect(address).await.unwrap()
+    }
+}
+
+pub struct Network {
+    pub ledger: MemoryLedger,
+    pub nodes: Vec<Node>,
+}
+
+impl Network {
+    /// Starts `size` nodes, the first of which the others bootstrap from.
+    /// A single verifier makes a quorum, so one node can settle audits.
+    pub async fn start(size: usize) -> Self {
+        Self::start_with(size, 1, &[]).await
+    }
+
+    /// Like `start`, with `quorum` verifiers having to agree on every audit
+    /// and the nodes at the `corrupt` positions lying about theirs.
+    pub async fn start_with(size: usize, quorum: usize, corrupt: &[usize]) -> Self {
+        let ledger = MemoryLedger::default();
+        let auditor = StaticSecret::random_from_rng(OsRng);
+        let mut nodes: Vec<Node> = vec![];
+        let mut bootstrap = vec![];
+        for index in 0..size {
+            let keypair = keypair_with_leading_zeros(0);
+            let mut settings = settings(&keypair, &auditor, bootstrap.clone());
+            settings.verifier.quorum = quorum;
+            settings.verifier.corrupt = corrupt.contains(&index);
+            let node = start_node(&keypair, settings, ledger.clone()).await;
+            if bootstrap.is_empty() {
+                bootstrap.push(SocketAddr::V4(SocketAddrV4::new(
+                    Ipv4Addr::LOCALHOST,
+                    node.swarm_port,
+                )));
+            }
+            nodes.push(node);
+        }
+
+        let network = Self { ledger, nodes };
+        network.wait_for_peers().await;
+        network
+    }
+
+    /// Waits until the first node knows all the others, so that files
+    /// stored through it always land on the same number of peers.
+    async fn wait_for_peers(&self) {
+        let mut client = self.first().client().await;
+        let mut peers = 0;
+        for _ in 0..RETRIES {
+            peers = client
+                .get_closest_peers(GetClosestPeersRequest {
+                    uuid: Uuid::new_v4().to_string(),
+                })
+                .await
+                .map(|x| x.into_inner().peer_uuids.len())
+                .unwrap_or_default();
+            if peers + 1 >= self.nodes.len() {
+                return;
+            }
+            tokio::time::sleep(RETRY_INTERVAL).await;
+        }
+        assert!(
+            peers + 1 >= self.nodes.len(),
+            "the first node found {} of the other {} nodes",
+            peers,
+            self.nodes.len() - 1
+        );
+    }
+
+    pub fn first(&self) -> &Node {
+        self.nodes.first().unwrap()
+    }
+
+    /// Stores the content through the first node for an hour and returns
+    /// its uuid.
+    pub async fn store(&self, content: &[u8], replication_factor: u32) -> String {
+        self.store_for(content, replication_factor, 3600).await
+    }
+
+    /// Like `store`, for `ttl` seconds.
+    pub async fn store_for(&self, content: &[u8], replication_factor: u32, ttl: i64) -> String {
+        let file_uuid = self
+            .first()
+            .client()
+            .await
+            .store(StoreRequest {
+                name: "test".to_string(),
+                content: content.to_vec(),
+                ttl,
+                redundancy: Some(RedundancyRequest {
+                    mode: Some(redundancy::Mode::ReplicationFactor(replication_factor)),
+                }),
+            })
+            .await
+            .unwrap()
+            .into_inner()
+            .name;
+        self.wait_for_holders(&file_uuid).await;
+        file_uuid
+    }
+
+    /// Pushes a copy of the record to its holders, the way a kademlia
+    /// replication round does, and gives them time to store it.
+    pub async fn replicate(&self, key: &str, value: &[u8], holders: Vec<PeerId>) {
+        self.first()
+            .controller
+            .put_to(key.to_string(), value.to_vec(), None, holders)
+            .await
+            .unwrap();
+        tokio::time::sleep(RETRY_INTERVAL * 5).await;
+    }
+
+    /// Waits until every holder of the file keeps its record. Holders store
+    /// records in the background, after they acknowledged them.
+    pub async fn wait_for_holders(&self, file_uuid: &str) {
+        let contracts = self.contracts(file_uuid).await;
+        for contract in contracts {
+            let storage = &self.node(contract.peer_id).storage;
+            let path = contract.record_key();
+            for _ in 0..RETRIES {
+                if storage.get(path.clone().into()).await.is_ok() {
+                    break;
+                }
+                tokio::time::sleep(RETRY_INTERVAL).await;
+            }
+            assert!(
+                storage.get(path.clone().into()).await.is_ok(),
+                "{} doesn't keep {}",
+                contract.peer_id,
+                path
+            );
+        }
+    }
+
+    pub fn node(&self, peer_id: PeerId) -> &Node {
+        self.nodes.iter().find(|x| x.peer_id == peer_id).unwrap()
+    }
+
+    pub async fn contracts(&self, file_uuid: &str) -> Vec<Contract> {
+        self.ledger
+            .clone()
+            .get_contracts(file_uuid.to_string())
+            .await
+            .unwrap()
+    }
+
+    pub async fn reputation(&self, peer_id: PeerId) -> i64 {
+        self.ledger.clone().get_reputation(peer_id).await.unwrap()
+    }
+
+    /// Runs one audit round on the node. Only the auditor is alive as a
+    /// verifier, so the round covers every contract.
+    pub async fn audit_all(&self, auditor: &Node) {
+        auditor.verifier.audit().await.unwrap();
+    }
+}
+
+fn settings(keypair: &Keypair, auditor: &StaticSecret, bootstrap: Vec<SocketAddr>) -> Settings {
+    let encode = |key: &[u8]| base64::engine::general_purpose::STANDARD_NO_PAD.encode(key);
+    Settings {
+        storage: Storage::Memory,
+        // the nodes pick their ports, see `start_node`
+        grpc: Grpc { port: 0 },
+        swarm: Swarm {
+            keypair: Some(keypair_to_base64_proto(keypair.clone())),
+            leading_zeros: 0,
+            port: 0,
+            bootstrap,
+        },
+        ledger: Ledger::Memory,
+        malicious_behavior: Some(MaliciousBehavior::None),
+        verifier: Verifier {
+            enabled: true,
+            corrupt: false,
+            auditor_secret_key: Some(encode(&auditor.to_bytes())),
+            audits_per_contract: AUDITS_PER_CONTRACT,
+            quorum: 1,
+        },
+        por: Por {
+            enabled: true,
+            auditor_public_key: Some(encode(PublicKey::from(auditor).as_bytes())),
+        },
+        redundancy: Redundancy::default(),
+        selection: Selection::default(),
+        reputation: Reputation::default(),
+    }
+}
+
+/// Starts the node and waits until it listens, to read back its ports.
+async fn start_node(keypair: &Keypair, settings: Settings, ledger: MemoryLedger) -> Node {
+    let storage = MemoryStorage::default();
+    let injector = memory_dependency_injector(settings, ledger, storage.clone()).unwrap();
+
+    let swarm: Svc<dyn ISwarm> = injector.get().unwrap();
+    let grpc_handler: Svc<dyn IGrpcHandler> = injector.get().unwrap();
+    let verifier: Svc<dyn IVerifier> = injector.get().unwrap();
+    let sweeper: Svc<dyn ISweeper> = injector.get().unwrap();
+    let controller: Svc<dyn ISwarmController> = injector.get().unwrap();
+    let tasks = vec![
+        tokio::spawn({
+            let swarm = swarm.clone();
+            async move { swarm.start().await }
+        }),
+        tokio::spawn({
+            let grpc_handler = grpc_handler.clone();
+            async move { grpc_handler.start().await }
+        }),
+    ];
+
+    for _ in 0..RETRIES {
+        if grpc_handler.local_addr().is_some() && swarm.listen_port().is_some() {
+            break;
+        }
+        tokio::time::sleep(RETRY_INTERVAL).await;
+    }
+    let grpc_addr = grpc_handler
+        .local_addr()
+        .expect("the grpc server isn't listening");
+    let swarm_port = swarm.listen_port().expect("the swarm isn't listening");
+
+    Node {
+        peer_id: PeerId::from(keypair.public()),
+        storage,
+        verifier,
+        sweeper,
+        controller,
+        grpc_addr,
+        swarm_port,
+        tasks,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::consts::{
+        AUDIT_PENALTY, AUDIT_QUORUM, AUDIT_REWARD, CONTRACT_STAKE, DISAGREEMENT_PENALTY,
+        INITIAL_REPUTATION, SLASH_PER_FAILED_AUDIT,
+    };
+    use crate::util::grpc::kiss_grpc::{
+        DeleteRequest, ErasureCoding, RenewLeaseRequest, RetrieveRequest,
+    };
+    use crate::util::types::shard_record_key;
+    use crate::verifier::partition::Ring;
+    use libp2p::kad::record::Key;
+    use libp2p::kad::Record;
+    use object_store::path::Path;
+    use std::time::Instant;
+    use time::OffsetDateTime;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn honest_holders_are_rewarded() {
+        let network = Network::start(3).await;
+        let file_uuid = network.store(b"some content", 2).await;
+
+        let contracts = network.contracts(&file_uuid).await;
+        assert_eq!(contracts.len(), 2);
+
+        network.audit_all(network.first()).await;
+        for contract in contracts {
+            assert_eq!(
+                network.reputation(contract.peer_id).await,
+                INITIAL_REPUTATION - CONTRACT_STAKE + AUDIT_REWARD
+            );
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn holder_which_drops_the_file_is_punished() {
+        let network = Network::start(3).await;
+        let file_uuid = network.store(b"some content", 2).await;
+
+        let contracts = network.contracts(&file_uuid).await;
+        assert_eq!(contracts.len(), 2);
+        let cheater = contracts.first().unwrap();
+        let honest = contracts.last().unwrap().peer_id;
+        let storage = &network.node(cheater.peer_id).storage;
+        for path in storage.list().await.unwrap() {
+            storage.remove(&path).await.unwrap();
+        }
+
+        network.audit_all(network.first()).await;
+        assert_eq!(
+            network.reputation(cheater.peer_id).await,
+            INITIAL_REPUTATION - CONTRACT_STAKE - AUDIT_PENALTY
+        );
+        assert_eq!(
+            network.reputation(honest).await,
+            INITIAL_REPUTATION - CONTRACT_STAKE + AUDIT_REWARD
+        );
+        // the failed audit costs part of the stake as well
+        assert_eq!(
+            network
+                .ledger
+                .clone()
+                .get_staked(cheater.peer_id)
+                .await
+                .unwrap(),
+            CONTRACT_STAKE - SLASH_PER_FAILED_AUDIT
+        );
+
+        let claims = network
+            .ledger
+            .clone()
+            .get_previous_verified(cheater.contract_uuid.clone())
+            .await
+            .unwrap();
+        assert_eq!(claims.len(), 1);
+        assert!(claims.iter().all(|x| !x.succeeded));
+
+        let audits = network
+            .ledger
+            .clone()
+            .get_peer_audits(cheater.peer_id, 0, i64::MAX)
+            .await
+            .unwrap();
+        assert_eq!(audits.len(), 1);
+        assert!(audits
+            .iter()
+            .all(|x| x.contract_uuid == cheater.contract_uuid && !x.succeeded));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn every_audit_cycle_is_logged() {
+        let network = Network::start(3).await;
+        let file_uuid = network.store(b"some content", 1).await;
+        let contracts = network.contracts(&file_uuid).await;
+        let contract = contracts.first().unwrap();
+
+        network.audit_all(network.first()).await;
+        network.audit_all(network.first()).await;
+
+        // the claim is replaced, the log keeps both audits
+        let claims = network
+            .ledger
+            .clone()
+            .get_previous_verified(contract.contract_uuid.clone())
+            .await
+            .unwrap();
+        assert_eq!(claims.len(), 1);
+        let audits = network
+            .ledger
+            .clone()
+            .get_contract_audits(contract.contract_uuid.clone(), 0, i64::MAX)
+            .await
+            .unwrap();
+        assert_eq!(audits.len(), 2);
+        assert!(audits
+            .iter()
+            .all(|x| x.verified_by_id == network.first().peer_id && x.succeeded));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn corrupt_verifier_is_outvoted() {
+        // the first node stores the file, so it's a verifier but not the holder
+        let network = Network::start_with(4, AUDIT_QUORUM, &[0]).await;
+        let file_uuid = network.store(b"some content", 1).await;
+        let contracts = network.contracts(&file_uuid).await;
+        let holder = contracts.first().unwrap();
+        let mut verifiers: Vec<&Node> = network
+            .nodes
+            .iter()
+            .filter(|x| x.peer_id != holder.peer_id)
+            .collect();
+        assert_eq!(verifiers.len(), 3);
+
+        // all of them are live from the start, so all of them audit the
+        // contract, and its leader audits last, once the others have claimed
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        for verifier in verifiers.iter() {
+            network
+                .ledger
+                .clone()
+                .record_verifier_heartbeat(verifier.peer_id, now)
+                .await
+                .unwrap();
+        }
+        let ring = Ring::new(&verifiers.iter().map(|x| x.peer_id).collect::<Vec<_>>());
+        let auditors = ring.auditors(&holder.contract_uuid, AUDITS_PER_CONTRACT);
+        let leader = *auditors.first().unwrap();
+        verifiers.sort_by_key(|x| x.peer_id == leader);
+        for verifier in verifiers.iter() {
+            verifier.verifier.audit().await.unwrap();
+        }
+
+        assert_eq!(
+            network.reputation(holder.peer_id).await,
+            INITIAL_REPUTATION - CONTRACT_STAKE + AUDIT_REWARD
+        );
+        let corrupt = network.first().peer_id;
+        assert_eq!(
+            network.reputation(corrupt).await,
+            INITIAL_REPUTATION - DISAGREEMENT_PENALTY
+        );
+        for honest in verifiers.iter().filter(|x| x.peer_id != corrupt) {
+            assert_eq!(network.reputation(honest.peer_id).await, INITIAL_REPUTATION);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn modified_shards_are_replaced_by_parity_shards() {
+        let network = Network::start(4).await;
+        let content = b"some content which is split into shards".to_vec();
+        let mut client = network.first().client().await;
+        let file_uuid = client
+            .store(StoreRequest {
+                name: "test".to_string(),
+                content: content.clone(),
+                ttl: 3600,
+                redundancy: Some(RedundancyRequest {
+                    mode: Some(redundancy::Mode::ErasureCoding(ErasureCoding {
+                        data_shards: 2,
+                        parity_shards: 1,
+                    })),
+                }),
+            })
+            .await
+            .unwrap()
+            .into_inner()
+            .name;
+        network.wait_for_holders(&file_uuid).await;
+
+        let contracts = network.contracts(&file_uuid).await;
+        let cheater = contracts
+            .iter()
+            .find(|x| x.shard.as_ref().is_some_and(|shard| shard.index == 0))
+            .unwrap();
+        network
+            .node(cheater.peer_id)
+            .storage
+            .put(Record::new(
+                Key::new(&shard_record_key(&file_uuid, 0)),
+                vec![0; 32],
+            ))
+            .await
+            .unwrap();
+
+        // holders only let verifiers and other holders fetch their shards
+        network
+            .ledger
+            .clone()
+            .record_verifier_heartbeat(
+                network.first().peer_id,
+                OffsetDateTime::now_utc().unix_timestamp(),
+            )
+            .await
+            .unwrap();
+        let retrieved = client
+            .retrieve(RetrieveRequest { name: file_uuid })
+            .await
+            .unwrap()
+            .into_inner()
+            .content;
+        assert_eq!(retrieved, content);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn deleted_files_are_removed_from_their_holders() {
+        let network = Network::start(3).await;
+        let file_uuid = network.store(b"some content", 2).await;
+        let contracts = network.contracts(&file_uuid).await;
+
+        let removals = network
+            .first()
+            .client()
+            .await
+            .delete(DeleteRequest {
+                file_uuid: file_uuid.clone(),
+            })
+            .await
+            .unwrap()
+            .into_inner()
+            .removals;
+        assert_eq!(removals.len(), 2);
+        assert!(removals.iter().all(|x| x.removed));
+        for contract in contracts {
+            let storage = &network.node(contract.peer_id).storage;
+            assert!(storage.get(file_uuid.clone().into()).await.is_err());
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn renewals_extend_every_holder_to_its_contract() {
+        let network = Network::start(3).await;
+        let file_uuid = network.store(b"some content", 2).await;
+
+        let mut client = network.first().client().await;
+        let response = client
+            .renew_lease(RenewLeaseRequest {
+                file_uuid: file_uuid.clone(),
+                extend_by: 60,
+            })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(response.ttl, 3600 + 60);
+        assert_eq!(response.renewals.len(), 2);
+        assert!(response.renewals.iter().all(|x| x.renewed));
+
+        let status = client
+            .renew_lease(RenewLeaseRequest {
+                file_uuid,
+                extend_by: i64::MAX,
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), tonic::Code::InvalidArgument);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn expired_files_are_swept_from_their_holders() {
+        let network = Network::start(3).await;
+        let file_uuid = network.store_for(b"some content", 2, 1).await;
+        let contracts = network.contracts(&file_uuid).await;
+        assert_eq!(contracts.len(), 2);
+
+        tokio::time::sleep(Duration::from_secs(2)).await;
+        for contract in contracts {
+            let holder = network.node(contract.peer_id);
+            assert_eq!(holder.sweeper.sweep().await.unwrap(), 1);
+            assert!(holder.storage.get(file_uuid.clone().into()).await.is_err());
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn replication_doesnt_bring_back_deleted_files() {
+        let network = Network::start(3).await;
+        let content = b"some content";
+        let file_uuid = network.store(content, 2).await;
+        let holders: Vec<PeerId> = network
+            .contracts(&file_uuid)
+            .await
+            .iter()
+            .map(|x| x.peer_id)
+            .collect();
+
+        network
+            .first()
+            .client()
+            .await
+            .delete(DeleteRequest {
+                file_uuid: file_uuid.clone(),
+            })
+            .await
+            .unwrap();
+        network
+            .replicate(&file_uuid, content, holders.clone())
+            .await;
+        for holder in holders {
+            let storage = &network.node(holder).storage;
+            assert!(storage.get(file_uuid.clone().into()).await.is_err());
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn replication_doesnt_undo_renewals() {
+        let network = Network::start(3).await;
+        let content = b"some content";
+        let file_uuid = network.store(content, 2).await;
+        let holders: Vec<PeerId> = network
+            .contracts(&file_uuid)
+            .await
+            .iter()
+            .map(|x| x.peer_id)
+            .collect();
+
+        network
+            .first()
+            .client()
+            .await
+            .renew_lease(RenewLeaseRequest {
+                file_uuid: file_uuid.clone(),
+                extend_by: 600,
+            })
+            .await
+            .unwrap();
+        network
+            .replicate(&file_uuid, content, holders.clone())
+            .await;
+        // the renewal moved the expiry 600s past the hour it was stored for
+        let renewed = Instant::now() + Duration::from_secs(3600 + 300);
+        for holder in holders {
+            let expiry = network
+                .node(holder)
+                .storage
+                .expiry(&Path::from(file_uuid.as_str()))
+                .await
+                .unwrap();
+            assert!(expiry.is_some_and(|expiry| expiry > renewed));
+        }
+    }
+}
@@ -9,7 +9,77 @@ pub const GRPC_TIMEOUT: u64 = 30;
 pub const DEFAULT_LEADING_ZEROS: usize = 2;
 pub const AUDIT_REWARD: i64 = 1;
 pub const AUDIT_PENALTY: i64 = 5;
+/// Reputation a peer starts with, so it can stake on its first contracts.
+pub const INITIAL_REPUTATION: i64 = 30;
+/// Reputation a holder stakes on every contract it accepts.
+pub const CONTRACT_STAKE: i64 = 3;
+/// Audit rewards stop adding reputation here.
+pub const REPUTATION_CEILING: i64 = 100;
+/// Peers whose reputation falls below this are banned.
+pub const BAN_BELOW: i64 = 0;
+/// Every interval reputation moves a step back towards `INITIAL_REPUTATION`.
+pub const REPUTATION_DECAY_INTERVAL: Duration = Duration::hours(1);
+pub const REPUTATION_DECAY_STEP: i64 = 1;
+pub const REPUTATION_UPDATE_ATTEMPTS: u32 = 5;
 pub const VERIFICATION_CYCLE_TIME: Duration = Duration::seconds(6);
-pub const NUM_PEERS: u128 = 3;
+/// Audits a verifier runs at the same time.
+pub const AUDIT_PARALLELISM: usize = 16;
+/// A holder which doesn't answer a challenge in time fails the audit.
+/// The least time it gets, larger files get longer, see
+/// `timing::challenge_timeout`.
+pub const CHALLENGE_TIMEOUT: Duration = Duration::seconds(2);
+/// How many times its response bound a holder gets to answer, so answers
+/// slow enough to be suspicious still arrive to be timed.
+pub const CHALLENGE_TIMEOUT_MARGIN: u32 = 2;
+/// Round trip assumed for holders which haven't answered a challenge yet.
+pub const DEFAULT_CHALLENGE_LATENCY: Duration = Duration::milliseconds(200);
+/// How many times its usual round trip a holder may take to answer.
+pub const LATENCY_TOLERANCE: u32 = 3;
+/// Time a holder needs per byte to go through its copy for a proof.
+pub const PROOF_NANOS_PER_BYTE: u64 = 10;
+/// Answers faster than this are never suspicious, whatever the history.
+pub const MIN_RESPONSE_BOUND: Duration = Duration::milliseconds(50);
+/// Verifiers without a heartbeat for this long get no contracts to audit.
+pub const VERIFIER_HEARTBEAT_TIMEOUT: Duration = Duration::seconds(18);
+/// Points every verifier has on the ring the contracts are split by.
+pub const VERIFIER_RING_POINTS: usize = 64;
+/// How many verifiers on the ring audit each contract per round.
+pub const AUDITS_PER_CONTRACT: usize = 3;
+/// Verifiers which have to agree on an audit before reputation moves.
+pub const AUDIT_QUORUM: usize = 2;
+/// Claims older than this don't count towards a decision.
+pub const SETTLEMENT_WINDOW: Duration = Duration::seconds(12);
+/// Reputation a verifier loses when its claim goes against the quorum.
+pub const DISAGREEMENT_PENALTY: i64 = 2;
 pub const DATABASE_NAME: &str = "kiss";
+/// Verifiers read the auditor secret key from this environment variable,
+/// so it doesn't have to be in a config file every node shares.
+pub const AUDITOR_SECRET_KEY_ENV: &str = "KISS_AUDITOR_SECRET_KEY";
 pub const REPLICATION_FACTOR: usize = 3;
+/// Extra lookups of random keys made when too few of the closest peers
+/// have enough reputation to hold a new file.
+pub const SELECTION_EXTRA_LOOKUPS: usize = 2;
+pub const REPAIR_AFTER_FAILED_AUDITS: u32 = 3;
+/// Staked reputation lost on every failed audit, on top of `AUDIT_PENALTY`.
+/// By the time a contract is repaired its whole stake is gone.
+pub const SLASH_PER_FAILED_AUDIT: i64 = CONTRACT_STAKE / REPAIR_AFTER_FAILED_AUDITS as i64;
+pub const STREAM_CHUNK_SIZE: usize = 1024 * 1024;
+/// The largest file a client can store.
+pub const MAX_FILE_SIZE: usize = 1024 * 1024 * 1024;
+/// Record requests only name the record they are about.
+pub const MAX_RECORD_REQUEST_SIZE: usize = 64 * 1024;
+/// Record responses carry at most a whole file, next to the name of its
+/// record.
+pub const MAX_RECORD_RESPONSE_SIZE: usize = MAX_FILE_SIZE + MAX_RECORD_REQUEST_SIZE;
+pub const SWEEP_INTERVAL: Duration = Duration::seconds(60);
+pub const BOOTSTRAP_INTERVAL: Duration = Duration::seconds(300);
+pub const RECORD_REPLICATION_INTERVAL: Duration = Duration::minutes(10);
+pub const RECORD_PUBLICATION_INTERVAL: Duration = Duration::hours(1);
+/// Larger records are left out of kademlia replication and republishing.
+/// They are kept on their holders by their contracts and repairs instead.
+pub const MAX_REPLICATED_RECORD_SIZE: usize = 1024 * 1024;
+/// How often a peer reads which records it holds active contracts for.
+/// Only those are replicated, see `LocalStore::records`.
+pub const HELD_RECORDS_REFRESH_INTERVAL: Duration = Duration::seconds(60);
+/// Changed provider records are written to disk at most this often.
+pub const PROVIDERS_PERSIST_INTERVAL: Duration = Duration::seconds(30);
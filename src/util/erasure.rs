@@ -0,0 +1,102 @@
+use crate::util::{types::Bytes, ErrorKind, Res};
+use reed_solomon_erasure::galois_8::ReedSolomon;
+
+/// Splits the content into `data_shards` equally sized shards, padding the
+/// last one with zeros, and appends `parity_shards` parity shards to them.
+pub fn encode(content: &[u8], data_shards: usize, parity_shards: usize) -> Res<Vec<Bytes>> {
+    let coder =
+        ReedSolomon::new(data_shards, parity_shards).map_err(ErrorKind::ErasureCodingError)?;
+    let shard_size = usize::max(1, (content.len() + data_shards - 1) / data_shards);
+
+    let mut shards: Vec<Bytes> = content.chunks(shard_size).map(|x| x.to_vec()).collect();
+    shards.resize(data_shards + parity_shards, vec![]);
+    for shard in shards.iter_mut() {
+        shard.resize(shard_size, 0);
+    }
+
+    coder
+        .encode(&mut shards)
+        .map_err(ErrorKind::ErasureCodingError)?;
+    Ok(shards)
+}
+
+/// Rebuilds the first `size` bytes of the content from the shards.
+/// Any `data_shards` of them are enough, the missing ones are `None`.
+pub fn reconstruct(
+    mut shards: Vec<Option<Bytes>>,
+    data_shards: usize,
+    parity_shards: usize,
+    size: usize,
+) -> Res<Bytes> {
+    let coder =
+        ReedSolomon::new(data_shards, parity_shards).map_err(ErrorKind::ErasureCodingError)?;
+    coder
+        .reconstruct_data(&mut shards)
+        .map_err(ErrorKind::ErasureCodingError)?;
+
+    let mut content: Bytes = shards
+        .into_iter()
+        .take(data_shards)
+        .flatten()
+        .flatten()
+        .collect();
+    content.truncate(size);
+    Ok(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn content(size: usize) -> Bytes {
+        (0..size).map(|x| (x % 251) as u8).collect()
+    }
+
+    fn without(shards: Vec<Bytes>, missing: &[usize]) -> Vec<Option<Bytes>> {
+        shards
+            .into_iter()
+            .enumerate()
+            .map(|(i, x)| (!missing.contains(&i)).then_some(x))
+            .collect()
+    }
+
+    #[test]
+    fn encode_makes_equal_shards() {
+        let shards = encode(&content(1000), 4, 2).unwrap();
+        assert_eq!(shards.len(), 6);
+        assert!(shards.iter().all(|x| x.len() == 250));
+    }
+
+    #[test]
+    fn reconstruct_from_all_shards() {
+        let original = content(1001);
+        let shards = without(encode(&original, 4, 2).unwrap(), &[]);
+        assert_eq!(reconstruct(shards, 4, 2, original.len()).unwrap(), original);
+    }
+
+    #[test]
+    fn reconstruct_with_missing_shards() {
+        let original = content(12345);
+        let shards = without(encode(&original, 4, 2).unwrap(), &[0, 3]);
+        assert_eq!(reconstruct(shards, 4, 2, original.len()).unwrap(), original);
+    }
+
+    #[test]
+    fn reconstruct_fails_with_too_few_shards() {
+        let original = content(100);
+        let shards = without(encode(&original, 4, 2).unwrap(), &[0, 1, 5]);
+        assert!(reconstruct(shards, 4, 2, original.len()).is_err());
+    }
+
+    #[test]
+    fn content_smaller_than_shard_count() {
+        let original = content(2);
+        let shards = without(encode(&original, 4, 2).unwrap(), &[1]);
+        assert_eq!(reconstruct(shards, 4, 2, original.len()).unwrap(), original);
+    }
+
+    #[test]
+    fn invalid_shard_counts() {
+        assert!(encode(&content(10), 0, 2).is_err());
+    }
+}
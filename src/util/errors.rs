@@ -1,4 +1,4 @@
-use crate::p2p::swarm::{QueryGetResponse, VerificationResponse};
+use crate::p2p::swarm::{QueryGetResponse, RecordResponse, VerificationResponse};
 use crate::util::grpc::immudb_grpc::SqlValue;
 use config::ConfigError;
 use error_chain::{error_chain, ExitCode};
@@ -105,6 +105,27 @@ error_chain! {
         SwarmReqResSendResponseError { display("swarm request response send response error") }
         InsufficientReputationToStake { display("insufficient reputation to stake") }
         InsufficientReputationToUnstake { display("insufficient reputation to unstake") }
+        RecordFetchFailed(peer: String) { display("fetching record from {} failed", peer) }
+        NoHealthyCopy(file_uuid: String) { display("no healthy copy of {} found", file_uuid) }
+        ShardModified(key: String) { display("shard {} doesn't match its contract", key) }
+        NoReplacementPeer(file_uuid: String) { display("no replacement peer for {} found", file_uuid) }
+        ErasureCodingError(e: reed_solomon_erasure::Error) { display("erasure coding failed: {}", e) }
+        AuditorKeyMissing { display("no auditor key configured") }
+        AuditorSecretOnStorageNode { display("the auditor secret key is set on a node which isn't a verifier") }
+        InvalidAuditorKey(e: String) { display("invalid auditor key: {}", e) }
+        SecretsSealingFailed { display("sealing contract secrets failed") }
+        SecretsUnsealingFailed { display("unsealing contract secrets failed") }
+        SecretsCommitmentMismatch { display("contract secrets don't match their commitment") }
+        LedgerChainBroken(sequence: u64) { display("ledger hash chain is broken at entry {}", sequence) }
+        RecordNotFound(path: String) { display("record {} not found", path) }
+        LedgerProofInvalid(reason: String) { display("ledger proof is invalid: {}", reason) }
+        LedgerHistoryDiverged(tx_id: u64) { display("ledger history diverged from the trusted state at transaction {}, the server was reset or tampered with", tx_id) }
+        LedgerValueMismatch(key: String) { display("ledger holds a different value for {} than was written", key) }
+        TransactionConflict { display("the transaction read values another transaction changed meanwhile") }
+        RenewalRefused(key: String) { display("refusing to renew {}, it's past the expiry of its contract here", key) }
+        RecordRefused(key: String) { display("refusing to store {}, it has no active contract here or a newer copy is kept", key) }
+        FetchRefused(key: String, peer: String) { display("refusing to let {} fetch {}, it's neither a verifier nor a holder", peer, key) }
+        RemovalRefused(key: String) { display("refusing to remove {}, the ledger has no terminated contract for it here", key) }
     }
 }
 
@@ -283,6 +304,12 @@ impl From<oneshot::Receiver<result::Result<VerificationResponse, Error>>> for Er
     }
 }
 
+impl From<oneshot::Receiver<result::Result<RecordResponse, Error>>> for Error {
+    fn from(_: oneshot::Receiver<result::Result<RecordResponse, Error>>) -> Self {
+        ErrorKind::SendReceiverFailed.into()
+    }
+}
+
 impl From<result::Result<QueryGetResponse, Error>> for Error {
     fn from(_: result::Result<QueryGetResponse, Error>) -> Self {
         ErrorKind::SendingResultFailed.into()
@@ -1,8 +1,10 @@
 pub mod consts;
 pub mod debug;
+pub mod erasure;
 mod errors;
 pub mod grpc;
 pub mod hasher;
+pub mod selection;
 pub mod types;
 
 pub use errors::die;
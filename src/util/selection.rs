@@ -0,0 +1,124 @@
+use crate::settings::SelectionPolicy;
+use libp2p::PeerId;
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::{thread_rng, Rng};
+use std::cmp::Reverse;
+
+/// Picks up to `needed` of the candidates with at least `min_reputation`,
+/// following the policy. The candidates come with their reputation, the
+/// closest ones to the file first.
+pub fn select(
+    policy: SelectionPolicy,
+    candidates: &[(PeerId, i64)],
+    min_reputation: i64,
+    needed: usize,
+) -> Vec<PeerId> {
+    let mut eligible: Vec<(PeerId, i64)> = candidates
+        .iter()
+        .filter(|(_, reputation)| *reputation >= min_reputation)
+        .copied()
+        .collect();
+    match policy {
+        SelectionPolicy::Closest => {}
+        // the sort is stable, so equally reputable peers stay closest first
+        SelectionPolicy::HighestReputation => {
+            eligible.sort_by_key(|(_, reputation)| Reverse(*reputation))
+        }
+        SelectionPolicy::WeightedRandom => {
+            eligible = weighted_shuffle(eligible, min_reputation, &mut thread_rng())
+        }
+    }
+    eligible
+        .into_iter()
+        .take(needed)
+        .map(|(peer, _)| peer)
+        .collect()
+}
+
+/// Orders the candidates randomly, each next one picked with a chance
+/// proportional to how far its reputation is over the threshold.
+fn weighted_shuffle(
+    mut candidates: Vec<(PeerId, i64)>,
+    min_reputation: i64,
+    rng: &mut impl Rng,
+) -> Vec<(PeerId, i64)> {
+    let mut shuffled = Vec::with_capacity(candidates.len());
+    while !candidates.is_empty() {
+        let weights = candidates
+            .iter()
+            .map(|(_, reputation)| reputation.saturating_sub(min_reputation).max(0) + 1);
+        match WeightedIndex::new(weights) {
+            Ok(index) => shuffled.push(candidates.swap_remove(index.sample(rng))),
+            Err(_) => break,
+        }
+    }
+    shuffled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    fn candidates(reputations: &[i64]) -> Vec<(PeerId, i64)> {
+        reputations.iter().map(|x| (PeerId::random(), *x)).collect()
+    }
+
+    fn peers(candidates: &[(PeerId, i64)], picked: &[usize]) -> Vec<PeerId> {
+        picked
+            .iter()
+            .filter_map(|x| candidates.get(*x))
+            .map(|(peer, _)| *peer)
+            .collect()
+    }
+
+    #[test]
+    fn closest_skips_peers_below_the_threshold() {
+        let candidates = candidates(&[5, -2, 7, 1, 9]);
+        assert_eq!(
+            select(SelectionPolicy::Closest, &candidates, 2, 3),
+            peers(&candidates, &[0, 2, 4])
+        );
+    }
+
+    #[test]
+    fn highest_reputation_breaks_ties_by_closeness() {
+        let candidates = candidates(&[5, 9, 7, 9, 1]);
+        assert_eq!(
+            select(SelectionPolicy::HighestReputation, &candidates, 0, 3),
+            peers(&candidates, &[1, 3, 2])
+        );
+    }
+
+    #[test]
+    fn too_few_eligible_peers_are_all_picked() {
+        let candidates = candidates(&[5, 0, 7]);
+        for policy in [
+            SelectionPolicy::Closest,
+            SelectionPolicy::HighestReputation,
+            SelectionPolicy::WeightedRandom,
+        ] {
+            let mut picked = select(policy, &candidates, 3, 3);
+            picked.sort();
+            let mut expected = peers(&candidates, &[0, 2]);
+            expected.sort();
+            assert_eq!(picked, expected);
+        }
+    }
+
+    #[test]
+    fn weighted_random_favours_reputable_peers() {
+        let candidates = candidates(&[0, 100]);
+        let mut rng = StdRng::seed_from_u64(7);
+        let reputable_first = (0..100)
+            .filter(|_| {
+                weighted_shuffle(candidates.clone(), 0, &mut rng)
+                    .first()
+                    .map(|(_, reputation)| *reputation)
+                    == Some(100)
+            })
+            .count();
+        assert!(reputable_first > 90, "{}", reputable_first);
+    }
+}
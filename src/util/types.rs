@@ -2,13 +2,16 @@ use core::fmt;
 use std::{
     collections::HashSet,
     fmt::{Display, Formatter},
+    time::{Duration, Instant},
 };
 
 use crate::{
-    p2p::swarm::{QueryGetResponse, VerificationResponse},
+    p2p::swarm::{QueryGetResponse, RecordResponse, VerificationResponse},
+    util::hasher::hash,
     Res,
 };
 use libp2p_identity::PeerId;
+use serde::{Deserialize, Serialize};
 use tokio::sync::oneshot;
 use uuid::Uuid;
 
@@ -26,11 +29,13 @@ pub enum CommandToSwarm {
     PutLocal {
         key: String,
         value: Bytes,
+        expires: Option<Instant>,
         resp: Responder<OneReceiver<Res<()>>>,
     },
     PutRemote {
         key: String,
         value: Bytes,
+        expires: Option<Instant>,
         remotes: Vec<PeerId>,
         resp: Responder<OneReceiver<Res<()>>>,
     },
@@ -52,6 +57,22 @@ pub enum CommandToSwarm {
         challenge_vector: Vec<u64>,
         resp: Responder<OneReceiver<Res<VerificationResponse>>>,
     },
+    RequestRemoval {
+        peer: PeerId,
+        file_uuid: String,
+        resp: Responder<OneReceiver<Res<RecordResponse>>>,
+    },
+    RequestFetch {
+        peer: PeerId,
+        file_uuid: String,
+        resp: Responder<OneReceiver<Res<RecordResponse>>>,
+    },
+    RequestRenewal {
+        peer: PeerId,
+        file_uuid: String,
+        expires_at: u64,
+        resp: Responder<OneReceiver<Res<RecordResponse>>>,
+    },
 }
 
 impl Display for CommandToSwarm {
@@ -66,11 +87,26 @@ impl Display for CommandToSwarm {
             CommandToSwarm::RequestVerification { peer, .. } => {
                 write!(f, "RequestVerification({})", peer)
             }
+            CommandToSwarm::RequestRemoval {
+                peer, file_uuid, ..
+            } => {
+                write!(f, "RequestRemoval({}, {})", peer, file_uuid)
+            }
+            CommandToSwarm::RequestFetch {
+                peer, file_uuid, ..
+            } => {
+                write!(f, "RequestFetch({}, {})", peer, file_uuid)
+            }
+            CommandToSwarm::RequestRenewal {
+                peer, file_uuid, ..
+            } => {
+                write!(f, "RequestRenewal({}, {})", peer, file_uuid)
+            }
         }
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Contract {
     pub contract_uuid: String,
     pub peer_id: PeerId,
@@ -78,16 +114,160 @@ pub struct Contract {
     pub file_hash: String,
     pub upload_date: i64,
     pub ttl: i64,
+    /// Only set on contracts made before the secrets were sealed.
     pub secret_n: Vec<u8>,
     pub secret_m: Vec<u8>,
     pub rows: i64,
     pub cols: i64,
+    pub terminated: bool,
+    /// Set when the contract is for one erasure coded shard of the file
+    /// rather than for a full copy.
+    pub shard: Option<Shard>,
+    /// How the proof of retrievability secrets were made, see
+    /// `verifier::por`. Newer versions keep the seed of the secrets here.
+    pub por_version: i64,
+    pub por_seed: u64,
+    /// The secrets encrypted for the auditors, see `verifier::secrets`.
+    pub sealed_secrets: Option<SealedSecrets>,
+    /// Reputation the holder staked on the contract and hasn't lost to
+    /// failed audits yet. It's returned when the contract is terminated.
+    // left out when there's none, so contracts written before stakes hash
+    // the same in the embedded ledger
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub stake: i64,
+}
+
+fn is_zero(x: &i64) -> bool {
+    *x == 0
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SealedSecrets {
+    pub ciphertext: Vec<u8>,
+    /// Hash of the plain secrets.
+    pub commitment: String,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Shard {
+    pub index: i64,
+    pub data_shards: i64,
+    pub parity_shards: i64,
+    /// Size of the whole file in bytes, without the padding of the shards.
+    pub file_size: i64,
+    /// Hash of the shard itself, empty for shards stored before it was
+    /// recorded.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub hash: String,
+}
+
+impl Shard {
+    /// Whether the content is this shard. Shards without a hash can't be
+    /// checked on their own, only the file they rebuild can.
+    pub fn holds(&self, content: &[u8]) -> bool {
+        self.hash.is_empty() || hash(content) == self.hash
+    }
+}
+
+/// The key a shard of a file is stored under.
+pub fn shard_record_key(file_uuid: &str, index: i64) -> String {
+    format!("{}.{}", file_uuid, index)
+}
+
+/// The file a record belongs to, whether it holds the file or a shard.
+pub fn record_file_uuid(key: &str) -> &str {
+    key.split('.').next().unwrap_or(key)
+}
+
+impl Contract {
+    /// The key the holder keeps the contracted data under.
+    pub fn record_key(&self) -> String {
+        match &self.shard {
+            Some(shard) => shard_record_key(&self.file_uuid, shard.index),
+            None => self.file_uuid.clone(),
+        }
+    }
+
+    /// Unix timestamp in seconds after which the contract is no longer valid.
+    /// A non-positive ttl means the contract never expires.
+    pub fn expires_at(&self) -> Option<i64> {
+        (self.ttl > 0).then(|| self.upload_date.saturating_add(self.ttl))
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        self.expires_at()
+            .map_or(false, |expires_at| expires_at <= now)
+    }
+}
+
+/// Converts a ttl in seconds into the expiry of a record.
+/// A non-positive ttl means the record never expires.
+pub fn record_expiry(ttl: i64) -> Option<Instant> {
+    (ttl > 0).then(|| Instant::now() + Duration::from_secs(ttl.unsigned_abs()))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct VerificationClaim {
     pub contract_uuid: String,
     pub verified_by_id: PeerId,
     pub verification_time: i64,
     pub succeeded: bool,
+    /// How long the holder took to answer, when it answered at all.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timing: Option<ResponseTiming>,
+}
+
+/// How long the holder took to answer the challenge of an audit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResponseTiming {
+    pub round_trip_ms: i64,
+    /// The longest an honest answer was expected to take.
+    pub bound_ms: i64,
+    /// The answer was slower than the bound, as if the holder fetched the
+    /// data from another peer first.
+    pub suspected_outsourcing: bool,
+}
+
+/// One challenge of a holder in the audit log. Unlike the claims, which
+/// keep only the last outcome per verifier, every challenge is kept.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    pub contract_uuid: String,
+    pub holder_id: PeerId,
+    pub verified_by_id: PeerId,
+    /// When the challenge was sent, in milliseconds since the epoch.
+    pub audited_at: i64,
+    pub succeeded: bool,
+    /// The round trip of the challenge, when the holder answered.
+    pub latency_ms: Option<i64>,
+    /// Why the audit failed, when it did.
+    pub failure: Option<AuditFailure>,
+}
+
+/// Why the holder failed an audit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AuditFailure {
+    /// No answer to the challenge within `CHALLENGE_TIMEOUT`.
+    Timeout,
+    /// The challenge couldn't be delivered or was refused.
+    Unreachable,
+    /// The answer doesn't prove the holder has the data.
+    InvalidProof,
+}
+
+impl AuditFailure {
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Timeout => "timeout",
+            Self::Unreachable => "unreachable",
+            Self::InvalidProof => "invalid_proof",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [Self::Timeout, Self::Unreachable, Self::InvalidProof]
+            .into_iter()
+            .find(|x| x.name() == name)
+    }
 }
@@ -0,0 +1,105 @@
+use crate::util::types::{Bytes, VerificationClaim};
+use libp2p::PeerId;
+
+/// The outcome the verifiers last agreed on for a contract.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Settlement {
+    /// The time of the newest claim it was decided from. Only newer claims
+    /// count towards the next one.
+    pub settled_at: i64,
+    pub succeeded: bool,
+}
+
+impl Settlement {
+    pub fn to_bytes(self) -> Bytes {
+        let mut bytes = self.settled_at.to_be_bytes().to_vec();
+        bytes.push(self.succeeded as u8);
+        bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        let (succeeded, settled_at) = bytes.split_last()?;
+        Some(Self {
+            settled_at: i64::from_be_bytes(settled_at.try_into().ok()?),
+            succeeded: *succeeded != 0,
+        })
+    }
+}
+
+/// The outcome at least `quorum` of the claims agree on, if it's also what
+/// most of them say.
+pub fn decide(claims: &[VerificationClaim], quorum: usize) -> Option<bool> {
+    let passed = claims.iter().filter(|x| x.succeeded).count();
+    let failed = claims.len() - passed;
+    if passed >= quorum && passed > failed {
+        Some(true)
+    } else if failed >= quorum && failed > passed {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// The verifiers whose claims went against the outcome.
+pub fn dissenters(claims: &[VerificationClaim], succeeded: bool) -> Vec<PeerId> {
+    claims
+        .iter()
+        .filter(|x| x.succeeded != succeeded)
+        .map(|x| x.verified_by_id)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn claims(outcomes: &[bool]) -> Vec<VerificationClaim> {
+        outcomes
+            .iter()
+            .map(|succeeded| VerificationClaim {
+                contract_uuid: "contract".to_string(),
+                verified_by_id: PeerId::random(),
+                verification_time: 0,
+                succeeded: *succeeded,
+                timing: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn the_majority_decides_once_there_is_a_quorum() {
+        assert_eq!(decide(&claims(&[true, true, false]), 2), Some(true));
+        assert_eq!(decide(&claims(&[false, true, false]), 2), Some(false));
+        assert_eq!(decide(&claims(&[true]), 2), None);
+        assert_eq!(decide(&claims(&[true, false]), 1), None);
+        assert_eq!(decide(&claims(&[false]), 1), Some(false));
+    }
+
+    #[test]
+    fn dissenters_are_the_minority() {
+        let claims = claims(&[true, false, true]);
+        let dissenters = dissenters(&claims, true);
+        assert_eq!(
+            dissenters,
+            claims
+                .iter()
+                .filter(|x| !x.succeeded)
+                .map(|x| x.verified_by_id)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(dissenters.len(), 1);
+    }
+
+    #[test]
+    fn settlements_round_trip_through_bytes() {
+        let settlement = Settlement {
+            settled_at: 1_700_000_000_123,
+            succeeded: false,
+        };
+        assert_eq!(
+            Settlement::from_bytes(&settlement.to_bytes()),
+            Some(settlement)
+        );
+        assert_eq!(Settlement::from_bytes(&[1, 2]), None);
+    }
+}
@@ -1,13 +1,21 @@
+pub mod consensus;
+pub mod partition;
 pub mod por;
+pub mod secrets;
+pub mod timing;
 
-use crate::ledger::{ILedger, ImmuLedger};
+use crate::ledger::ILedger;
 use crate::p2p::controller::ISwarmController;
+use crate::repair::IRepairer;
+use crate::reputation::ReputationModel;
 use crate::settings::ISettings;
 use crate::util::debug::print_now;
+use crate::util::types::{AuditEntry, AuditFailure, Contract, ResponseTiming};
 use crate::util::{consts, Res};
 use crate::util::{Er, ErrorKind};
 use async_trait::async_trait;
 use base64::Engine as _;
+use futures::stream::{self, StreamExt};
 use libp2p::PeerId;
 use libp2p_identity::Keypair;
 use log::{debug, info, warn};
@@ -15,13 +23,26 @@ use runtime_injector::{
     interface, InjectError, InjectResult, Injector, RequestInfo, Service, ServiceFactory,
     ServiceInfo, Svc,
 };
-use std::str::FromStr;
+use std::collections::HashMap;
 use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use time::{Duration, OffsetDateTime, Time};
 use tokio::sync::Mutex;
-use uuid::Uuid;
 
+use self::consensus::Settlement;
+use self::partition::Ring;
 use self::por::{VerificationClient, VerificationClientConfig};
+use self::secrets::AuditorKeys;
+use self::timing::Latencies;
+
+/// The key holding the last outcome the verifiers agreed on for the contract.
+fn settlement_key(contract_uuid: &str) -> String {
+    format!("audit.settlement.{}", contract_uuid)
+}
+
+/// The key holding up to when the reputation of the peer has decayed.
+fn decayed_at_key(peer_id: PeerId) -> String {
+    format!("reputation.decayed_at.{}", peer_id)
+}
 
 interface! {
     dyn IVerifier = [
@@ -38,9 +59,11 @@ impl ServiceFactory<()> for VerifierProvider {
         injector: &Injector,
         _request_info: &RequestInfo,
     ) -> InjectResult<Self::Result> {
-        let ledger: Svc<Mutex<ImmuLedger>> = injector.get()?;
+        let ledger: Svc<Mutex<Box<dyn ILedger>>> = injector.get()?;
         let swarm_controller = injector.get::<Svc<dyn ISwarmController>>()?;
         let settings: Svc<dyn ISettings> = injector.get()?;
+        let repairer = injector.get::<Svc<dyn IRepairer>>()?;
+        let auditor_keys = injector.get::<Svc<AuditorKeys>>()?;
 
         let local_key = match settings.swarm().keypair {
             Some(keypair) => Keypair::from_protobuf_encoding(
@@ -70,11 +93,16 @@ impl ServiceFactory<()> for VerifierProvider {
         Ok(Verifier {
             ledger,
             swarm_controller,
+            repairer,
+            auditor_keys,
+            failed_audits: Mutex::new(HashMap::new()),
+            latencies: Mutex::new(Latencies::default()),
             iteration: Mutex::new(1),
-            starting_uuid: Mutex::new(0),
-            ending_uuid: Mutex::new(u128::MAX / consts::NUM_PEERS),
+            audits_per_contract: settings.verifier().audits_per_contract,
+            quorum: settings.verifier().quorum,
             peer_id: local_peer_id,
             corrupt: settings.verifier().corrupt,
+            reputation: ReputationModel::new(settings.reputation()),
         })
     }
 }
@@ -82,182 +110,510 @@ impl ServiceFactory<()> for VerifierProvider {
 #[async_trait]
 pub trait IVerifier: Service {
     async fn start(&self) -> Res<()>; // TALK can't be mut
+    /// Audits the contracts of the current round once.
+    async fn audit(&self) -> Res<()>;
 }
 
 pub struct Verifier {
-    ledger: Svc<Mutex<ImmuLedger>>,
+    ledger: Svc<Mutex<Box<dyn ILedger>>>,
     swarm_controller: Svc<dyn ISwarmController>,
+    repairer: Svc<dyn IRepairer>,
+    auditor_keys: Svc<AuditorKeys>,
+    /// Consecutive failed audits per contract.
+    failed_audits: Mutex<HashMap<String, u32>>,
+    latencies: Mutex<Latencies>,
     iteration: Mutex<u128>,
-    starting_uuid: Mutex<u128>,
-    ending_uuid: Mutex<u128>,
+    /// How many verifiers audit every contract each cycle.
+    audits_per_contract: usize,
+    /// How many of them have to agree before the holder is rewarded or
+    /// punished.
+    quorum: usize,
     peer_id: PeerId,
     corrupt: bool,
+    reputation: ReputationModel,
 }
 
 #[async_trait]
 impl IVerifier for Verifier {
     async fn start(&self) -> Res<()> {
         loop {
-            let contracts = {
-                let mut ledger = self.ledger.lock().await;
-                ledger.get_all_contracts().await?
-            };
             let time_before_start = Instant::now();
+            self.audit().await?;
 
-            let starting_uuid = *self.starting_uuid.lock().await;
-            let ending_uuid = *self.ending_uuid.lock().await;
-            let mut audits = vec![];
-            for contract in contracts {
-                let uuid_uint = Uuid::from_str(contract.file_uuid.as_str())
-                    .unwrap_or_default()
-                    .as_u128();
-                if uuid_uint < starting_uuid || uuid_uint > ending_uuid {
-                    debug!("skipping: {}", contract.file_uuid);
-                    continue;
-                }
+            tokio::time::sleep_until(tokio::time::Instant::from_std(
+                time_before_start + consts::VERIFICATION_CYCLE_TIME,
+            ))
+            .await;
+        }
+    }
 
-                let verification_client =
-                    VerificationClient::new(VerificationClientConfig::from_contract(&contract));
-                let challenge = verification_client.make_challenge_vector();
-                let response = self
-                    .swarm_controller
-                    .request_verification(
-                        contract.peer_id,
-                        contract.file_uuid.clone(),
-                        challenge.clone(),
-                    )
-                    .await;
-
-                let mut is_success = false;
-                match response {
-                    Ok(response) => match verification_client.audit(challenge, response) {
-                        true => {
-                            self.reward_peer(contract.peer_id).await;
-                            audits.push((contract.contract_uuid.clone(), true));
-                            is_success = true;
-                        }
-                        false => {
-                            // print_now(format!("audit failed for: {}", contract.file_uuid).as_str());
-                            self.punish_peer(contract.peer_id).await;
-                            audits.push((contract.contract_uuid.clone(), false));
-                        }
-                    },
-                    Err(_) => {
-                        // print_now(format!("audit failed for: {}", contract.file_uuid).as_str());
-                        self.punish_peer(contract.peer_id).await;
-                        audits.push((contract.contract_uuid.clone(), false));
-                    }
-                }
+    async fn audit(&self) -> Res<()> {
+        let deadline = Instant::now() + consts::VERIFICATION_CYCLE_TIME;
+        let contracts = {
+            let mut ledger = self.ledger.lock().await;
+            ledger.get_active_contracts().await?
+        };
+        let unix_now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs() as i64;
 
-                let mut previous = self
+        let ring = self.live_ring().await?;
+        let mut due = vec![];
+        // contracts this verifier settles, with how many verifiers audit them
+        let mut led = vec![];
+        for contract in contracts {
+            let auditors = ring.auditors(&contract.contract_uuid, self.audits_per_contract);
+            if !auditors.contains(&self.peer_id) {
+                debug!("skipping: {}", contract.contract_uuid);
+                continue;
+            }
+
+            if contract.is_expired(unix_now) {
+                info!("contract {} has expired", contract.contract_uuid);
+                if let Err(e) = self
                     .ledger
                     .lock()
                     .await
-                    .get_previous_verified(contract.contract_uuid.clone())
-                    .await?;
-                previous.sort_by(|a, b| a.verification_time.cmp(&b.verification_time).reverse());
-
-                if let Some(last) = previous.first() {
-                    let now = SystemTime::now();
-                    let last_time = UNIX_EPOCH + Duration::milliseconds(last.verification_time);
-                    let elapsed = now.duration_since(last_time)?;
-                    if is_success != last.succeeded {
-                        info!(
-                            "verifier {} caught cheating after {} ms",
-                            contract.peer_id,
-                            elapsed.as_millis()
-                        );
-                    }
+                    .terminate_contract(contract.contract_uuid.clone())
+                    .await
+                {
+                    warn!(
+                        "failed to terminate contract {}: {}",
+                        contract.contract_uuid, e
+                    );
                 }
+                continue;
+            }
+            if auditors.first() == Some(&self.peer_id) {
+                led.push((contract.clone(), auditors.len()));
             }
+            due.push(contract);
+        }
 
-            self.next_iteration().await;
-            info!(
-                "iteration: {}, successfully verified: {}, corrupted: {}",
-                *self.iteration.lock().await,
-                audits.iter().filter(|(_, succeeded)| *succeeded).count(),
-                audits.iter().filter(|(_, succeeded)| !*succeeded).count()
-            );
+        // a slow holder only holds up its own audit, and audits which can't
+        // start before the cycle is over are left for the next one
+        let outcomes: Vec<(Contract, i64, AuditOutcome, Option<ResponseTiming>)> =
+            stream::iter(due)
+                .map(|contract| async move {
+                    let audited_at = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .map_or(0, |x| x.as_millis() as i64);
+                    let (outcome, timing) = self.audit_contract(&contract, deadline).await;
+                    (contract, audited_at, outcome, timing)
+                })
+                .buffer_unordered(consts::AUDIT_PARALLELISM)
+                .collect()
+                .await;
 
-            for (contract_uuid, is_success) in audits {
-                let res = if !self.corrupt {
-                    self.ledger
-                        .lock()
-                        .await
-                        .create_verified_claim(contract_uuid, self.peer_id, is_success)
-                        .await
-                } else {
-                    self.ledger
-                        .lock()
-                        .await
-                        .create_verified_claim(contract_uuid, self.peer_id, !is_success)
-                        .await
-                };
-                match res {
-                    Ok(_) => {}
-                    Err(e) => {
-                        // warn!("failed to create verified claim: {}", e);
-                    }
-                }
+        let iteration = {
+            let mut iteration = self.iteration.lock().await;
+            *iteration += 1;
+            *iteration
+        };
+        let count =
+            |f: fn(&AuditOutcome) -> bool| outcomes.iter().filter(|(_, _, x, _)| f(x)).count();
+        info!(
+            "iteration: {}, successfully verified: {}, corrupted: {}, timed out: {}, skipped after overrun: {}, suspected outsourcing: {}",
+            iteration,
+            count(|x| matches!(x, AuditOutcome::Passed)),
+            count(|x| matches!(x, AuditOutcome::Failed(_))),
+            count(|x| matches!(x, AuditOutcome::Failed(AuditFailure::Timeout))),
+            count(|x| matches!(x, AuditOutcome::Skipped)),
+            outcomes
+                .iter()
+                .filter(|(_, _, _, timing)| timing.is_some_and(|x| x.suspected_outsourcing))
+                .count(),
+        );
+
+        for (contract, audited_at, outcome, timing) in outcomes {
+            let (is_success, failure) = match outcome {
+                AuditOutcome::Passed => (true, None),
+                AuditOutcome::Failed(reason) => (false, Some(reason)),
+                AuditOutcome::Skipped | AuditOutcome::Unauditable => continue,
+            };
+            // a corrupt verifier lies in both, so the claims and the log agree
+            let is_success = is_success != self.corrupt;
+            let entry = AuditEntry {
+                contract_uuid: contract.contract_uuid.clone(),
+                holder_id: contract.peer_id,
+                verified_by_id: self.peer_id,
+                audited_at,
+                succeeded: is_success,
+                latency_ms: timing.map(|x| x.round_trip_ms),
+                failure: failure.filter(|_| !is_success),
+            };
+            let mut ledger = self.ledger.lock().await;
+            if let Err(e) = ledger.append_audit(entry).await {
+                warn!(
+                    "failed to log the audit of {}: {}",
+                    contract.contract_uuid, e
+                );
+            }
+            if let Err(e) = ledger
+                .create_verified_claim(
+                    contract.contract_uuid.clone(),
+                    self.peer_id,
+                    is_success,
+                    timing,
+                )
+                .await
+            {
+                warn!(
+                    "failed to create verified claim for {}: {}",
+                    contract.contract_uuid, e
+                );
             }
+        }
 
-            tokio::time::sleep_until(tokio::time::Instant::from_std(
-                time_before_start + consts::VERIFICATION_CYCLE_TIME,
-            ))
-            .await;
+        // too few live verifiers to make a quorum, a single one would decide
+        let (led, short): (Vec<_>, Vec<_>) = led
+            .into_iter()
+            .partition(|(_, auditors)| *auditors >= self.quorum);
+        if let Some((_, auditors)) = short.first() {
+            warn!(
+                "not settling {} contracts: {} live auditors can't make a quorum of {}",
+                short.len(),
+                auditors,
+                self.quorum
+            );
+        }
+        for (contract, _) in led {
+            if let Err(e) = self.settle(&contract).await {
+                warn!("failed to settle {}: {}", contract.contract_uuid, e);
+            }
         }
+        Ok(())
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum AuditOutcome {
+    Passed,
+    Failed(AuditFailure),
+    /// Not started before the cycle ran out.
+    Skipped,
+    /// The secrets of the contract can't be read, which isn't the fault of
+    /// the holder.
+    Unauditable,
+}
+
 impl Verifier {
-    pub async fn punish_peer(&self, peer_id: PeerId) {
-        let rep = self
-            .ledger
+    /// Challenges the holder of the contract. Nothing is decided from the
+    /// outcome until enough verifiers agree on it, see `settle`.
+    /// Returns how long the holder took when it answered.
+    async fn audit_contract(
+        &self,
+        contract: &Contract,
+        deadline: Instant,
+    ) -> (AuditOutcome, Option<ResponseTiming>) {
+        if Instant::now() >= deadline {
+            return (AuditOutcome::Skipped, None);
+        }
+        let client_config = match VerificationClientConfig::from_contract(
+            contract,
+            self.auditor_keys.secret.as_ref(),
+        ) {
+            Ok(client_config) => client_config,
+            Err(e) => {
+                // not the fault of the holder, so it isn't punished
+                warn!(
+                    "can't read the secrets of {}: {}",
+                    contract.contract_uuid, e
+                );
+                return (AuditOutcome::Unauditable, None);
+            }
+        };
+        let verification_client = VerificationClient::new(client_config);
+        let challenge = verification_client.make_challenge_vector();
+        let usual = self.latencies.lock().await.usual(&contract.peer_id);
+        let timeout = timing::challenge_timeout(
+            VerificationClientConfig::proven_bytes(contract.rows, contract.cols),
+            usual,
+        );
+        let started = Instant::now();
+        let response = tokio::time::timeout(
+            timeout,
+            self.swarm_controller.request_verification(
+                contract.peer_id,
+                contract.record_key(),
+                challenge.clone(),
+            ),
+        )
+        .await;
+        let timing = match response {
+            Ok(Ok(_)) => Some(self.time_response(contract, started).await),
+            _ => None,
+        };
+
+        let outcome = match response {
+            Ok(Ok(response)) => match verification_client.audit(challenge, response) {
+                true => AuditOutcome::Passed,
+                false => AuditOutcome::Failed(AuditFailure::InvalidProof),
+            },
+            Ok(Err(_)) => AuditOutcome::Failed(AuditFailure::Unreachable),
+            Err(_) => AuditOutcome::Failed(AuditFailure::Timeout),
+        };
+        match outcome {
+            AuditOutcome::Failed(reason) => debug!(
+                "{} failed the audit of {}: {:?}",
+                contract.peer_id, contract.contract_uuid, reason
+            ),
+            AuditOutcome::Passed => {
+                if let Some(timing) = timing.filter(|x| !x.suspected_outsourcing) {
+                    self.latencies.lock().await.record(
+                        contract.peer_id,
+                        std::time::Duration::from_millis(timing.round_trip_ms.unsigned_abs()),
+                    );
+                }
+            }
+            AuditOutcome::Skipped | AuditOutcome::Unauditable => {}
+        }
+        (outcome, timing)
+    }
+
+    /// Decides the outcome of the audits of the contract once `quorum` of its
+    /// auditors agree on it, then rewards or punishes the holder and
+    /// penalises the verifiers which claimed otherwise. Only claims newer
+    /// than the last decision and from verifiers which aren't banned count.
+    async fn settle(&self, contract: &Contract) -> Res<()> {
+        let key = settlement_key(&contract.contract_uuid);
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_millis() as i64;
+        let (last, claims) = {
+            let mut ledger = self.ledger.lock().await;
+            let last = match ledger.verified_get(key.clone()).await {
+                Ok(value) => Some(
+                    Settlement::from_bytes(&value)
+                        .ok_or_else(|| ErrorKind::LedgerValueMismatch(key.clone()))?,
+                ),
+                Err(e) if matches!(e.kind(), ErrorKind::RecordNotFound(_)) => None,
+                Err(e) => return Err(e),
+            };
+            let since = i64::max(
+                last.map_or(i64::MIN, |x| x.settled_at + 1),
+                now - consts::SETTLEMENT_WINDOW.whole_milliseconds() as i64,
+            );
+            let mut claims = vec![];
+            for claim in ledger
+                .get_previous_verified(contract.contract_uuid.clone())
+                .await?
+            {
+                if claim.verification_time < since {
+                    continue;
+                }
+                let reputation = ledger.get_total_reputation(claim.verified_by_id).await?;
+                if self.reputation.is_banned(reputation) {
+                    debug!("ignoring the claim of banned {}", claim.verified_by_id);
+                    continue;
+                }
+                claims.push(claim);
+            }
+            (last, claims)
+        };
+
+        let quorum = self.quorum.max(1);
+        let succeeded = match consensus::decide(&claims, quorum) {
+            Some(succeeded) => succeeded,
+            None => {
+                debug!(
+                    "no quorum of {} on {} yet, {} claims",
+                    quorum,
+                    contract.contract_uuid,
+                    claims.len()
+                );
+                return Ok(());
+            }
+        };
+        let settlement = Settlement {
+            settled_at: claims
+                .iter()
+                .map(|x| x.verification_time)
+                .max()
+                .unwrap_or(now),
+            succeeded,
+        };
+        // the decision goes first, so a failure can't apply it twice
+        self.ledger
             .lock()
             .await
-            .get_reputation(peer_id)
-            .await
-            .unwrap();
-        // time now in seconds:
-        let now = OffsetDateTime::now_utc().unix_timestamp();
-        info!("{} other peer rep: {}", now, rep);
+            .verified_set(key, settlement.to_bytes())
+            .await?;
 
-        let res = {
-            self.ledger
+        for verifier in consensus::dissenters(&claims, succeeded) {
+            warn!(
+                "verifier {} disagreed with the quorum on {}",
+                verifier, contract.contract_uuid
+            );
+            let res = {
+                self.ledger
+                    .lock()
+                    .await
+                    .decrease_reputation(verifier, self.reputation.disagreement_penalty())
+                    .await
+            };
+            debug!("penalising a dissenting verifier result: {:?}", res);
+        }
+
+        if succeeded {
+            self.reward_peer(contract.peer_id).await;
+            self.failed_audits
                 .lock()
                 .await
-                .decrease_reputation(peer_id, consts::AUDIT_PENALTY)
-                .await
-        };
+                .remove(&contract.contract_uuid);
+        } else {
+            self.punish_peer(contract.peer_id).await;
+            self.slash_stake(contract).await;
+            self.record_failed_audit(contract).await;
+        }
+
+        if let Some(last) = last.filter(|x| x.succeeded && !succeeded) {
+            info!(
+                "verifier {} caught cheating after {} ms",
+                contract.peer_id,
+                settlement.settled_at - last.settled_at
+            );
+        }
+        Ok(())
+    }
+
+    /// Compares the round trip of the challenge sent at `started` with what
+    /// the holder should take, and flags answers slow enough to have been
+    /// fetched from another replica.
+    async fn time_response(&self, contract: &Contract, started: Instant) -> ResponseTiming {
+        let round_trip = started.elapsed();
+        let usual = self.latencies.lock().await.usual(&contract.peer_id);
+        let bound = timing::response_bound(
+            VerificationClientConfig::proven_bytes(contract.rows, contract.cols),
+            usual,
+        );
+        let suspected_outsourcing = round_trip > bound;
+        if suspected_outsourcing {
+            warn!(
+                "{} took {} ms to answer for {}, over the {} ms bound, it may be fetching the data from another peer",
+                contract.peer_id,
+                round_trip.as_millis(),
+                contract.contract_uuid,
+                bound.as_millis()
+            );
+        }
+        ResponseTiming {
+            round_trip_ms: round_trip.as_millis() as i64,
+            bound_ms: bound.as_millis() as i64,
+            suspected_outsourcing,
+        }
+    }
+
+    pub async fn punish_peer(&self, peer_id: PeerId) {
+        let res = self.adjust_reputation(peer_id, false).await;
         debug!("decreasing reputation after punishment result: {:?}", res)
     }
 
     pub async fn reward_peer(&self, peer_id: PeerId) {
+        let res = self.adjust_reputation(peer_id, true).await;
+        debug!("increasing reputation after audit result: {:?}", res)
+    }
+
+    /// Applies the decay due since the last audit of the peer, then the
+    /// outcome of this one.
+    async fn adjust_reputation(&self, peer_id: PeerId, passed: bool) -> Res<()> {
+        let mut ledger = self.ledger.lock().await;
+        let before = ledger.get_total_reputation(peer_id).await?;
+
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let key = decayed_at_key(peer_id);
+        let (decayed_at, first_audit) = match ledger.verified_get(key.clone()).await {
+            Ok(value) => (
+                i64::from_be_bytes(
+                    value
+                        .try_into()
+                        .map_err(|_| ErrorKind::LedgerValueMismatch(key.clone()))?,
+                ),
+                false,
+            ),
+            // decay starts with the first audit
+            Err(e) if matches!(e.kind(), ErrorKind::RecordNotFound(_)) => (now, true),
+            Err(e) => return Err(e),
+        };
+        let decay = self.reputation.decay(before, decayed_at, now);
+        if first_audit || decay.decayed_at != decayed_at {
+            // the time goes first, so a failure can't apply the same decay twice
+            ledger
+                .verified_set(key, decay.decayed_at.to_be_bytes().to_vec())
+                .await?;
+        }
+        if decay.amount != 0 {
+            ledger.decay_reputation(peer_id, decay.amount).await?;
+        }
+        let reputation = before + decay.amount;
+
+        let after = if passed {
+            let reward = self.reputation.reward(reputation);
+            if reward > 0 {
+                ledger.increase_reputation(peer_id, reward).await?;
+            }
+            reputation + reward
+        } else {
+            let penalty = self.reputation.penalty();
+            ledger.decrease_reputation(peer_id, penalty).await?;
+            reputation - penalty
+        };
+        if self.reputation.is_banned(after) && !self.reputation.is_banned(before) {
+            warn!("banning {}, its reputation fell to {}", peer_id, after);
+        }
+        Ok(())
+    }
+
+    /// Takes part of the stake on the contract for every failed audit, so
+    /// the loss grows with the number of failures.
+    async fn slash_stake(&self, contract: &Contract) {
         let res = {
             self.ledger
                 .lock()
                 .await
-                .increase_reputation(peer_id, consts::AUDIT_REWARD)
+                .slash_contract(
+                    contract.contract_uuid.clone(),
+                    consts::SLASH_PER_FAILED_AUDIT,
+                )
                 .await
         };
-        debug!("increasing reputation after audit result: {:?}", res)
+        debug!("slashing the stake after a failed audit result: {:?}", res)
     }
 
-    async fn next_iteration(&self) {
-        let mut iteration = self.iteration.lock().await;
-        let mut starting_uuid = self.starting_uuid.lock().await;
-        let mut ending_uuid = self.ending_uuid.lock().await;
-        *iteration += 1;
-        if *iteration == consts::NUM_PEERS + 1 {
-            *iteration = 0;
-            *starting_uuid = 0;
-            *ending_uuid = *starting_uuid + u128::MAX / consts::NUM_PEERS;
-        } else if *iteration == consts::NUM_PEERS {
-            *starting_uuid += u128::MAX / consts::NUM_PEERS;
-            *ending_uuid = u128::MAX;
-        } else {
-            *starting_uuid += u128::MAX / consts::NUM_PEERS;
-            *ending_uuid = *starting_uuid + u128::MAX / consts::NUM_PEERS;
+    /// Repairs the contract once its holder fails enough audits in a row.
+    async fn record_failed_audit(&self, contract: &Contract) {
+        let failures = {
+            let mut failed_audits = self.failed_audits.lock().await;
+            let failures = failed_audits
+                .entry(contract.contract_uuid.clone())
+                .or_default();
+            *failures += 1;
+            *failures
+        };
+        if failures < consts::REPAIR_AFTER_FAILED_AUDITS {
+            return;
+        }
+
+        match self.repairer.repair(contract).await {
+            Ok(_) => {
+                self.failed_audits
+                    .lock()
+                    .await
+                    .remove(&contract.contract_uuid);
+            }
+            Err(e) => warn!("failed to repair {}: {}", contract.file_uuid, e),
+        }
+    }
+
+    /// Records that this verifier is alive and places the verifiers alive
+    /// lately on the ring.
+    async fn live_ring(&self) -> Res<Ring> {
+        let now = OffsetDateTime::now_utc().unix_timestamp();
+        let mut ledger = self.ledger.lock().await;
+        ledger.record_verifier_heartbeat(self.peer_id, now).await?;
+        let mut verifiers = ledger
+            .get_live_verifiers(now - consts::VERIFIER_HEARTBEAT_TIMEOUT.whole_seconds())
+            .await?;
+        if !verifiers.contains(&self.peer_id) {
+            verifiers.push(self.peer_id);
         }
+        debug!("{} live verifiers", verifiers.len());
+        Ok(Ring::new(&verifiers))
     }
 }
@@ -0,0 +1,107 @@
+use crate::util::consts;
+use libp2p::PeerId;
+use sha3::{Digest, Sha3_256};
+
+/// Splits the contracts among the live verifiers by consistent hashing.
+/// Every verifier sits on a ring at several points and a contract goes to
+/// the verifiers which follow it on the ring, so when a verifier joins or
+/// leaves only the contracts next to its points change hands.
+pub struct Ring {
+    /// Sorted by position.
+    points: Vec<(u64, PeerId)>,
+}
+
+impl Ring {
+    pub fn new(verifiers: &[PeerId]) -> Self {
+        let mut points: Vec<(u64, PeerId)> = verifiers
+            .iter()
+            .flat_map(|peer| {
+                (0..consts::VERIFIER_RING_POINTS)
+                    .map(move |i| (position(format!("{}.{}", peer, i).as_bytes()), *peer))
+            })
+            .collect();
+        points.sort();
+        points.dedup();
+        Self { points }
+    }
+
+    /// Up to `count` different verifiers which audit the contract.
+    pub fn auditors(&self, contract_uuid: &str, count: usize) -> Vec<PeerId> {
+        let key = position(contract_uuid.as_bytes());
+        let start = self.points.partition_point(|(x, _)| *x < key);
+        let mut auditors: Vec<PeerId> = vec![];
+        for (_, peer) in self
+            .points
+            .iter()
+            .skip(start)
+            .chain(self.points.iter().take(start))
+        {
+            if auditors.len() == count {
+                break;
+            }
+            if !auditors.contains(peer) {
+                auditors.push(*peer);
+            }
+        }
+        auditors
+    }
+}
+
+fn position(key: &[u8]) -> u64 {
+    Sha3_256::digest(key)
+        .iter()
+        .take(8)
+        .fold(0, |position, x| position << 8 | *x as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use uuid::Uuid;
+
+    fn contracts(count: usize) -> Vec<String> {
+        (0..count).map(|_| Uuid::new_v4().to_string()).collect()
+    }
+
+    #[test]
+    fn every_contract_gets_distinct_auditors() {
+        let verifiers: Vec<PeerId> = (0..5).map(|_| PeerId::random()).collect();
+        let ring = Ring::new(&verifiers);
+        for contract in contracts(100) {
+            let mut auditors = ring.auditors(&contract, 3);
+            auditors.sort();
+            auditors.dedup();
+            assert_eq!(auditors.len(), 3);
+        }
+        assert_eq!(ring.auditors("contract", 10).len(), 5);
+        assert!(Ring::new(&[]).auditors("contract", 1).is_empty());
+    }
+
+    #[test]
+    fn audits_are_spread_over_the_verifiers() {
+        let verifiers: Vec<PeerId> = (0..4).map(|_| PeerId::random()).collect();
+        let ring = Ring::new(&verifiers);
+        let contracts = contracts(1000);
+        for verifier in verifiers.iter() {
+            let audited = contracts
+                .iter()
+                .filter(|x| ring.auditors(x, 1).contains(verifier))
+                .count();
+            assert!((100..=400).contains(&audited), "{}", audited);
+        }
+    }
+
+    #[test]
+    fn only_the_contracts_of_a_leaving_verifier_move() {
+        let verifiers: Vec<PeerId> = (0..4).map(|_| PeerId::random()).collect();
+        let (leaving, staying) = verifiers.split_last().unwrap();
+        let before = Ring::new(&verifiers);
+        let after = Ring::new(staying);
+        for contract in contracts(200) {
+            let auditors = before.auditors(&contract, 1);
+            if !auditors.contains(leaving) {
+                assert_eq!(after.auditors(&contract, 1), auditors);
+            }
+        }
+    }
+}
@@ -1,6 +1,11 @@
 #![allow(clippy::all)]
 
-use crate::util::types::{Bytes, Contract};
+use crate::util::types::{Bytes, Contract, SealedSecrets};
+use crate::util::{ErrorKind, Res};
+use crate::verifier::secrets::{self, PorSecrets};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use x25519_dalek::{PublicKey, StaticSecret};
 
 const P_BITS: u64 = 57;
 const MIN_LOOP: usize = 8;
@@ -8,6 +13,12 @@ const P57: u64 = 144115188075855859;
 const TINYMT_MASK: u64 = 0x7fffffffffffffff;
 const BYTES_UNDER_P: usize = 7;
 const CHUNK_ALIGN: usize = 56;
+/// Contracts made before secrets were seeded per contract. Their secret
+/// vector was generated from `LEGACY_SEED`, so it's the same for every file.
+pub const POR_VERSION_LEGACY: i64 = 1;
+/// Contracts whose secret vector is generated from their own random seed.
+pub const POR_VERSION_SEEDED: i64 = 2;
+const LEGACY_SEED: u64 = 2020;
 
 #[derive(Debug)]
 pub struct VerificationClientConfig {
@@ -15,16 +26,37 @@ pub struct VerificationClientConfig {
     cols: usize,
     secret_m_vector: Vec<u64>,
     secret_n_vector: Vec<u64>,
+    version: i64,
+    seed: u64,
 }
 
 impl VerificationClientConfig {
-    pub fn from_contract(contract: &Contract) -> Self {
-        Self {
+    /// Sealed secrets can only be read with the auditor key, contracts made
+    /// before sealing keep them in plain text.
+    pub fn from_contract(contract: &Contract, auditor: Option<&StaticSecret>) -> Res<Self> {
+        let secrets = match (&contract.sealed_secrets, auditor) {
+            (Some(sealed), Some(auditor)) => secrets::unseal(sealed, auditor)?,
+            (Some(_), None) => Err(ErrorKind::AuditorKeyMissing)?,
+            (None, _) => PorSecrets {
+                secret_n: contract.secret_n.clone(),
+                secret_m: contract.secret_m.clone(),
+                seed: contract.por_seed,
+            },
+        };
+
+        let cols = contract.cols as usize;
+        let secret_m_vector = match contract.por_version {
+            POR_VERSION_SEEDED => Random::rand_vector(cols, secrets.seed),
+            _ => bytes_to_u64(&secrets.secret_m),
+        };
+        Ok(Self {
             rows: contract.rows as usize,
-            cols: contract.cols as usize,
-            secret_m_vector: bytes_to_u64(&contract.secret_m),
-            secret_n_vector: bytes_to_u64(&contract.secret_n),
-        }
+            cols,
+            secret_m_vector,
+            secret_n_vector: bytes_to_u64(&secrets.secret_n),
+            version: contract.por_version,
+            seed: secrets.seed,
+        })
     }
 
     pub fn from_contract_fields(secret_n: Bytes, secret_m: Bytes, rows: i64, cols: i64) -> Self {
@@ -33,25 +65,54 @@ impl VerificationClientConfig {
             cols: cols as usize,
             secret_m_vector: bytes_to_u64(&secret_m),
             secret_n_vector: bytes_to_u64(&secret_n),
+            version: POR_VERSION_LEGACY,
+            seed: LEGACY_SEED,
         }
     }
 
-    pub fn to_contract(&self) -> (Bytes, Bytes, i64, i64) {
-        (
-            u64_to_bytes(self.secret_n_vector.clone()),
-            u64_to_bytes(self.secret_m_vector.clone()),
-            self.rows as i64,
-            self.cols as i64,
+    /// How many bytes of the file the proofs go through.
+    pub fn proven_bytes(rows: i64, cols: i64) -> u64 {
+        (rows * cols).unsigned_abs() * BYTES_UNDER_P as u64
+    }
+
+    /// Rows and columns of the contract, which aren't secret.
+    pub fn dimensions(&self) -> (i64, i64) {
+        (self.rows as i64, self.cols as i64)
+    }
+
+    /// Encrypts the secrets so only the auditors can read them.
+    pub fn seal(&self, auditor: &PublicKey) -> Res<SealedSecrets> {
+        secrets::seal(
+            &PorSecrets {
+                secret_n: u64_to_bytes(self.secret_n_vector.clone()),
+                secret_m: u64_to_bytes(self.secret_m_vector.clone()),
+                seed: self.seed,
+            },
+            auditor,
         )
     }
 
+    pub fn version(&self) -> i64 {
+        self.version
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Makes the secrets of a new contract from a fresh random seed,
+    /// so no two contracts share them, even for the same file.
     pub fn from_file(file: &Vec<u8>) -> Self {
+        Self::from_file_with_seed(file, OsRng.next_u64())
+    }
+
+    pub fn from_file_with_seed(file: &Vec<u8>, seed: u64) -> Self {
         let num_chunks = 1 + (file.len() - 1) / BYTES_UNDER_P;
         let rows = (((num_chunks as f64).sqrt() / CHUNK_ALIGN as f64).ceil() * CHUNK_ALIGN as f64)
             as usize;
         let cols = 1 + (num_chunks - 1) / rows;
 
-        let vector_u = Random::rand_vector(cols, 2020);
+        let vector_u = Random::rand_vector(cols, seed);
 
         let mut partials1 = vec![0_u128; rows];
         let bytes_per_row = BYTES_UNDER_P * rows;
@@ -96,6 +157,8 @@ impl VerificationClientConfig {
             cols,
             secret_m_vector: vector_u,
             secret_n_vector: partials1.iter().map(|x| *x as u64).collect::<Vec<u64>>(),
+            version: POR_VERSION_SEEDED,
+            seed,
         }
     }
 }
@@ -140,8 +203,10 @@ impl VerificationClient {
         Self { config }
     }
 
+    /// Every challenge is drawn from a fresh seed, so a holder can't
+    /// answer it ahead of time.
     pub fn make_challenge_vector(&self) -> Vec<u64> {
-        Random::rand_vector(self.config.rows, 20)
+        Random::rand_vector(self.config.rows, OsRng.next_u64())
     }
 
     pub fn audit(&self, challenge: Vec<u64>, response: Vec<u64>) -> bool {
@@ -328,7 +393,7 @@ mod tests {
             as usize;
         let cols = 1 + (num_chunks - 1) / rows;
 
-        let vector_u = Random::rand_vector(cols, 2020);
+        let vector_u = Random::rand_vector(cols, LEGACY_SEED);
 
         let mut partials1 = vec![0_u128; rows];
         let bytes_per_row = BYTES_UNDER_P * rows;
@@ -373,6 +438,8 @@ mod tests {
             cols,
             secret_m_vector: vector_u,
             secret_n_vector: partials1.iter().map(|x| *x as u64).collect::<Vec<u64>>(),
+            version: POR_VERSION_LEGACY,
+            seed: LEGACY_SEED,
         };
 
         let server_config = VerificationServerConfig { rows, cols, file };
@@ -424,6 +491,114 @@ mod tests {
         assert!(audit(&client, &server));
     }
 
+    fn contract(config: &VerificationClientConfig) -> Contract {
+        let secret_n = u64_to_bytes(config.secret_n_vector.clone());
+        let secret_m = u64_to_bytes(config.secret_m_vector.clone());
+        let (rows, cols) = config.dimensions();
+        Contract {
+            contract_uuid: String::new(),
+            peer_id: libp2p_identity::PeerId::random(),
+            file_uuid: String::new(),
+            file_hash: String::new(),
+            upload_date: 0,
+            ttl: 0,
+            secret_n,
+            secret_m,
+            rows,
+            cols,
+            terminated: false,
+            shard: None,
+            por_version: config.version(),
+            por_seed: config.seed(),
+            sealed_secrets: None,
+            stake: 0,
+        }
+    }
+
+    #[test]
+    fn test_secrets_differ_per_contract() {
+        let file = "abcdefghijklmnopqrstuvwxyz".as_bytes().to_vec();
+        let first = VerificationClientConfig::from_file(&file);
+        let second = VerificationClientConfig::from_file(&file);
+        assert_ne!(first.seed(), second.seed());
+        assert_ne!(first.secret_m_vector, second.secret_m_vector);
+        assert_ne!(first.secret_n_vector, second.secret_n_vector);
+    }
+
+    #[test]
+    fn test_audit_from_seeded_contract() {
+        let file = "abcdefghijklmnopqrstuvwxyz".repeat(100).as_bytes().to_vec();
+        let config = VerificationClientConfig::from_file(&file);
+        let mut contract = contract(&config);
+        // the secret vector is regenerated from the seed
+        contract.secret_m = vec![];
+        let client = VerificationClient::new(
+            VerificationClientConfig::from_contract(&contract, None).unwrap(),
+        );
+        let server = VerificationServer::new(VerificationServerConfig::from_file(file));
+        assert_eq!(client.config.secret_m_vector, config.secret_m_vector);
+        assert!(audit(&client, &server));
+    }
+
+    #[test]
+    fn test_audit_from_legacy_contract() {
+        let file = "abcdefghijklmnopqrstuvwxyz".as_bytes().to_vec();
+        let (client_config, server_config) = init(file);
+        let contract = contract(&client_config);
+        assert_eq!(contract.por_version, POR_VERSION_LEGACY);
+        let client = VerificationClient::new(
+            VerificationClientConfig::from_contract(&contract, None).unwrap(),
+        );
+        let server = VerificationServer::new(server_config);
+        assert!(audit(&client, &server));
+    }
+
+    #[test]
+    fn test_audit_fails_with_other_contract() {
+        let file = "abcdefghijklmnopqrstuvwxyz".repeat(100).as_bytes().to_vec();
+        let config = VerificationClientConfig::from_file(&file);
+        let other = VerificationClientConfig::from_file(&file);
+        let mut contract = contract(&config);
+        contract.por_seed = other.seed();
+        let client = VerificationClient::new(
+            VerificationClientConfig::from_contract(&contract, None).unwrap(),
+        );
+        let server = VerificationServer::new(VerificationServerConfig::from_file(file));
+        assert!(!audit(&client, &server));
+    }
+
+    #[test]
+    fn test_audit_from_sealed_contract() {
+        let file = "abcdefghijklmnopqrstuvwxyz".repeat(100).as_bytes().to_vec();
+        let auditor = StaticSecret::random_from_rng(OsRng);
+        let config = VerificationClientConfig::from_file(&file);
+        let mut contract = contract(&config);
+        contract.secret_n = vec![];
+        contract.secret_m = vec![];
+        contract.por_seed = 0;
+        contract.sealed_secrets = Some(config.seal(&PublicKey::from(&auditor)).unwrap());
+
+        assert!(VerificationClientConfig::from_contract(&contract, None).is_err());
+        let other = StaticSecret::random_from_rng(OsRng);
+        assert!(VerificationClientConfig::from_contract(&contract, Some(&other)).is_err());
+
+        let client = VerificationClient::new(
+            VerificationClientConfig::from_contract(&contract, Some(&auditor)).unwrap(),
+        );
+        let server = VerificationServer::new(VerificationServerConfig::from_file(file));
+        assert!(audit(&client, &server));
+    }
+
+    #[test]
+    fn test_challenges_differ() {
+        let file = "abcdefghijklmnopqrstuvwxyz".as_bytes().to_vec();
+        let client = VerificationClient::new(VerificationClientConfig::from_file(&file));
+        assert_ne!(
+            client.make_challenge_vector(),
+            client.make_challenge_vector()
+        );
+    }
+
     #[test]
     fn test_audit_abc_for_debugging() {
         let file = "abc".as_bytes().to_vec();
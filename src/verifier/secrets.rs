@@ -0,0 +1,218 @@
+use crate::settings::ISettings;
+use crate::util::hasher::hash;
+use crate::util::types::{Bytes, SealedSecrets};
+use crate::util::{Er, ErrorKind, Res};
+use base64::Engine as _;
+use chacha20poly1305::aead::{Aead, KeyInit};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use runtime_injector::{
+    InjectError, InjectResult, Injector, RequestInfo, ServiceFactory, ServiceInfo, Svc,
+};
+use serde::{Deserialize, Serialize};
+use sha3::{Digest, Sha3_256};
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+const KEY_LEN: usize = 32;
+const NONCE_LEN: usize = 12;
+
+/// The part of a contract only auditors may read.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PorSecrets {
+    pub secret_n: Bytes,
+    pub secret_m: Bytes,
+    pub seed: u64,
+}
+
+/// Keys of the auditor role. Whoever writes contracts needs the public key,
+/// only verifiers hold the secret key.
+pub struct AuditorKeys {
+    pub public: Option<PublicKey>,
+    pub secret: Option<StaticSecret>,
+}
+
+impl AuditorKeys {
+    pub fn public(&self) -> Res<&PublicKey> {
+        self.public
+            .as_ref()
+            .ok_or_else(|| ErrorKind::AuditorKeyMissing.into())
+    }
+}
+
+pub struct AuditorKeysProvider;
+impl ServiceFactory<()> for AuditorKeysProvider {
+    type Result = AuditorKeys;
+
+    fn invoke(
+        &mut self,
+        injector: &Injector,
+        _request_info: &RequestInfo,
+    ) -> InjectResult<Self::Result> {
+        let settings: Svc<dyn ISettings> = injector.get()?;
+        let activation_failed = |e: Er| InjectError::ActivationFailed {
+            service_info: ServiceInfo::of::<AuditorKeys>(),
+            inner: Box::<Er>::new(e),
+        };
+
+        let public = match settings.por().auditor_public_key {
+            Some(key) => Some(PublicKey::from(
+                decode_key(&key).map_err(activation_failed)?,
+            )),
+            None => None,
+        };
+        let secret = match settings.verifier().auditor_secret_key {
+            Some(key) => Some(StaticSecret::from(
+                decode_key(&key).map_err(activation_failed)?,
+            )),
+            None => None,
+        };
+        Ok(AuditorKeys { public, secret })
+    }
+}
+
+fn decode_key(key: &str) -> Res<[u8; KEY_LEN]> {
+    base64::engine::general_purpose::STANDARD_NO_PAD
+        .decode(key.trim_end_matches('='))
+        .map_err(|e| ErrorKind::InvalidAuditorKey(e.to_string()))?
+        .try_into()
+        .map_err(|_| ErrorKind::InvalidAuditorKey(format!("expected {} bytes", KEY_LEN)).into())
+}
+
+/// Both sides derive the key from the shared secret and the two public keys.
+fn derive_key(shared: &[u8], ephemeral: &PublicKey, auditor: &PublicKey) -> Key {
+    let mut hasher = Sha3_256::new();
+    hasher.update(shared);
+    hasher.update(ephemeral.as_bytes());
+    hasher.update(auditor.as_bytes());
+    Key::clone_from_slice(&hasher.finalize())
+}
+
+/// Encrypts the secrets to the auditor key with a fresh ephemeral key.
+/// The ciphertext is the ephemeral public key, the nonce and the encrypted
+/// secrets. The commitment is the hash of the plain secrets, so an auditor
+/// can tell that what it decrypted is what was agreed on.
+pub fn seal(secrets: &PorSecrets, auditor: &PublicKey) -> Res<SealedSecrets> {
+    let plain = bincode::serialize(secrets).map_err(|_| ErrorKind::SecretsSealingFailed)?;
+
+    let ephemeral_secret = EphemeralSecret::random_from_rng(OsRng);
+    let ephemeral = PublicKey::from(&ephemeral_secret);
+    let shared = ephemeral_secret.diffie_hellman(auditor);
+    let cipher = ChaCha20Poly1305::new(&derive_key(shared.as_bytes(), &ephemeral, auditor));
+
+    let mut nonce = [0_u8; NONCE_LEN];
+    OsRng.fill_bytes(&mut nonce);
+    let encrypted = cipher
+        .encrypt(Nonce::from_slice(&nonce), plain.as_slice())
+        .map_err(|_| ErrorKind::SecretsSealingFailed)?;
+
+    Ok(SealedSecrets {
+        ciphertext: ephemeral
+            .as_bytes()
+            .iter()
+            .chain(nonce.iter())
+            .chain(encrypted.iter())
+            .copied()
+            .collect(),
+        commitment: hash(&plain),
+    })
+}
+
+pub fn unseal(sealed: &SealedSecrets, auditor: &StaticSecret) -> Res<PorSecrets> {
+    let ephemeral: [u8; KEY_LEN] = sealed
+        .ciphertext
+        .get(..KEY_LEN)
+        .and_then(|x| x.try_into().ok())
+        .ok_or(ErrorKind::SecretsUnsealingFailed)?;
+    let ephemeral = PublicKey::from(ephemeral);
+    let nonce = sealed
+        .ciphertext
+        .get(KEY_LEN..KEY_LEN + NONCE_LEN)
+        .ok_or(ErrorKind::SecretsUnsealingFailed)?;
+    let encrypted = sealed
+        .ciphertext
+        .get(KEY_LEN + NONCE_LEN..)
+        .ok_or(ErrorKind::SecretsUnsealingFailed)?;
+
+    let shared = auditor.diffie_hellman(&ephemeral);
+    let cipher = ChaCha20Poly1305::new(&derive_key(
+        shared.as_bytes(),
+        &ephemeral,
+        &PublicKey::from(auditor),
+    ));
+    let plain = cipher
+        .decrypt(Nonce::from_slice(nonce), encrypted)
+        .map_err(|_| ErrorKind::SecretsUnsealingFailed)?;
+
+    if hash(&plain) != sealed.commitment {
+        return Err(ErrorKind::SecretsCommitmentMismatch.into());
+    }
+    bincode::deserialize(&plain).map_err(|_| ErrorKind::SecretsUnsealingFailed.into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn secrets() -> PorSecrets {
+        PorSecrets {
+            secret_n: vec![1, 2, 3, 4, 5, 6, 7, 8],
+            secret_m: vec![8, 7, 6, 5, 4, 3, 2, 1],
+            seed: 42,
+        }
+    }
+
+    #[test]
+    fn seal_and_unseal() {
+        let auditor = StaticSecret::random_from_rng(OsRng);
+        let sealed = seal(&secrets(), &PublicKey::from(&auditor)).unwrap();
+        assert_eq!(unseal(&sealed, &auditor).unwrap(), secrets());
+    }
+
+    #[test]
+    fn ciphertext_hides_secrets() {
+        let auditor = StaticSecret::random_from_rng(OsRng);
+        let sealed = seal(&secrets(), &PublicKey::from(&auditor)).unwrap();
+        let plain = bincode::serialize(&secrets()).unwrap();
+        assert!(!sealed
+            .ciphertext
+            .windows(plain.len())
+            .any(|x| x == plain.as_slice()));
+    }
+
+    #[test]
+    fn other_key_cannot_unseal() {
+        let auditor = StaticSecret::random_from_rng(OsRng);
+        let other = StaticSecret::random_from_rng(OsRng);
+        let sealed = seal(&secrets(), &PublicKey::from(&auditor)).unwrap();
+        assert!(unseal(&sealed, &other).is_err());
+    }
+
+    #[test]
+    fn tampered_ciphertext_is_rejected() {
+        let auditor = StaticSecret::random_from_rng(OsRng);
+        let mut sealed = seal(&secrets(), &PublicKey::from(&auditor)).unwrap();
+        if let Some(last) = sealed.ciphertext.last_mut() {
+            *last ^= 1;
+        }
+        assert!(unseal(&sealed, &auditor).is_err());
+    }
+
+    #[test]
+    fn wrong_commitment_is_rejected() {
+        let auditor = StaticSecret::random_from_rng(OsRng);
+        let mut sealed = seal(&secrets(), &PublicKey::from(&auditor)).unwrap();
+        sealed.commitment = hash(b"something else");
+        assert!(unseal(&sealed, &auditor).is_err());
+    }
+
+    #[test]
+    fn decode_key_with_or_without_padding() {
+        let key = "AvsCHXGsZddF1S72m3U/e1HRCaA+uLOSwu5dFyxtuiQ";
+        assert_eq!(
+            decode_key(key).unwrap(),
+            decode_key(&format!("{}=", key)).unwrap()
+        );
+        assert!(decode_key("AAAA").is_err());
+    }
+}
@@ -0,0 +1,86 @@
+use crate::util::consts;
+use libp2p::PeerId;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// The usual round trip to every holder which answered a challenge. Only
+/// answers which weren't suspicious count, so a holder can't slowly train
+/// the verifier to accept outsourced answers.
+#[derive(Default)]
+pub struct Latencies {
+    usual: HashMap<PeerId, Duration>,
+}
+
+impl Latencies {
+    pub fn usual(&self, peer_id: &PeerId) -> Option<Duration> {
+        self.usual.get(peer_id).copied()
+    }
+
+    /// Moves the usual round trip of the peer an eighth of the way to this one.
+    pub fn record(&mut self, peer_id: PeerId, round_trip: Duration) {
+        let usual = self.usual.entry(peer_id).or_insert(round_trip);
+        *usual = (*usual * 7 + round_trip) / 8;
+    }
+}
+
+/// The longest an honest holder should take to answer a challenge about
+/// `size` bytes. It reads its own copy, so it takes its usual round trip
+/// plus the time to go through the data. A holder which fetches the data
+/// from another replica first is slower by a whole transfer.
+pub fn response_bound(size: u64, usual: Option<Duration>) -> Duration {
+    let network = usual.unwrap_or(consts::DEFAULT_CHALLENGE_LATENCY.unsigned_abs())
+        * consts::LATENCY_TOLERANCE;
+    let reading = Duration::from_nanos(size.saturating_mul(consts::PROOF_NANOS_PER_BYTE));
+    (network + reading).max(consts::MIN_RESPONSE_BOUND.unsigned_abs())
+}
+
+/// How long a holder gets to answer a challenge about `size` bytes before
+/// it fails the audit.
+pub fn challenge_timeout(size: u64, usual: Option<Duration>) -> Duration {
+    (response_bound(size, usual) * consts::CHALLENGE_TIMEOUT_MARGIN)
+        .max(consts::CHALLENGE_TIMEOUT.unsigned_abs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bound_grows_with_size_and_latency() {
+        let small = response_bound(1_000, Some(Duration::from_millis(40)));
+        assert_eq!(
+            small,
+            Duration::from_millis(40) * consts::LATENCY_TOLERANCE
+                + Duration::from_nanos(1_000 * consts::PROOF_NANOS_PER_BYTE)
+        );
+        assert!(response_bound(1_000_000_000, Some(Duration::from_millis(40))) > small);
+        assert!(response_bound(1_000, Some(Duration::from_millis(400))) > small);
+        assert_eq!(
+            response_bound(0, Some(Duration::ZERO)),
+            consts::MIN_RESPONSE_BOUND.unsigned_abs()
+        );
+    }
+
+    #[test]
+    fn large_files_get_longer_to_answer() {
+        let floor = consts::CHALLENGE_TIMEOUT.unsigned_abs();
+        assert_eq!(challenge_timeout(1_000, None), floor);
+        let large = 10_000_000_000;
+        assert_eq!(
+            challenge_timeout(large, None),
+            response_bound(large, None) * consts::CHALLENGE_TIMEOUT_MARGIN
+        );
+        assert!(challenge_timeout(large, None) > floor);
+    }
+
+    #[test]
+    fn usual_latency_follows_recent_answers() {
+        let peer = PeerId::random();
+        let mut latencies = Latencies::default();
+        assert_eq!(latencies.usual(&peer), None);
+        latencies.record(peer, Duration::from_millis(80));
+        assert_eq!(latencies.usual(&peer), Some(Duration::from_millis(80)));
+        latencies.record(peer, Duration::from_millis(160));
+        assert_eq!(latencies.usual(&peer), Some(Duration::from_millis(90)));
+    }
+}